@@ -0,0 +1,382 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::sim::*;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+    Insane,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 4] = [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard, Difficulty::Insane];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Difficulty::Easy => "Easy",
+            Difficulty::Normal => "Normal",
+            Difficulty::Hard => "Hard",
+            Difficulty::Insane => "Insane",
+        }
+    }
+
+    /// Following difficulty, wrapping around, for menu selectors.
+    pub fn next(&self) -> Difficulty {
+        let index = Difficulty::ALL.iter().position(|difficulty| difficulty == self).unwrap_or(0);
+        Difficulty::ALL[(index + 1) % Difficulty::ALL.len()]
+    }
+
+    pub fn profile(&self) -> AiProfile {
+        match self {
+            Difficulty::Easy => AiProfile {
+                predictive: false,
+                reaction_delay: 0.3,
+                aim_error: 0.,
+                speed_factor: 0.6,
+                aim_corners: false,
+            },
+            Difficulty::Normal => AiProfile {
+                predictive: false,
+                reaction_delay: 0.15,
+                aim_error: 0.,
+                speed_factor: 1.,
+                aim_corners: false,
+            },
+            Difficulty::Hard => AiProfile {
+                predictive: true,
+                reaction_delay: 0.1,
+                aim_error: 10.,
+                speed_factor: 1.,
+                aim_corners: false,
+            },
+            Difficulty::Insane => AiProfile {
+                predictive: true,
+                reaction_delay: 0.,
+                aim_error: 2.,
+                speed_factor: 1.,
+                aim_corners: true,
+            },
+        }
+    }
+}
+
+/// Tuning of a difficulty level.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AiProfile {
+    /// Moves to where the ball will cross the paddle, wall bounces included, instead of chasing it.
+    pub predictive: bool,
+    /// Seconds before reacting to a ball coming back.
+    pub reaction_delay: f32,
+    /// Maximum distance, in pixels, between the aimed and the actual interception point (predictive only).
+    pub aim_error: f32,
+    /// Fraction of the paddle speed used.
+    pub speed_factor: f32,
+    /// Hits the ball with the paddle edges to send it towards the corner away from the opponent.
+    pub aim_corners: bool,
+}
+
+/// Hit factor used when aiming at corners, 0.5 being the very edge of the paddle.
+const AI_CORNER_HIT_FACTOR: f32 = 0.4;
+/// Seconds an AI waits before serving.
+const AI_SERVE_DELAY: f32 = 1.;
+
+/// AI driving a paddle, keeps its state from one tick to the next.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PaddleAi {
+    pub difficulty: Difficulty,
+    profile: AiProfile,
+    /// Whether the ball was coming towards the paddle on the last tick.
+    tracking: bool,
+    reaction_timer: f32,
+    /// Offset from the interception point the paddle center aims at, for this rally.
+    aim_offset: f32,
+    serve_timer: f32,
+}
+
+impl PaddleAi {
+    pub fn new(difficulty: Difficulty) -> Self {
+        PaddleAi {
+            difficulty,
+            profile: difficulty.profile(),
+            tracking: false,
+            reaction_timer: 0.,
+            aim_offset: 0.,
+            serve_timer: AI_SERVE_DELAY,
+        }
+    }
+
+    /// Inputs of a paddle of `side` for the next tick of `dt` seconds, serving by itself.
+    pub fn input(&mut self, sim: &PongSim, side: Side, line: PaddleLine, dt: f32, rng: &mut impl Rng) -> PaddleInput {
+        let mut serve = false;
+
+        // Only back paddles serve
+        if sim.service == Some(side) && line == PaddleLine::Back {
+            self.serve_timer -= dt;
+            serve = self.serve_timer <= 0.;
+        } else {
+            self.serve_timer = AI_SERVE_DELAY;
+        }
+
+        PaddleInput { axis: self.axis(sim, side, line, dt, rng), serve }
+    }
+
+    /// Axis to apply to a paddle of `side` for the next tick of `dt` seconds.
+    pub fn axis(&mut self, sim: &PongSim, side: Side, line: PaddleLine, dt: f32, rng: &mut impl Rng) -> f32 {
+        let Some(paddle) = sim.paddle_at(side, line) else {
+            return 0.;
+        };
+        let ball = threatening_ball(sim, paddle);
+        let coming = ball.velocity.dot(side.normal()) < 0.;
+
+        if !coming {
+            self.tracking = false;
+
+            // Better players get back to the center while waiting
+            if self.profile.predictive && sim.service.is_none() {
+                return self.move_towards(paddle, 0., dt);
+            }
+            return 0.;
+        }
+
+        if !self.tracking {
+            self.tracking = true;
+            self.reaction_timer = self.profile.reaction_delay;
+            self.aim_offset = self.pick_aim_offset(sim, paddle, rng);
+        }
+
+        if self.reaction_timer > 0. {
+            self.reaction_timer -= dt;
+            return 0.;
+        }
+
+        if !self.profile.predictive {
+            return chase_ball_axis(sim, paddle, ball, rng) * self.profile.speed_factor;
+        }
+
+        let Some(intercept) = predict_intercept(sim, paddle, ball) else {
+            return 0.;
+        };
+
+        self.move_towards(paddle, intercept + self.aim_offset, dt)
+    }
+
+    fn pick_aim_offset(&self, sim: &PongSim, paddle: &SimPaddle, rng: &mut impl Rng) -> f32 {
+        let error = if self.profile.aim_error > 0. {
+            rng.gen_range(-self.profile.aim_error..=self.profile.aim_error)
+        } else {
+            0.
+        };
+
+        if !self.profile.aim_corners {
+            return error;
+        }
+
+        // Send the ball away from the opponent: a ball touching above the paddle center goes up
+        let opponent = sim.paddle(paddle.side.opponent()).map_or(0., |opponent| opponent.position_along());
+        let hit_factor = if opponent > 0. { -AI_CORNER_HIT_FACTOR } else { AI_CORNER_HIT_FACTOR };
+
+        // Paddle center = ball y - hit factor * paddle height
+        -hit_factor * paddle.length() + error
+    }
+
+    fn move_towards(&self, paddle: &SimPaddle, target: f32, dt: f32) -> f32 {
+        paddle.axis_towards(target, dt) * self.profile.speed_factor
+    }
+}
+
+/// Ball `paddle` has to deal with first: the soonest to reach it among those coming its way from in front of it,
+/// else the served one.
+pub fn threatening_ball<'a>(sim: &'a PongSim, paddle: &SimPaddle) -> &'a SimBall {
+    let normal = paddle.side.normal();
+    let time_to_reach = |ball: &SimBall| (ball.body.position - paddle.body.position).dot(normal) / -ball.velocity.dot(normal);
+
+    sim.balls
+        .iter()
+        .filter(|ball| ball.velocity.dot(normal) < 0.)
+        // Balls already past the paddle are lost, and would come first with a negative time
+        .filter(|ball| (ball.body.position - paddle.body.position).dot(normal) >= 0.)
+        .min_by(|a, b| time_to_reach(a).total_cmp(&time_to_reach(b)))
+        .unwrap_or(sim.ball())
+}
+
+/// Where along its side the center of `ball` reaches the front of `paddle`.
+pub fn predict_intercept(sim: &PongSim, paddle: &SimPaddle, ball: &SimBall) -> Option<f32> {
+    let side = paddle.side;
+
+    if sim.mode != SimMode::FourPlayers {
+        let contact_x = paddle.body.position.x + side.direction() * (paddle.body.size.x + ball.body.size.x) / 2.;
+        return predict_intercept_y(sim, ball, contact_x);
+    }
+
+    // Four players has no side walls to bounce on, until sides get eliminated
+    let normal = side.normal();
+    let speed = ball.velocity.dot(normal);
+    if speed >= 0. {
+        return None;
+    }
+
+    let contact = paddle.body.position + normal * (paddle.body.size + ball.body.size) / 2.;
+    let time = (contact - ball.body.position).dot(normal) / speed;
+    if time < 0. {
+        return None;
+    }
+
+    let position = ball.body.position + ball.velocity * time;
+    Some(if side.is_vertical() { position.y } else { position.x })
+}
+
+/// Y of the center of `ball` when it reaches `x`, following its bounces on the top and bottom walls.
+pub fn predict_intercept_y(sim: &PongSim, ball: &SimBall, x: f32) -> Option<f32> {
+    if ball.velocity.x == 0. {
+        return None;
+    }
+
+    let time = (x - ball.body.position.x) / ball.velocity.x;
+    if time < 0. {
+        return None;
+    }
+
+    let max_y = sim.settings.court_size.y / 2. - sim.settings.unit_size - ball.body.size.y / 2.;
+    let span = max_y * 2.;
+    if span <= 0. {
+        return Some(0.);
+    }
+
+    // Unfold the bounces: the trajectory is a straight line mirrored every `span`
+    let unfolded_y = ball.body.position.y + ball.velocity.y * time + max_y;
+    let mut y = unfolded_y.rem_euclid(span * 2.);
+    if y > span {
+        y = span * 2. - y;
+    }
+
+    Some(y - max_y)
+}
+
+/// Follows `ball` along the side while it comes towards the paddle, with a random dead zone.
+pub fn chase_ball_axis(sim: &PongSim, paddle: &SimPaddle, ball: &SimBall, rng: &mut impl Rng) -> f32 {
+    let side = paddle.side;
+    let normal = side.normal();
+
+    // Ball going away
+    if ball.velocity.dot(normal) >= 0. {
+        return 0.;
+    }
+
+    // Ball already behind the paddle
+    let half_depth = if side.is_vertical() { sim.settings.court_size.x / 2. } else { sim.settings.court_size.y / 2. };
+    if ball.body.position.dot(normal) < -half_depth {
+        return 0.;
+    }
+
+    let ball_along = if side.is_vertical() { ball.body.position.y } else { ball.body.position.x };
+    let paddle_along = paddle.position_along();
+    let dist: f32 = (paddle_along - ball_along).abs();
+    let rnd = rng.gen_range(1..=((paddle.length() / 2.) as u32).max(1));
+
+    if dist < rnd as f32 {
+        return 0.;
+    }
+
+    // Top and bottom paddles go left for up
+    let axis = if ball_along > paddle_along { 1. } else { -1. };
+    if side.is_vertical() { axis } else { -axis }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec2;
+
+    use crate::arena::Arena;
+    use super::*;
+
+    fn settings() -> SimSettings {
+        SimSettings {
+            court_size: Vec2::new(768., 576.),
+            unit_size: 16.,
+            paddle_speed: 400.,
+            ball_speed_min: 500.,
+            ball_speed_max: 750.,
+            ball_speed_incr: 5.,
+            ball_oob_x: 200.,
+            score_to_win: 9,
+            rules: MatchRules::default(),
+            first_service: None,
+            forward_offset: 192.,
+            arena: Arena::default(),
+        }
+    }
+
+    fn rally(position: Vec2, velocity: Vec2) -> PongSim {
+        let mut sim = PongSim::new(settings(), SimMode::Versus, 0);
+        sim.service = None;
+        sim.balls[0].body.snap(position);
+        sim.balls[0].velocity = velocity;
+        sim
+    }
+
+    #[test]
+    fn intercept_after_two_bounces() {
+        // The ball center moves between -264 and 264: up 264, down 528, then up 198 more
+        let sim = rally(Vec2::ZERO, Vec2::new(100., 300.));
+        assert_eq!(predict_intercept_y(&sim, sim.ball(), 330.), Some(-66.));
+
+        let sim = rally(Vec2::ZERO, Vec2::new(100., -300.));
+        assert_eq!(predict_intercept_y(&sim, sim.ball(), 330.), Some(66.));
+    }
+
+    #[test]
+    fn intercept_matches_the_simulation() {
+        let mut sim = rally(Vec2::new(-300., 40.), Vec2::new(200., 700.));
+        let predicted = predict_intercept_y(&sim, sim.ball(), 330.).unwrap();
+
+        while sim.ball().body.position.x < 330. - 0.01 {
+            sim.step(&SimInputs::default(), 1. / 120.);
+        }
+        assert!((sim.ball().body.position.y - predicted).abs() < 0.5, "{} vs {}", sim.ball().body.position.y, predicted);
+    }
+
+    #[test]
+    fn no_intercept_behind_the_ball() {
+        let sim = rally(Vec2::ZERO, Vec2::new(100., 300.));
+        assert_eq!(predict_intercept_y(&sim, sim.ball(), -330.), None);
+
+        let sim = rally(Vec2::ZERO, Vec2::new(0., 300.));
+        assert_eq!(predict_intercept_y(&sim, sim.ball(), 330.), None);
+    }
+
+    fn ball_at(position: Vec2, velocity: Vec2) -> SimBall {
+        SimBall { body: Body::new(position, Vec2::splat(16.)), speed: velocity.length(), velocity, hold: None, last_hit: None }
+    }
+
+    #[test]
+    fn soonest_ball_coming_is_the_threat() {
+        let mut sim = rally(Vec2::new(-300., 0.), Vec2::new(100., 0.));
+        // Closer but slower, then one going away
+        sim.balls.push(ball_at(Vec2::new(200., 50.), Vec2::new(50., 0.)));
+        sim.balls.push(ball_at(Vec2::new(300., -50.), Vec2::new(-500., 0.)));
+        let paddle = sim.paddle(Side::Right).unwrap().clone();
+
+        assert_eq!(threatening_ball(&sim, &paddle), &sim.balls[1]);
+
+        sim.balls[1].velocity.x = 20.;
+        assert_eq!(threatening_ball(&sim, &paddle), &sim.balls[0]);
+
+        // Nothing coming
+        sim.balls[0].velocity.x = -100.;
+        sim.balls[1].velocity.x = -20.;
+        assert_eq!(threatening_ball(&sim, &paddle), sim.ball());
+    }
+
+    #[test]
+    fn ball_past_the_paddle_is_ignored() {
+        let mut sim = rally(Vec2::new(-300., 0.), Vec2::new(100., 0.));
+        let paddle = sim.paddle(Side::Right).unwrap().clone();
+        sim.balls.push(ball_at(paddle.body.position + Vec2::new(12., 40.), Vec2::new(500., 0.)));
+
+        assert_eq!(threatening_ball(&sim, &paddle), &sim.balls[0]);
+    }
+}
@@ -0,0 +1,165 @@
+use glam::Vec2;
+use serde::{Deserialize, Serialize};
+
+/// Block of an arena, positions are in pixels from the middle of the court.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ArenaBlock {
+    pub center: Vec2,
+    pub size: Vec2,
+}
+
+/// Block going through the points of its path in turn, then back to the first one.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ArenaObstacle {
+    pub size: Vec2,
+    /// Starts on the first point.
+    pub path: Vec<Vec2>,
+    /// In pixels per second.
+    pub speed: f32,
+}
+
+/// Block sending the ball back faster.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ArenaBumper {
+    pub center: Vec2,
+    pub size: Vec2,
+    /// Ball speed factor of a bounce, up to the maximum speed.
+    pub boost: f32,
+}
+
+/// Court layout on top of the top and bottom walls, read from an arena file.
+/// The default one is the classic court.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Arena {
+    /// Shown on the match rules screen, and saved with the settings to pick the arena again.
+    pub name: String,
+    pub walls: Vec<ArenaBlock>,
+    pub obstacles: Vec<ArenaObstacle>,
+    pub bumpers: Vec<ArenaBumper>,
+    /// Height of the opening of both goals, around the middle of the edges. The rest of the edges is walled up.
+    /// The whole edge when `None`.
+    pub goal_height: Option<f32>,
+}
+
+impl Arena {
+    /// Values that would break the game, each naming the offending field.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        if self.name.is_empty() {
+            problems.push("name: must not be empty".to_string());
+        }
+
+        let sizes = self.walls.iter().map(|wall| ("walls", wall.size))
+            .chain(self.obstacles.iter().map(|obstacle| ("obstacles", obstacle.size)))
+            .chain(self.bumpers.iter().map(|bumper| ("bumpers", bumper.size)));
+        for (field, size) in sizes {
+            if size.x <= 0. || size.y <= 0. {
+                problems.push(format!("{}: sizes must be positive, got {}", field, size));
+            }
+        }
+
+        for obstacle in self.obstacles.iter() {
+            if obstacle.path.is_empty() {
+                problems.push("obstacles: a path needs at least a point".to_string());
+            }
+            if obstacle.speed < 0. {
+                problems.push(format!("obstacles: speed must not be negative, got {}", obstacle.speed));
+            }
+        }
+
+        for bumper in self.bumpers.iter() {
+            if bumper.boost <= 0. {
+                problems.push(format!("bumpers: boost must be positive, got {}", bumper.boost));
+            }
+        }
+
+        if let Some(goal_height) = self.goal_height {
+            if goal_height <= 0. {
+                problems.push(format!("goal_height: must be positive, got {}", goal_height));
+            }
+        }
+
+        problems
+    }
+
+    /// Blocks and obstacle paths reaching outside of a court of `court_size`, where the ball could get stuck.
+    pub fn validate_court(&self, court_size: Vec2) -> Vec<String> {
+        let half_court = court_size / 2.;
+        let outside = |center: Vec2, size: Vec2| (center.abs() + size / 2.).cmpgt(half_court).any();
+
+        let blocks = self.walls.iter().map(|wall| ("walls", wall.center, wall.size))
+            .chain(self.obstacles.iter().flat_map(|obstacle| obstacle.path.iter().map(|&point| ("obstacles", point, obstacle.size))))
+            .chain(self.bumpers.iter().map(|bumper| ("bumpers", bumper.center, bumper.size)));
+
+        blocks
+            .filter(|&(_, center, size)| outside(center, size))
+            .map(|(field, center, size)| format!("{}: block of {} at {} is outside of the {} court", field, size, center, court_size))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COURT_SIZE: Vec2 = Vec2::new(768., 576.);
+
+    fn load(text: &str) -> Arena {
+        let options = ron::Options::default().with_default_extension(ron::extensions::Extensions::IMPLICIT_SOME);
+        options.from_str(text).unwrap()
+    }
+
+    #[test]
+    fn shipped_arenas_are_valid() {
+        let files = [
+            include_str!("../assets/arenas/gates.arena.ron"),
+            include_str!("../assets/arenas/pillars.arena.ron"),
+            include_str!("../assets/arenas/pinball.arena.ron"),
+        ];
+        for file in files {
+            let arena = load(file);
+            assert_eq!(arena.validate(), Vec::<String>::new(), "{}", arena.name);
+            assert_eq!(arena.validate_court(COURT_SIZE), Vec::<String>::new(), "{}", arena.name);
+        }
+    }
+
+    #[test]
+    fn malformed_arena_is_refused() {
+        let arena = load("(
+            walls: [(center: (0., 0.), size: (0., 64.))],
+            obstacles: [(size: (16., 16.), path: [], speed: -1.)],
+            bumpers: [(center: (0., 0.), size: (32., 32.), boost: 0.)],
+            goal_height: 0.,
+        )");
+
+        let problems = arena.validate();
+
+        assert_eq!(problems.len(), 6);
+        for field in ["name", "walls", "obstacles: a path", "obstacles: speed", "bumpers", "goal_height"] {
+            assert!(problems.iter().any(|problem| problem.starts_with(field)), "{} in {:?}", field, problems);
+        }
+    }
+
+    #[test]
+    fn arena_outside_of_the_court_is_refused() {
+        let arena = load("(
+            name: \"Outside\",
+            walls: [(center: (-96., 0.), size: (16., 64.)), (center: (380., 0.), size: (16., 64.))],
+            obstacles: [(size: (48., 16.), path: [(0., 200.), (0., 290.)], speed: 100.)],
+            bumpers: [(center: (0., -300.), size: (32., 32.), boost: 1.1)],
+        )");
+
+        assert!(arena.validate().is_empty());
+        let problems = arena.validate_court(COURT_SIZE);
+        assert_eq!(problems.len(), 3, "{:?}", problems);
+        assert!(problems[0].starts_with("walls"));
+        assert!(problems[1].starts_with("obstacles"));
+        assert!(problems[2].starts_with("bumpers"));
+        assert!(arena.validate_court(COURT_SIZE * 2.).is_empty());
+    }
+}
@@ -0,0 +1,150 @@
+use glam::Vec2;
+
+/// Axis-aligned box described by its center and half extents.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub center: Vec2,
+    pub half_size: Vec2,
+}
+
+impl Aabb {
+    pub fn new(center: Vec2, size: Vec2) -> Self {
+        Aabb { center, half_size: size / 2. }
+    }
+}
+
+/// Earliest contact found by [`sweep_aabb`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SweepHit {
+    /// Fraction of the displacement travelled before the contact, from 0 to 1.
+    pub time: f32,
+    /// Contact normal pointing from the target towards the moving box.
+    /// Both components are set on an exact corner hit.
+    pub normal: Vec2,
+}
+
+/// Two entry times closer than this are considered simultaneous (corner hit).
+const CORNER_EPSILON: f32 = 1e-5;
+
+/// Sweeps `moving` along `displacement` and returns the first contact with `target`, if any.
+///
+/// Boxes that merely touch, or that separate along the displacement, never collide.
+/// Boxes already overlapping are left to [`overlap_aabb`].
+pub fn sweep_aabb(moving: &Aabb, displacement: Vec2, target: &Aabb) -> Option<SweepHit> {
+    if displacement == Vec2::ZERO {
+        return None;
+    }
+
+    // Minkowski sum: sweeping a box against a box is a ray against the expanded box
+    let half_size = target.half_size + moving.half_size;
+    let min = target.center - half_size;
+    let max = target.center + half_size;
+    let origin = moving.center;
+
+    let (x_entry, x_exit) = slab(origin.x, displacement.x, min.x, max.x)?;
+    let (y_entry, y_exit) = slab(origin.y, displacement.y, min.y, max.y)?;
+
+    let entry = x_entry.max(y_entry);
+    let exit = x_exit.min(y_exit);
+
+    if entry >= exit || entry < 0. || entry > 1. {
+        return None;
+    }
+
+    let mut normal = Vec2::ZERO;
+    if x_entry >= y_entry - CORNER_EPSILON {
+        normal.x = -displacement.x.signum();
+    }
+    if y_entry >= x_entry - CORNER_EPSILON {
+        normal.y = -displacement.y.signum();
+    }
+
+    Some(SweepHit { time: entry, normal })
+}
+
+/// Returns the smallest translation that pushes `moving` out of `target`, if they overlap.
+pub fn overlap_aabb(moving: &Aabb, target: &Aabb) -> Option<Vec2> {
+    let delta = moving.center - target.center;
+    let overlap = moving.half_size + target.half_size - delta.abs();
+
+    if overlap.x <= 0. || overlap.y <= 0. {
+        return None;
+    }
+
+    let sign = |value: f32| if value < 0. { -1. } else { 1. };
+
+    if overlap.x < overlap.y {
+        Some(Vec2::new(overlap.x * sign(delta.x), 0.))
+    } else {
+        Some(Vec2::new(0., overlap.y * sign(delta.y)))
+    }
+}
+
+/// Entry and exit times of a ray along one axis, or `None` if it can never be inside the slab.
+fn slab(origin: f32, displacement: f32, min: f32, max: f32) -> Option<(f32, f32)> {
+    if displacement == 0. {
+        if origin <= min || origin >= max {
+            return None;
+        }
+        return Some((-f32::INFINITY, f32::INFINITY));
+    }
+
+    let t1 = (min - origin) / displacement;
+    let t2 = (max - origin) / displacement;
+
+    Some((t1.min(t2), t1.max(t2)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_box(x: f32, y: f32) -> Aabb {
+        Aabb::new(Vec2::new(x, y), Vec2::splat(16.))
+    }
+
+    #[test]
+    fn head_on_hit() {
+        let hit = sweep_aabb(&unit_box(0., 0.), Vec2::new(100., 0.), &unit_box(66., 0.)).unwrap();
+
+        assert_eq!(hit.time, 0.5);
+        assert_eq!(hit.normal, Vec2::new(-1., 0.));
+    }
+
+    #[test]
+    fn hit_across_a_long_displacement() {
+        // A whole court in a single step, far more than the size of both boxes
+        let hit = sweep_aabb(&unit_box(-300., 0.), Vec2::new(1600., 0.), &unit_box(340., 0.)).unwrap();
+
+        assert_eq!(hit.time, 0.39);
+        assert_eq!(hit.normal, Vec2::new(-1., 0.));
+    }
+
+    #[test]
+    fn corner_hit() {
+        let hit = sweep_aabb(&unit_box(0., 0.), Vec2::new(100., 100.), &unit_box(66., 66.)).unwrap();
+
+        assert_eq!(hit.time, 0.5);
+        assert_eq!(hit.normal, Vec2::new(-1., -1.));
+    }
+
+    #[test]
+    fn starting_overlap_is_pushed_out() {
+        let moving = unit_box(0., 0.);
+        let target = unit_box(12., 2.);
+
+        assert_eq!(sweep_aabb(&moving, Vec2::new(100., 0.), &target), None);
+        assert_eq!(overlap_aabb(&moving, &target), Some(Vec2::new(-4., 0.)));
+    }
+
+    #[test]
+    fn no_hit_moving_away() {
+        let moving = unit_box(0., 0.);
+
+        assert_eq!(sweep_aabb(&moving, Vec2::new(-100., 0.), &unit_box(20., 0.)), None);
+        // Touching boxes separating along the displacement
+        assert_eq!(sweep_aabb(&moving, Vec2::new(-100., 0.), &unit_box(16., 0.)), None);
+        // Passing next to the target
+        assert_eq!(sweep_aabb(&moving, Vec2::new(100., 0.), &unit_box(50., 40.)), None);
+    }
+}
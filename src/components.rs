@@ -1,56 +1,98 @@
 use bevy::prelude::*;
-use bevy_kira_audio::{AudioSource};
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
 
+pub use crate::sim::Side;
+use crate::ai::*;
+use crate::replay::*;
+use crate::sim::*;
+
+/// Simulation driven by the current game mode.
+#[derive(Resource)]
+pub struct Sim(pub PongSim);
+
+/// Random source of the current match for decisions taken outside the simulation (AI...).
+#[derive(Resource)]
+pub struct GameRng {
+    pub rng: ChaCha8Rng,
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        // Not the simulation stream, so AI draws never shift serves
+        GameRng { rng: ChaCha8Rng::seed_from_u64(seed.wrapping_add(1)) }
+    }
+}
+
+/// AI level picked on the menu.
+#[derive(Default, Resource)]
+pub struct AiDifficulty(pub Difficulty);
+
+/// AI levels of the left and right paddles in Watch mode.
 #[derive(Default, Resource)]
-pub struct GameData {
-    pub left_score: u32,
-    pub right_score: u32,
-    pub starting_side: Side,
-    pub game_over: Option<Side>,
+pub struct WatchDifficulties {
+    pub left: Difficulty,
+    pub right: Difficulty,
 }
 
-#[derive(Component)]
-pub struct GameModeEntity {}
+/// Inputs applied on the next simulation tick.
+#[derive(Default, Resource)]
+pub struct PendingInputs(pub SimInputs);
 
-#[derive(Clone, Copy, PartialEq)]
-pub enum Side {
-    Left,
-    Right,
+/// Inputs of the current match, saved as a replay when leaving it.
+#[derive(Resource)]
+pub struct MatchRecorder(pub Replay);
+
+/// Side serving first in the next match, set by a rematch.
+#[derive(Resource)]
+pub struct RematchService(pub Side);
+
+/// Match being played back: the mode plugins step it from the recording instead of the players.
+#[derive(Resource)]
+pub struct ReplayPlayback {
+    pub replay: Replay,
+    pub speed_index: usize,
 }
 
-impl Default for Side {
-    fn default() -> Self {
-        Side::Left
-    }
+#[derive(Component, Clone)]
+pub struct GameModeEntity {}
+
+/// Sprite rendering a simulated paddle.
+#[derive(Component)]
+pub struct PaddleView {
+    pub side: Side,
+    pub line: PaddleLine,
 }
 
+/// Sprite rendering a simulated ball, by its index in `PongSim::balls`.
 #[derive(Component)]
-pub struct LeftPaddle {
-    pub speed: f32,
+pub struct BallView {
+    pub index: usize,
 }
 
+/// Sprite rendering a moving obstacle of the arena, by its index in `PongSim::obstacles`.
 #[derive(Component)]
-pub struct RightPaddle {
-    /// For AI control.
-    pub velocity: Vec3,
-    /// For Human control.
-    pub speed: f32,
+pub struct ObstacleView {
+    pub index: usize,
 }
 
+/// Sprites of the power-up waiting on the court, a box and its label.
 #[derive(Component)]
-pub struct Ball {
-    pub speed: f32,
-    pub velocity: Vec3,
+pub struct PowerUpView {
+    pub kind: PowerUpKind,
 }
 
+/// Sprite of the wall a shield puts behind the paddles of a side.
 #[derive(Component)]
-pub enum Collider {
-    Paddle,
-    Wall,
+pub struct ShieldView {
+    pub side: Side,
 }
 
+/// Power-ups running for a side, under its score.
 #[derive(Component)]
-pub struct Service {}
+pub struct PowerUpText {
+    pub side: Side,
+}
 
 #[derive(Component)]
 pub struct LeftScore {}
@@ -58,10 +100,15 @@ pub struct LeftScore {}
 #[derive(Component)]
 pub struct RightScore {}
 
+/// Sets won by a side, shown under its score in matches of several sets.
+#[derive(Component)]
+pub struct SetCounter {
+    pub side: Side,
+}
+
 #[derive(Component)]
 pub struct Instruction {}
 
+/// Result texts, removed when seeking a replay back before the end of the match.
 #[derive(Component)]
-pub struct SoundEmitter {
-    pub source: Handle<AudioSource>,
-}
+pub struct GameOverText {}
@@ -1,7 +1,29 @@
-use bevy::prelude::{Handle, Resource};
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::RangeInclusive;
+use bevy::asset::{AssetLoader, AsyncReadExt, LoadContext, io::Reader};
+use bevy::prelude::{Asset, AssetServer, Assets, Handle, Resource, TypePath, Vec2};
 use bevy::text::Font;
+use bevy::utils::BoxedFuture;
 use crate::{Color};
 use bevy_kira_audio::{AudioSource};
+use serde::Deserialize;
+
+use crate::arena::Arena;
+use crate::sim::{MatchRules, SimSettings};
+use crate::state::GameState;
+
+/// Tuning file, under `assets`.
+pub const CONFIG_PATH: &str = "pong.config.ron";
+
+/// Logical size of the window, which is the court.
+pub const COURT_SIZE: Vec2 = Vec2::new(768., 576.);
+
+/// Simulation rates accepted from the tuning file, and from the host of an online match.
+pub const FIXED_HZ_RANGE: RangeInclusive<f64> = 10.0..=1000.0;
+
+/// Smallest unit, below it paddles are too short for the AI to aim.
+const MIN_UNIT_SIZE: f32 = 4.;
 
 #[derive(Default, Resource)]
 pub struct Config {
@@ -11,6 +33,14 @@ pub struct Config {
     pub game_ball_speed_incr: f32,
     pub game_ball_oob_x: f32,
     pub game_1v1_score_to_win: u32,
+    /// Distance from the back to the forward paddle of a doubles team.
+    pub game_forward_offset: f32,
+    /// Simulation rate of the `FixedUpdate` schedule, in ticks per second.
+    pub game_fixed_hz: f64,
+    /// Seed of every match, a random one is drawn per match when unset.
+    pub game_seed: Option<u64>,
+    /// Game tuning overridden by some modes, by `GameState::config_key`.
+    pub game_mode_overrides: HashMap<String, GameOverrides>,
 
     pub sprite_unit_size: f32,
 
@@ -26,4 +56,456 @@ pub struct Config {
     pub audio_paddle_left: Handle<AudioSource>,
     pub audio_paddle_right: Handle<AudioSource>,
     pub audio_wall: Handle<AudioSource>,
+
+    /// Arenas offered before a match, in the order of the config file.
+    pub arenas: Vec<Handle<ArenaAsset>>,
+}
+
+impl Config {
+    /// Simulation settings of the given mode, its overrides applied.
+    pub fn sim_settings(&self, state: &GameState, court_size: Vec2) -> SimSettings {
+        let overrides = state
+            .config_key()
+            .and_then(|key| self.game_mode_overrides.get(key))
+            .cloned()
+            .unwrap_or_default();
+
+        SimSettings {
+            court_size,
+            unit_size: self.sprite_unit_size,
+            paddle_speed: overrides.paddle_speed.unwrap_or(self.game_paddle_speed),
+            ball_speed_min: overrides.ball_speed_min.unwrap_or(self.game_ball_speed_min),
+            ball_speed_max: overrides.ball_speed_max.unwrap_or(self.game_ball_speed_max),
+            ball_speed_incr: overrides.ball_speed_incr.unwrap_or(self.game_ball_speed_incr),
+            ball_oob_x: overrides.ball_oob_x.unwrap_or(self.game_ball_oob_x),
+            score_to_win: overrides.score_to_win.unwrap_or(self.game_1v1_score_to_win),
+            rules: MatchRules::default(),
+            first_service: None,
+            forward_offset: overrides.forward_offset.unwrap_or(self.game_forward_offset),
+            arena: Arena::default(),
+        }
+    }
+
+    /// Arenas loaded so far, the broken ones are left out.
+    pub fn loaded_arenas<'a>(&'a self, arena_assets: &'a Assets<ArenaAsset>) -> impl Iterator<Item = &'a Arena> {
+        self.arenas.iter().filter_map(|handle| arena_assets.get(handle)).map(|asset| &asset.0)
+    }
+
+    /// Copies the tuning of `file` over, loading the font and sounds it points at.
+    pub fn apply(&mut self, file: &ConfigFile, asset_server: &AssetServer) {
+        self.game_paddle_speed = file.game.paddle_speed;
+        self.game_ball_speed_min = file.game.ball_speed_min;
+        self.game_ball_speed_max = file.game.ball_speed_max;
+        self.game_ball_speed_incr = file.game.ball_speed_incr;
+        self.game_ball_oob_x = file.game.ball_oob_x;
+        self.game_1v1_score_to_win = file.game.score_to_win;
+        self.game_forward_offset = file.game.forward_offset;
+        self.game_fixed_hz = file.game.fixed_hz;
+        self.game_mode_overrides = file.modes.clone();
+
+        self.sprite_unit_size = file.sprite_unit_size;
+
+        self.color_transparent = file.colors.transparent.into();
+        self.color_white = file.colors.white.into();
+        self.color_grey = file.colors.grey.into();
+        self.color_yellow = file.colors.yellow.into();
+        self.color_green = file.colors.green.into();
+        self.color_red = file.colors.red.into();
+
+        self.font = asset_server.load(file.font.clone());
+
+        self.audio_paddle_left = asset_server.load(file.sounds.paddle_left.clone());
+        self.audio_paddle_right = asset_server.load(file.sounds.paddle_right.clone());
+        self.audio_wall = asset_server.load(file.sounds.wall.clone());
+
+        self.arenas = file.arenas.iter().map(|path| asset_server.load(path.clone())).collect();
+    }
+}
+
+/// Tuning file as loaded: a broken file still loads, so a reload can report what is wrong with it.
+#[derive(Asset, TypePath, Debug)]
+pub struct ConfigAsset(pub Result<ConfigFile, ConfigError>);
+
+/// Contents of the tuning file, every field can be left out to keep its default.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigFile {
+    pub game: GameFile,
+    /// Per mode overrides of `game`, keyed like `GameState::config_key`.
+    pub modes: HashMap<String, GameOverrides>,
+    pub sprite_unit_size: f32,
+    pub colors: ColorsFile,
+    pub font: String,
+    pub sounds: SoundsFile,
+    /// Arena files, under `assets`.
+    pub arenas: Vec<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GameFile {
+    pub paddle_speed: f32,
+    pub ball_speed_min: f32,
+    pub ball_speed_max: f32,
+    pub ball_speed_incr: f32,
+    pub ball_oob_x: f32,
+    pub score_to_win: u32,
+    pub forward_offset: f32,
+    pub fixed_hz: f64,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GameOverrides {
+    pub paddle_speed: Option<f32>,
+    pub ball_speed_min: Option<f32>,
+    pub ball_speed_max: Option<f32>,
+    pub ball_speed_incr: Option<f32>,
+    pub ball_oob_x: Option<f32>,
+    pub score_to_win: Option<u32>,
+    pub forward_offset: Option<f32>,
+}
+
+/// RGBA, 0 to 255.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct ColorFile(pub u8, pub u8, pub u8, pub u8);
+
+impl From<ColorFile> for Color {
+    fn from(color: ColorFile) -> Self {
+        Color::rgba_u8(color.0, color.1, color.2, color.3)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ColorsFile {
+    pub transparent: ColorFile,
+    pub white: ColorFile,
+    pub grey: ColorFile,
+    pub yellow: ColorFile,
+    pub green: ColorFile,
+    pub red: ColorFile,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SoundsFile {
+    pub paddle_left: String,
+    pub paddle_right: String,
+    pub wall: String,
+}
+
+impl Default for ConfigFile {
+    fn default() -> Self {
+        ConfigFile {
+            game: GameFile::default(),
+            modes: HashMap::new(),
+            sprite_unit_size: 16.,
+            colors: ColorsFile::default(),
+            font: "fonts/Volter__28Goldfish_29.ttf".to_string(),
+            sounds: SoundsFile::default(),
+            arenas: vec![
+                "arenas/pillars.arena.ron".to_string(),
+                "arenas/gates.arena.ron".to_string(),
+                "arenas/pinball.arena.ron".to_string(),
+            ],
+        }
+    }
+}
+
+impl Default for GameFile {
+    fn default() -> Self {
+        GameFile {
+            paddle_speed: 400.,
+            ball_speed_min: 500.,
+            ball_speed_max: 750.,
+            ball_speed_incr: 5.,
+            ball_oob_x: 200.,
+            score_to_win: 9,
+            forward_offset: 192.,
+            fixed_hz: 120.,
+        }
+    }
+}
+
+impl Default for ColorsFile {
+    fn default() -> Self {
+        ColorsFile {
+            transparent: ColorFile(0, 0, 0, 0),
+            white: ColorFile(255, 255, 255, 255),
+            grey: ColorFile(100, 100, 100, 255),
+            yellow: ColorFile(221, 173, 29, 255),
+            green: ColorFile(69, 183, 130, 255),
+            red: ColorFile(196, 89, 73, 255),
+        }
+    }
+}
+
+impl Default for SoundsFile {
+    fn default() -> Self {
+        SoundsFile {
+            paddle_left: "sounds/left.wav".to_string(),
+            paddle_right: "sounds/right.wav".to_string(),
+            wall: "sounds/wall.wav".to_string(),
+        }
+    }
+}
+
+impl ConfigFile {
+    /// Parses a tuning file, optional fields may be written without `Some(...)`.
+    pub fn from_ron(text: &str) -> Result<Self, ConfigError> {
+        let options = ron::Options::default().with_default_extension(ron::extensions::Extensions::IMPLICIT_SOME);
+        let file: ConfigFile = options.from_str(text).map_err(ConfigError::Parse)?;
+
+        let problems = file.validate();
+        if !problems.is_empty() {
+            return Err(ConfigError::Invalid(problems));
+        }
+
+        Ok(file)
+    }
+
+    /// Values that would break the game, each naming the offending field.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        validate_game("game", &self.game.clone().into(), &self.game, self.sprite_unit_size, &mut problems);
+
+        if !FIXED_HZ_RANGE.contains(&self.game.fixed_hz) {
+            problems.push(format!(
+                "game.fixed_hz: must be from {} to {}, got {}",
+                FIXED_HZ_RANGE.start(), FIXED_HZ_RANGE.end(), self.game.fixed_hz,
+            ));
+        }
+
+        for (key, overrides) in self.modes.iter() {
+            if !GameState::CONFIG_KEYS.contains(&key.as_str()) {
+                problems.push(format!("modes.{}: unknown mode, expected one of {:?}", key, GameState::CONFIG_KEYS));
+                continue;
+            }
+
+            validate_game(&format!("modes.{}", key), overrides, &self.game, self.sprite_unit_size, &mut problems);
+        }
+
+        // Power-ups spawn off the top and bottom walls, 4 units away
+        let max_unit_size = COURT_SIZE.y / 8.;
+        if !(MIN_UNIT_SIZE..max_unit_size).contains(&self.sprite_unit_size) {
+            problems.push(format!(
+                "sprite_unit_size: must be from {} and below {} for a court {} high, got {}",
+                MIN_UNIT_SIZE, max_unit_size, COURT_SIZE.y, self.sprite_unit_size,
+            ));
+        }
+
+        problems
+    }
+}
+
+impl From<GameFile> for GameOverrides {
+    fn from(game: GameFile) -> Self {
+        GameOverrides {
+            paddle_speed: Some(game.paddle_speed),
+            ball_speed_min: Some(game.ball_speed_min),
+            ball_speed_max: Some(game.ball_speed_max),
+            ball_speed_incr: Some(game.ball_speed_incr),
+            ball_oob_x: Some(game.ball_oob_x),
+            score_to_win: Some(game.score_to_win),
+            forward_offset: Some(game.forward_offset),
+        }
+    }
+}
+
+/// Checks `overrides` once applied over `base`, `path` prefixing the reported fields.
+fn validate_game(path: &str, overrides: &GameOverrides, base: &GameFile, unit_size: f32, problems: &mut Vec<String>) {
+    let floats = [
+        ("paddle_speed", overrides.paddle_speed),
+        ("ball_speed_min", overrides.ball_speed_min),
+        ("ball_speed_max", overrides.ball_speed_max),
+        ("ball_speed_incr", overrides.ball_speed_incr),
+        ("ball_oob_x", overrides.ball_oob_x),
+        ("forward_offset", overrides.forward_offset),
+    ];
+    for (field, value) in floats {
+        if let Some(value) = value.filter(|value| !value.is_finite()) {
+            problems.push(format!("{}.{}: must be a finite number, got {}", path, field, value));
+        }
+    }
+
+    let positive = [
+        ("paddle_speed", overrides.paddle_speed),
+        ("ball_speed_min", overrides.ball_speed_min),
+        ("ball_speed_max", overrides.ball_speed_max),
+    ];
+    for (field, value) in positive {
+        if let Some(value) = value {
+            if value <= 0. {
+                problems.push(format!("{}.{}: must be positive, got {}", path, field, value));
+            }
+        }
+    }
+
+    let not_negative = [
+        ("ball_speed_incr", overrides.ball_speed_incr),
+        ("ball_oob_x", overrides.ball_oob_x),
+    ];
+    for (field, value) in not_negative {
+        if let Some(value) = value {
+            if value < 0. {
+                problems.push(format!("{}.{}: must not be negative, got {}", path, field, value));
+            }
+        }
+    }
+
+    // The forward paddle of doubles stays between the back one and the net, touching neither
+    if let Some(forward_offset) = overrides.forward_offset.filter(|value| value.is_finite()) {
+        let min_offset = unit_size * 2.;
+        let max_offset = COURT_SIZE.x / 2. - unit_size * 2.;
+        if !(min_offset..max_offset).contains(&forward_offset) {
+            problems.push(format!(
+                "{}.forward_offset: must be from {} and below {} for a court {} wide, got {}",
+                path, min_offset, max_offset, COURT_SIZE.x, forward_offset,
+            ));
+        }
+    }
+
+    if overrides.score_to_win == Some(0) {
+        problems.push(format!("{}.score_to_win: must be at least 1", path));
+    }
+
+    let speed_min = overrides.ball_speed_min.unwrap_or(base.ball_speed_min);
+    let speed_max = overrides.ball_speed_max.unwrap_or(base.ball_speed_max);
+    if (overrides.ball_speed_min.is_some() || overrides.ball_speed_max.is_some()) && speed_min > speed_max {
+        problems.push(format!(
+            "{}.ball_speed_min: {} is above ball_speed_max {}",
+            path, speed_min, speed_max,
+        ));
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(std::io::Error),
+    Parse(ron::error::SpannedError),
+    Invalid(Vec<String>),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(error) => write!(f, "{}", error),
+            ConfigError::Parse(error) => write!(f, "{}", error),
+            ConfigError::Invalid(problems) => write!(f, "{}", problems.join("; ")),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[derive(Default)]
+pub struct ConfigLoader;
+
+impl AssetLoader for ConfigLoader {
+    type Asset = ConfigAsset;
+    type Settings = ();
+    type Error = ConfigError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<ConfigAsset, ConfigError>> {
+        Box::pin(async move {
+            let mut text = String::new();
+            reader.read_to_string(&mut text).await.map_err(ConfigError::Io)?;
+            Ok(ConfigAsset(ConfigFile::from_ron(&text)))
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["config.ron"]
+    }
+}
+
+/// Arena file, loaded like the tuning file.
+#[derive(Asset, TypePath, Debug)]
+pub struct ArenaAsset(pub Arena);
+
+/// Broken arena files fail to load, with what is wrong with them in the log.
+#[derive(Default)]
+pub struct ArenaLoader;
+
+impl AssetLoader for ArenaLoader {
+    type Asset = ArenaAsset;
+    type Settings = ();
+    type Error = ConfigError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<ArenaAsset, ConfigError>> {
+        Box::pin(async move {
+            let mut text = String::new();
+            reader.read_to_string(&mut text).await.map_err(ConfigError::Io)?;
+
+            let options = ron::Options::default().with_default_extension(ron::extensions::Extensions::IMPLICIT_SOME);
+            let arena: Arena = options.from_str(&text).map_err(ConfigError::Parse)?;
+
+            let problems = arena.validate();
+            if !problems.is_empty() {
+                return Err(ConfigError::Invalid(problems));
+            }
+
+            Ok(ArenaAsset(arena))
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["arena.ron"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forward_offset_stays_between_the_back_paddle_and_the_net() {
+        let mut file = ConfigFile::default();
+        assert_eq!(file.validate(), Vec::<String>::new());
+
+        file.game.forward_offset = COURT_SIZE.x / 2.;
+        file.modes.insert("2v2".to_string(), GameOverrides { forward_offset: Some(file.sprite_unit_size), ..Default::default() });
+
+        let problems = file.validate();
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+        assert!(problems[0].starts_with("game.forward_offset: "), "{:?}", problems);
+        assert!(problems[1].starts_with("modes.2v2.forward_offset: "), "{:?}", problems);
+    }
+
+    #[test]
+    fn numbers_that_would_panic_are_refused() {
+        for fixed_hz in [0., 5., 2000., f64::NAN, f64::INFINITY] {
+            let mut file = ConfigFile::default();
+            file.game.fixed_hz = fixed_hz;
+            assert_eq!(file.validate().len(), 1, "fixed_hz {}", fixed_hz);
+        }
+
+        for unit_size in [0., 3., COURT_SIZE.y / 8., f32::NAN] {
+            let file = ConfigFile { sprite_unit_size: unit_size, ..Default::default() };
+            assert!(file.validate().iter().any(|problem| problem.starts_with("sprite_unit_size: ")), "unit size {}", unit_size);
+        }
+
+        let mut file = ConfigFile::default();
+        file.game.ball_speed_max = f32::INFINITY;
+        file.modes.insert("1p".to_string(), GameOverrides { paddle_speed: Some(f32::NAN), forward_offset: Some(f32::NAN), ..Default::default() });
+        let mut problems = file.validate();
+        problems.sort();
+        assert_eq!(problems, vec![
+            "game.ball_speed_max: must be a finite number, got inf".to_string(),
+            "modes.1p.forward_offset: must be a finite number, got NaN".to_string(),
+            "modes.1p.paddle_speed: must be a finite number, got NaN".to_string(),
+        ]);
+    }
 }
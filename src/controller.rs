@@ -0,0 +1,207 @@
+use bevy::prelude::*;
+
+use crate::ai::*;
+use crate::components::*;
+use crate::gamepad::*;
+use crate::pointer::*;
+use crate::input_map::*;
+use crate::sim::*;
+
+/// Drives the paddle of one side, whatever the game mode.
+#[derive(Component)]
+pub struct PaddleController {
+    pub side: Side,
+    pub line: PaddleLine,
+    pub kind: ControllerKind,
+}
+
+/// Source of the inputs of a paddle.
+pub enum ControllerKind {
+    /// Keys of a player, and the gamepad assigned to them if any.
+    Human(HumanControls),
+    Ai(PaddleAi),
+    /// Inputs recorded in the replay being played back.
+    Replay,
+    /// Inputs of the other peer of an online match, applied by its rollback session.
+    Remote,
+}
+
+pub struct HumanControls {
+    pub up: KeyCode,
+    pub down: KeyCode,
+    pub serve: KeyCode,
+    /// Player whose keys these are.
+    pub player: Player,
+    /// Player whose gamepad also drives the paddle.
+    pub pad: Player,
+    /// Where a finger, or the mouse, drives the paddle.
+    pub zone: PointerZone,
+    /// Serve key or button released since the last tick.
+    serve_requested: bool,
+}
+
+impl HumanControls {
+    pub fn new(up: KeyCode, down: KeyCode, serve: KeyCode, player: Player) -> Self {
+        HumanControls {
+            up,
+            down,
+            serve,
+            player,
+            pad: player,
+            zone: PointerZone::Whole,
+            serve_requested: false,
+        }
+    }
+
+    /// Picks up the keys of the player again, after they were changed.
+    pub fn rebind(&mut self, bindings: &KeyBindings) {
+        self.up = bindings.key(self.player, Action::MoveUp);
+        self.down = bindings.key(self.player, Action::MoveDown);
+        self.serve = bindings.key(self.player, Action::Serve);
+    }
+
+    pub fn with_zone(mut self, zone: PointerZone) -> Self {
+        self.zone = zone;
+        self
+    }
+
+    pub fn for_player(bindings: &KeyBindings, player: Player) -> Self {
+        HumanControls::new(
+            bindings.key(player, Action::MoveUp),
+            bindings.key(player, Action::MoveDown),
+            bindings.key(player, Action::Serve),
+            player,
+        )
+    }
+
+    /// The keys win over the pad, which wins over the pointer: each only counts when the previous one is idle.
+    /// The pointer is followed at most at the paddle speed, like the AI.
+    fn input(
+        &mut self,
+        keyboard: &Input<KeyCode>,
+        pad: Option<GamepadState<'_>>,
+        pointer: Option<(f32, &SimPaddle)>,
+        dt: f32,
+    ) -> PaddleInput {
+        let mut axis = 0.;
+
+        if keyboard.pressed(self.up) {
+            axis += 1.;
+        }
+
+        if keyboard.pressed(self.down) {
+            axis -= 1.;
+        }
+
+        if axis == 0. {
+            if let Some(pad) = pad {
+                axis = pad.axis();
+            }
+        }
+
+        if axis == 0. {
+            if let Some((target_y, paddle)) = pointer {
+                axis = paddle.axis_towards(target_y, dt);
+            }
+        }
+
+        let serve = self.serve_requested;
+        self.serve_requested = false;
+
+        PaddleInput { axis, serve }
+    }
+}
+
+/// Keys may have been changed from the pause menu.
+pub fn rebind_controllers_system(
+    mut controller_query: Query<&mut PaddleController>,
+    bindings: Res<KeyBindings>,
+) {
+    for mut controller in controller_query.iter_mut() {
+        if let ControllerKind::Human(controls) = &mut controller.kind {
+            controls.rebind(&bindings);
+        }
+    }
+}
+
+/// Spawns the controllers of a match, every side following the replay during playback.
+pub fn spawn_controllers(
+    commands: &mut Commands,
+    playback: bool,
+    controllers: impl IntoIterator<Item = (Side, ControllerKind)>,
+) {
+    let controllers = controllers.into_iter().map(|(side, kind)| (side, PaddleLine::Back, kind));
+    spawn_team_controllers(commands, playback, controllers);
+}
+
+/// Same as `spawn_controllers`, for sides with several paddles.
+pub fn spawn_team_controllers(
+    commands: &mut Commands,
+    playback: bool,
+    controllers: impl IntoIterator<Item = (Side, PaddleLine, ControllerKind)>,
+) {
+    for (side, line, kind) in controllers {
+        let kind = if playback { ControllerKind::Replay } else { kind };
+
+        commands
+            .spawn(PaddleController { side, line, kind })
+            .insert(GameModeEntity {});
+    }
+}
+
+/// Runs every frame, so short key presses are not missed between two ticks.
+pub fn latch_serve_system(
+    mut controller_query: Query<&mut PaddleController>,
+    keyboard: Res<Input<KeyCode>>,
+    gamepads: GamepadInputs,
+    mut pointer: PointerInputs,
+) {
+    pointer.update_taps();
+
+    for mut controller in controller_query.iter_mut() {
+        if let ControllerKind::Human(controls) = &mut controller.kind {
+            let pad_serve = gamepads
+                .for_player(controls.pad)
+                .is_some_and(|pad| pad.just_released(GamepadButtonType::South));
+
+            if keyboard.just_released(controls.serve) || pad_serve || pointer.tapped(controls.zone) {
+                controls.serve_requested = true;
+            }
+        }
+    }
+}
+
+pub fn drive_paddles_system(
+    mut controller_query: Query<&mut PaddleController>,
+    mut pending_inputs: ResMut<PendingInputs>,
+    mut game_rng: ResMut<GameRng>,
+    playback: Option<Res<ReplayPlayback>>,
+    keyboard: Res<Input<KeyCode>>,
+    gamepads: GamepadInputs,
+    pointer: PointerInputs,
+    sim: Res<Sim>,
+    time: Res<Time>,
+) {
+    for mut controller in controller_query.iter_mut() {
+        let side = controller.side;
+        let line = controller.line;
+
+        let input = match &mut controller.kind {
+            ControllerKind::Human(controls) => {
+                let pad = gamepads.for_player(controls.pad);
+                // Pointers follow the height of the finger, the top and bottom paddles ignore them
+                let pointer = pointer.target_y(controls.zone).filter(|_| side.is_vertical()).zip(sim.0.paddle_at(side, line));
+                controls.input(&keyboard, pad, pointer, time.delta_seconds())
+            }
+            ControllerKind::Ai(ai) => ai.input(&sim.0, side, line, time.delta_seconds(), &mut game_rng.rng),
+            ControllerKind::Replay => playback
+                .as_ref()
+                .and_then(|playback| playback.replay.inputs_at(sim.0.tick))
+                .map(|inputs| *inputs.get_at(side, line))
+                .unwrap_or_default(),
+            ControllerKind::Remote => continue,
+        };
+
+        *pending_inputs.0.get_at_mut(side, line) = input;
+    }
+}
@@ -0,0 +1,527 @@
+use bevy::prelude::*;
+use bevy::ui::UiSystem;
+use bevy::window::PrimaryWindow;
+
+use crate::config::*;
+use crate::gamepad::*;
+use crate::navigation::*;
+use crate::pause::*;
+use crate::input_map::*;
+use crate::systems_generic::*;
+use crate::state::*;
+
+#[derive(Component)]
+struct ControlsEntity {}
+
+#[derive(Component)]
+struct ControlsButton {}
+
+/// Key of one action of one player, click it then press the new key.
+#[derive(Component)]
+struct BindingButton {
+    player: Player,
+    action: Action,
+}
+
+#[derive(Component)]
+struct ResetBindingsButton {}
+
+#[derive(Component)]
+struct FollowMouseButton {}
+
+#[derive(Component)]
+struct ControlsMessage {}
+
+/// Binding waiting for a key.
+#[derive(Resource)]
+struct Rebinding {
+    player: Player,
+    action: Action,
+}
+
+pub struct ControlsPlugin;
+
+impl Plugin for ControlsPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_systems(OnEnter(GameState::Controls), setup_controls_system)
+            .add_systems(OnEnter(PauseState::Settings), setup_controls_system)
+            .add_systems(PreUpdate, button_navigation_system::<ControlsButton>
+                .after(UiSystem::Focus)
+                .run_if(controls_open)
+                .run_if(not(resource_exists::<Rebinding>())))
+            .add_systems(Update, (
+                hover_controls_buttons_system,
+                update_binding_labels_system,
+                click_binding_button_system,
+                click_reset_button_system,
+                click_follow_mouse_button_system,
+            ).run_if(controls_open))
+            .add_systems(Update, capture_key_system.run_if(controls_open).run_if(resource_exists::<Rebinding>()))
+            // The back key may be the one being bound
+            .add_systems(Update, controls_back_system.run_if(controls_open).run_if(not(resource_exists::<Rebinding>())))
+            .add_systems(OnExit(GameState::Controls), (
+                cleanup_entities::<ControlsEntity>,
+                cancel_rebinding_system,
+            ))
+            .add_systems(OnExit(PauseState::Settings), (
+                cleanup_entities::<ControlsEntity>,
+                cancel_rebinding_system,
+            ));
+    }
+}
+
+/// From the menu, or over a paused match.
+fn controls_open(
+    game_state: Res<State<GameState>>,
+    pause_state: Res<State<PauseState>>,
+) -> bool {
+    *game_state.get() == GameState::Controls || *pause_state.get() == PauseState::Settings
+}
+
+fn setup_controls_system(
+    mut commands: Commands,
+    window: Query<&Window, With<PrimaryWindow>>,
+    bindings: Res<KeyBindings>,
+    config: Res<Config>,
+) {
+    let window = window.get_single().unwrap();
+
+    // Backdrop, over the match when opened from the pause menu
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(100.),
+                height: Val::Percent(100.),
+                position_type: PositionType::Absolute,
+                ..Default::default()
+            },
+            background_color: BACKDROP_COLOR.into(),
+            z_index: ZIndex::Global(1),
+            ..Default::default()
+        })
+        .insert(ControlsEntity {})
+        .with_children(|parent| {
+            // Title
+            parent
+                .spawn(ButtonBundle {
+                    style: Style {
+                        width: Val::Px(416.),
+                        height: Val::Px(96.),
+                        right: Val::Px((window.width() - 416.) / 2.),
+                        top: Val::Px(48.),
+                        position_type: PositionType::Absolute,
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    background_color: config.color_transparent.into(),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    parent.spawn(TextBundle {
+                        text: Text::from_section(
+                            "CONTROLS",
+                            TextStyle {
+                                font: config.font.clone(),
+                                font_size: 66.,
+                                color: config.color_white,
+                            },
+                        ),
+                        ..Default::default()
+                    });
+                });
+
+            let player_columns = [
+                (Player::One, window.width() / 2. - 32.),
+                (Player::Two, window.width() / 2. + 160.),
+            ];
+
+            // Player headers
+            for (player, left) in player_columns {
+                parent
+                    .spawn(ButtonBundle {
+                        style: Style {
+                            width: Val::Px(160.),
+                            height: Val::Px(40.),
+                            left: Val::Px(left),
+                            top: Val::Px(148.),
+                            position_type: PositionType::Absolute,
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..Default::default()
+                        },
+                        background_color: config.color_transparent.into(),
+                        ..Default::default()
+                    })
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle {
+                            text: Text::from_section(
+                                player.label(),
+                                TextStyle {
+                                    font: config.font.clone(),
+                                    font_size: 24.,
+                                    color: config.color_grey,
+                                },
+                            ),
+                            ..Default::default()
+                        });
+                    });
+            }
+
+            for (index, action) in Action::ALL.into_iter().enumerate() {
+                let top = 192. + index as f32 * 44.;
+
+                // Action label
+                parent
+                    .spawn(ButtonBundle {
+                        style: Style {
+                            width: Val::Px(224.),
+                            height: Val::Px(40.),
+                            left: Val::Px(window.width() / 2. - 288.),
+                            top: Val::Px(top),
+                            position_type: PositionType::Absolute,
+                            justify_content: JustifyContent::FlexEnd,
+                            align_items: AlignItems::Center,
+                            ..Default::default()
+                        },
+                        background_color: config.color_transparent.into(),
+                        ..Default::default()
+                    })
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle {
+                            text: Text::from_section(
+                                action.label(),
+                                TextStyle {
+                                    font: config.font.clone(),
+                                    font_size: 24.,
+                                    color: config.color_yellow,
+                                },
+                            ),
+                            ..Default::default()
+                        });
+                    });
+
+                // Keys of both players
+                for (player, left) in player_columns {
+                    parent
+                        .spawn(ButtonBundle {
+                            style: Style {
+                                width: Val::Px(160.),
+                                height: Val::Px(40.),
+                                left: Val::Px(left),
+                                top: Val::Px(top),
+                                position_type: PositionType::Absolute,
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..Default::default()
+                            },
+                            background_color: config.color_transparent.into(),
+                            ..Default::default()
+                        })
+                        .with_children(|parent| {
+                            parent.spawn(TextBundle {
+                                text: Text::from_section(
+                                    key_label(bindings.key(player, action)),
+                                    TextStyle {
+                                        font: config.font.clone(),
+                                        font_size: 24.,
+                                        color: config.color_white,
+                                    },
+                                ),
+                                ..Default::default()
+                            });
+                        })
+                        .insert(ControlsButton {})
+                        .insert(BindingButton { player, action });
+                }
+            }
+
+            // Message
+            parent
+                .spawn(ButtonBundle {
+                    style: Style {
+                        width: Val::Px(window.width()),
+                        height: Val::Px(32.),
+                        left: Val::Px(0.),
+                        top: Val::Px(420.),
+                        position_type: PositionType::Absolute,
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    background_color: config.color_transparent.into(),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    parent.spawn(TextBundle {
+                        text: Text::from_section(
+                            default_message(&bindings),
+                            TextStyle {
+                                font: config.font.clone(),
+                                font_size: 18.,
+                                color: config.color_grey,
+                            },
+                        ),
+                        ..Default::default()
+                    });
+                })
+                .insert(ControlsMessage {});
+
+            // Mouse option
+            parent
+                .spawn(ButtonBundle {
+                    style: Style {
+                        width: Val::Px(256.),
+                        height: Val::Px(44.),
+                        left: Val::Px(window.width() / 2. - 272.),
+                        top: Val::Px(468.),
+                        position_type: PositionType::Absolute,
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    background_color: config.color_transparent.into(),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    parent.spawn(TextBundle {
+                        text: Text::from_section(
+                            follow_mouse_label(&bindings),
+                            TextStyle {
+                                font: config.font.clone(),
+                                font_size: 30.,
+                                color: config.color_white,
+                            },
+                        ),
+                        ..Default::default()
+                    });
+                })
+                .insert(ControlsButton {})
+                .insert(FollowMouseButton {});
+
+            // Reset button
+            parent
+                .spawn(ButtonBundle {
+                    style: Style {
+                        width: Val::Px(256.),
+                        height: Val::Px(44.),
+                        left: Val::Px(window.width() / 2. + 16.),
+                        top: Val::Px(468.),
+                        position_type: PositionType::Absolute,
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    background_color: config.color_transparent.into(),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    parent.spawn(TextBundle {
+                        text: Text::from_section(
+                            "Reset defaults",
+                            TextStyle {
+                                font: config.font.clone(),
+                                font_size: 30.,
+                                color: config.color_white,
+                            },
+                        ),
+                        ..Default::default()
+                    });
+                })
+                .insert(ControlsButton {})
+                .insert(ResetBindingsButton {});
+        });
+}
+
+fn follow_mouse_label(bindings: &KeyBindings) -> &'static str {
+    if bindings.follow_mouse { "Mouse: on" } else { "Mouse: off" }
+}
+
+fn default_message(bindings: &KeyBindings) -> String {
+    format!("click a key to change it, {} to go back", bindings.shared_label(Action::Back))
+}
+
+fn set_message(
+    message_query: &Query<&Children, With<ControlsMessage>>,
+    text_query: &mut Query<&mut Text>,
+    message: String,
+    color: Color,
+) {
+    for children in message_query.iter() {
+        let mut text = text_query.get_mut(children[0]).unwrap();
+        text.sections[0].value = message.clone();
+        text.sections[0].style.color = color;
+    }
+}
+
+fn hover_controls_buttons_system(
+    mut window: Query<&mut Window, With<PrimaryWindow>>,
+    interaction_query: Query<(&Interaction, &Children, Option<&BindingButton>), With<ControlsButton>>,
+    mut text_query: Query<&mut Text>,
+    rebinding: Option<Res<Rebinding>>,
+    config: Res<Config>,
+) {
+    let mut window = window.get_single_mut().unwrap();
+    let mut hovered: bool = false;
+
+    for (interaction, children, binding) in interaction_query.iter() {
+        let mut text = text_query.get_mut(children[0]).unwrap();
+        let waiting = match (&rebinding, binding) {
+            (Some(rebinding), Some(binding)) => rebinding.player == binding.player && rebinding.action == binding.action,
+            _ => false,
+        };
+
+        if *interaction == Interaction::Hovered {
+            hovered = true;
+        }
+
+        text.sections[0].style.color = if waiting {
+            config.color_green
+        } else if *interaction == Interaction::Hovered {
+            config.color_yellow
+        } else {
+            config.color_white
+        };
+    }
+
+    window.cursor.icon = if hovered { CursorIcon::Hand } else { CursorIcon::Default };
+}
+
+fn update_binding_labels_system(
+    button_query: Query<(&Children, &BindingButton)>,
+    mut text_query: Query<&mut Text>,
+    rebinding: Option<Res<Rebinding>>,
+    bindings: Res<KeyBindings>,
+) {
+    for (children, button) in button_query.iter() {
+        let waiting = rebinding.as_ref().is_some_and(|rebinding| rebinding.player == button.player && rebinding.action == button.action);
+        let label = if waiting {
+            "press a key".to_string()
+        } else {
+            key_label(bindings.key(button.player, button.action))
+        };
+
+        let mut text = text_query.get_mut(children[0]).unwrap();
+        if text.sections[0].value != label {
+            text.sections[0].value = label;
+        }
+    }
+}
+
+fn click_binding_button_system(
+    mut commands: Commands,
+    interaction_query: Query<(&Interaction, &BindingButton), Changed<Interaction>>,
+    message_query: Query<&Children, With<ControlsMessage>>,
+    mut text_query: Query<&mut Text>,
+    rebinding: Option<Res<Rebinding>>,
+    bindings: Res<KeyBindings>,
+    config: Res<Config>,
+) {
+    for (interaction, button) in interaction_query.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+
+        // Clicking the binding being changed again cancels it
+        if rebinding.as_ref().is_some_and(|rebinding| rebinding.player == button.player && rebinding.action == button.action) {
+            commands.remove_resource::<Rebinding>();
+            set_message(&message_query, &mut text_query, default_message(&bindings), config.color_grey);
+            return;
+        }
+
+        commands.insert_resource(Rebinding { player: button.player, action: button.action });
+        set_message(
+            &message_query,
+            &mut text_query,
+            format!("press a key to {} for {}, click again to cancel", button.action.label(), button.player.label()),
+            config.color_grey,
+        );
+        return;
+    }
+}
+
+fn capture_key_system(
+    mut commands: Commands,
+    mut bindings: ResMut<KeyBindings>,
+    mut text_query: Query<&mut Text>,
+    message_query: Query<&Children, With<ControlsMessage>>,
+    rebinding: Res<Rebinding>,
+    keyboard: Res<Input<KeyCode>>,
+    config: Res<Config>,
+) {
+    let Some(&key) = keyboard.get_just_pressed().next() else {
+        return;
+    };
+
+    // A conflicting key is refused, the binding keeps waiting for another one
+    match bindings.bind(rebinding.player, rebinding.action, key) {
+        Ok(()) => {
+            commands.remove_resource::<Rebinding>();
+            set_message(&message_query, &mut text_query, default_message(&bindings), config.color_grey);
+        }
+        Err(conflict) => {
+            set_message(&message_query, &mut text_query, conflict.to_string(), config.color_red);
+        }
+    }
+}
+
+fn click_reset_button_system(
+    mut commands: Commands,
+    mut bindings: ResMut<KeyBindings>,
+    mut text_query: Query<&mut Text>,
+    interaction_query: Query<&Interaction, (Changed<Interaction>, With<ResetBindingsButton>)>,
+    message_query: Query<&Children, With<ControlsMessage>>,
+    config: Res<Config>,
+) {
+    for interaction in interaction_query.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+
+        // Only the keys, the mouse option has its own button
+        *bindings = KeyBindings { follow_mouse: bindings.follow_mouse, ..Default::default() };
+        commands.remove_resource::<Rebinding>();
+        set_message(&message_query, &mut text_query, "default controls restored".to_string(), config.color_green);
+    }
+}
+
+fn click_follow_mouse_button_system(
+    mut bindings: ResMut<KeyBindings>,
+    interaction_query: Query<(Ref<Interaction>, &Children), With<FollowMouseButton>>,
+    mut text_query: Query<&mut Text>,
+) {
+    for (interaction, children) in interaction_query.iter() {
+        if !interaction.is_changed() || *interaction != Interaction::Pressed {
+            continue;
+        }
+
+        bindings.follow_mouse = !bindings.follow_mouse;
+        text_query.get_mut(children[0]).unwrap().sections[0].value = follow_mouse_label(&bindings).to_string();
+    }
+}
+
+fn controls_back_system(
+    mut next_game_state: ResMut<NextState<GameState>>,
+    mut next_pause_state: ResMut<NextState<PauseState>>,
+    pause_state: Res<State<PauseState>>,
+    keyboard: Res<Input<KeyCode>>,
+    gamepads: GamepadInputs,
+    bindings: Res<KeyBindings>,
+) {
+    if bindings.keys(Action::Back).any(|key| keyboard.just_pressed(key))
+        || gamepads.any_just_pressed(GamepadButtonType::Select)
+        || gamepads.any_just_pressed(GamepadButtonType::Start) {
+        if *pause_state.get() == PauseState::Settings {
+            next_pause_state.set(PauseState::Paused);
+        } else {
+            next_game_state.set(GameState::Menu);
+        }
+    }
+}
+
+fn cancel_rebinding_system(
+    mut commands: Commands,
+) {
+    commands.remove_resource::<Rebinding>();
+}
@@ -1,14 +1,7 @@
 use bevy::ecs::event::Event;
-use crate::components::*;
+use crate::sim::*;
 
-pub struct GameOverEvent(pub Side);
+/// Forwards what happened during a simulation tick to the presentation systems.
+pub struct MatchEvent(pub SimEvent);
 
-impl Event for GameOverEvent {}
-
-pub struct BallOutEvent(pub Side);
-
-impl Event for BallOutEvent {}
-
-pub struct BallHitPaddleEvent();
-
-impl Event for BallHitPaddleEvent {}
+impl Event for MatchEvent {}
@@ -0,0 +1,513 @@
+use bevy::prelude::*;
+use bevy::ui::UiSystem;
+use bevy::window::PrimaryWindow;
+
+use crate::components::*;
+use crate::config::*;
+use crate::events::*;
+use crate::high_scores::*;
+use crate::input_map::*;
+use crate::loading::*;
+use crate::navigation::*;
+use crate::pause::*;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::replay::files;
+use crate::sim::*;
+use crate::systems_generic::*;
+use crate::state::*;
+
+#[derive(Component)]
+struct GameOverButton {}
+
+#[derive(Component, Copy, Clone)]
+enum GameOverAction {
+    Rematch,
+    /// Replays are files, there is none on the web.
+    #[cfg(not(target_arch = "wasm32"))]
+    SaveReplay,
+    Menu,
+}
+
+impl GameOverAction {
+    fn all() -> Vec<GameOverAction> {
+        let mut actions = vec![GameOverAction::Rematch];
+        #[cfg(not(target_arch = "wasm32"))]
+        actions.push(GameOverAction::SaveReplay);
+        actions.push(GameOverAction::Menu);
+        actions
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            GameOverAction::Rematch => "Rematch",
+            #[cfg(not(target_arch = "wasm32"))]
+            GameOverAction::SaveReplay => "Save replay",
+            GameOverAction::Menu => "Back to menu",
+        }
+    }
+}
+
+#[derive(Default)]
+struct SideStats {
+    points: u32,
+    sets: u32,
+    serves: u32,
+    /// Points scored on its own serve.
+    serves_won: u32,
+}
+
+impl SideStats {
+    fn serve_win_rate(&self) -> String {
+        if self.serves == 0 {
+            return "-".to_string();
+        }
+
+        format!("{}%", self.serves_won * 100 / self.serves)
+    }
+}
+
+/// Summary of the current local match, built from its `MatchEvent`s.
+#[derive(Default, Resource)]
+pub struct MatchStats {
+    left: SideStats,
+    right: SideStats,
+    top: SideStats,
+    bottom: SideStats,
+    /// Four players sides, in the order they went out.
+    eliminated: Vec<Side>,
+    first_service: Option<Side>,
+    service: Option<Side>,
+    /// Paddle hits since the last serve.
+    rally: u32,
+    longest_rally: u32,
+    fastest_ball_speed: f32,
+    /// Seconds, once the match is over.
+    duration: f32,
+}
+
+impl MatchStats {
+    /// In pixels per second.
+    pub fn fastest_ball_speed(&self) -> f32 {
+        self.fastest_ball_speed
+    }
+
+    fn side_mut(&mut self, side: Side) -> &mut SideStats {
+        match side {
+            Side::Left => &mut self.left,
+            Side::Right => &mut self.right,
+            Side::Top => &mut self.top,
+            Side::Bottom => &mut self.bottom,
+        }
+    }
+}
+
+pub struct GameOverPlugin;
+
+impl Plugin for GameOverPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_systems(Update, (
+                record_match_stats_system,
+                setup_game_over_panel_system.after(record_match_stats_system),
+            ).run_if(in_local_match))
+            .add_systems(PreUpdate, button_navigation_system::<GameOverButton>
+                .after(UiSystem::Focus)
+                .run_if(in_local_match)
+                .run_if(match_over)
+                .run_if(not(naming_high_score)))
+            .add_systems(Update, (
+                hover_game_over_buttons_system,
+                click_game_over_buttons_system,
+                back_to_menu_system,
+            ).run_if(in_local_match).run_if(match_over).run_if(not(naming_high_score)));
+
+        #[cfg(not(target_arch = "wasm32"))]
+        app.add_systems(Update, save_replay_button_system.run_if(in_local_match).run_if(match_over).run_if(not(naming_high_score)));
+
+        for state in LOCAL_MATCH_STATES {
+            app
+                .add_systems(OnEnter(state.clone()), reset_match_stats_system)
+                .add_systems(OnExit(state), reset_cursor_system);
+        }
+    }
+}
+
+pub fn match_over(
+    sim: Option<Res<Sim>>,
+) -> bool {
+    sim.is_some_and(|sim| sim.0.game_over.is_some())
+}
+
+fn reset_match_stats_system(
+    mut commands: Commands,
+) {
+    commands.insert_resource(MatchStats::default());
+}
+
+pub fn record_match_stats_system(
+    mut match_events: EventReader<MatchEvent>,
+    mut stats: ResMut<MatchStats>,
+    sim: Res<Sim>,
+    fixed_time: Res<Time<Fixed>>,
+) {
+    for event in match_events.read() {
+        match event.0 {
+            SimEvent::Served(side) => {
+                stats.first_service.get_or_insert(side);
+                stats.service = Some(side);
+                stats.side_mut(side).serves += 1;
+                stats.rally = 0;
+            }
+            SimEvent::PaddleHit(_) => {
+                stats.rally += 1;
+                stats.longest_rally = stats.longest_rally.max(stats.rally);
+            }
+            SimEvent::Scored(side) => {
+                stats.side_mut(side).points += 1;
+                if stats.service == Some(side) && sim.0.mode == SimMode::Versus {
+                    stats.side_mut(side).serves_won += 1;
+                }
+            }
+            SimEvent::SetWon(side) => stats.side_mut(side).sets += 1,
+            SimEvent::Eliminated(side) => stats.eliminated.push(side),
+            SimEvent::GameOver(_) => {
+                stats.duration = sim.0.tick as f32 * fixed_time.timestep().as_secs_f32();
+            }
+            _ => {}
+        }
+
+        // The ball only speeds up when served or hit, the sim is at most a few ticks ahead of the event
+        if let SimEvent::Served(_) | SimEvent::PaddleHit(_) = event.0 {
+            for ball in sim.0.balls.iter() {
+                stats.fastest_ball_speed = stats.fastest_ball_speed.max(ball.speed);
+            }
+        }
+    }
+}
+
+fn setup_game_over_panel_system(
+    mut commands: Commands,
+    mut match_events: EventReader<MatchEvent>,
+    window: Query<&Window, With<PrimaryWindow>>,
+    stats: Res<MatchStats>,
+    state: Res<State<GameState>>,
+    bindings: Res<KeyBindings>,
+    config: Res<Config>,
+) {
+    let Some(winner) = match_events.read().find_map(|event| match event.0 {
+        SimEvent::GameOver(winner) => Some(winner),
+        _ => None,
+    }) else {
+        return;
+    };
+
+    let window = window.get_single().unwrap();
+
+    let title = match (state.get(), winner) {
+        (GameState::ModeWall, _) => "GAME OVER".to_string(),
+        (GameState::Mode1P, Side::Left) => "YOU WIN".to_string(),
+        (GameState::Mode1P, _) => "YOU LOSE".to_string(),
+        (_, side) => format!("{} WINS", side.label().to_uppercase()),
+    };
+
+    let duration = stats.duration as u32;
+    let fastest_ball = format!("{:.0} px/s", stats.fastest_ball_speed);
+
+    // Wall mode makes room for the high scores below its stats, see `HighScoresPlugin`
+    let wall = *state.get() == GameState::ModeWall;
+    let rows = if wall {
+        vec![
+            ("Points", format!("{}", stats.left.points)),
+            ("Fastest ball", fastest_ball),
+        ]
+    } else if *state.get() == GameState::Mode4P {
+        let eliminated: Vec<&str> = stats.eliminated.iter().map(|side| side.label()).collect();
+        vec![
+            ("Duration", format!("{}:{:02}", duration / 60, duration % 60)),
+            ("Longest rally", format!("{} hits", stats.longest_rally)),
+            ("Fastest ball", fastest_ball),
+            ("Out in order", eliminated.join(", ")),
+        ]
+    } else {
+        let mut rows = vec![
+            ("Duration", format!("{}:{:02}", duration / 60, duration % 60)),
+            ("Longest rally", format!("{} hits", stats.longest_rally)),
+            ("Fastest ball", fastest_ball),
+            ("Points", format!("{} - {}", stats.left.points, stats.right.points)),
+            ("Serves won", format!("{} - {}", stats.left.serve_win_rate(), stats.right.serve_win_rate())),
+        ];
+        // A single set match has nothing to add to the points
+        if stats.left.sets + stats.right.sets > 1 {
+            rows.insert(3, ("Sets", format!("{} - {}", stats.left.sets, stats.right.sets)));
+        }
+        rows
+    };
+    let (title_top, rows_top) = if wall { (24., 104.) } else { (40., 136.) };
+    let row_step = if wall || rows.len() > 5 { 28. } else { 32. };
+    let (buttons_top, button_step) = if wall { (420., 44.) } else { (316., 52.) };
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(100.),
+                height: Val::Percent(100.),
+                position_type: PositionType::Absolute,
+                ..Default::default()
+            },
+            background_color: BACKDROP_COLOR.into(),
+            z_index: ZIndex::Global(1),
+            ..Default::default()
+        })
+        .insert(GameModeEntity {})
+        .with_children(|parent| {
+            // Title
+            parent
+                .spawn(ButtonBundle {
+                    style: Style {
+                        width: Val::Px(544.),
+                        height: Val::Px(80.),
+                        right: Val::Px((window.width() - 544.) / 2.),
+                        top: Val::Px(title_top),
+                        position_type: PositionType::Absolute,
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    background_color: config.color_transparent.into(),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    parent.spawn(TextBundle {
+                        text: Text::from_section(
+                            title,
+                            TextStyle {
+                                font: config.font.clone(),
+                                font_size: 66.,
+                                color: config.color_white,
+                            },
+                        ),
+                        ..Default::default()
+                    });
+                });
+
+            // Stats, labels on the left half and values on the right one
+            for (index, (label, value)) in rows.into_iter().enumerate() {
+                let top = rows_top + index as f32 * row_step;
+
+                parent
+                    .spawn(ButtonBundle {
+                        style: Style {
+                            width: Val::Px(window.width() / 2. - 16.),
+                            height: Val::Px(32.),
+                            left: Val::Px(0.),
+                            top: Val::Px(top),
+                            position_type: PositionType::Absolute,
+                            justify_content: JustifyContent::FlexEnd,
+                            align_items: AlignItems::Center,
+                            ..Default::default()
+                        },
+                        background_color: config.color_transparent.into(),
+                        ..Default::default()
+                    })
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle {
+                            text: Text::from_section(
+                                label,
+                                TextStyle {
+                                    font: config.font.clone(),
+                                    font_size: 24.,
+                                    color: config.color_yellow,
+                                },
+                            ),
+                            ..Default::default()
+                        });
+                    });
+
+                parent
+                    .spawn(ButtonBundle {
+                        style: Style {
+                            width: Val::Px(window.width() / 2. - 16.),
+                            height: Val::Px(32.),
+                            left: Val::Px(window.width() / 2. + 16.),
+                            top: Val::Px(top),
+                            position_type: PositionType::Absolute,
+                            justify_content: JustifyContent::FlexStart,
+                            align_items: AlignItems::Center,
+                            ..Default::default()
+                        },
+                        background_color: config.color_transparent.into(),
+                        ..Default::default()
+                    })
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle {
+                            text: Text::from_section(
+                                value,
+                                TextStyle {
+                                    font: config.font.clone(),
+                                    font_size: 24.,
+                                    color: config.color_grey,
+                                },
+                            ),
+                            ..Default::default()
+                        });
+                    });
+            }
+
+            for (index, action) in GameOverAction::all().into_iter().enumerate() {
+                parent
+                    .spawn(ButtonBundle {
+                        style: Style {
+                            width: Val::Px(320.),
+                            height: Val::Px(button_step - 8.),
+                            right: Val::Px((window.width() - 320.) / 2.),
+                            top: Val::Px(buttons_top + index as f32 * button_step),
+                            position_type: PositionType::Absolute,
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..Default::default()
+                        },
+                        background_color: config.color_transparent.into(),
+                        ..Default::default()
+                    })
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle {
+                            text: Text::from_section(
+                                action.label(),
+                                TextStyle {
+                                    font: config.font.clone(),
+                                    font_size: 36.,
+                                    color: config.color_white,
+                                },
+                            ),
+                            ..Default::default()
+                        });
+                    })
+                    .insert(GameOverButton {})
+                    .insert(action);
+            }
+
+            // Keys, there is no room left for them under the high scores
+            if !wall {
+                parent
+                    .spawn(ButtonBundle {
+                        style: Style {
+                            width: Val::Px(window.width()),
+                            height: Val::Px(32.),
+                            left: Val::Px(0.),
+                            top: Val::Px(484.),
+                            position_type: PositionType::Absolute,
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..Default::default()
+                        },
+                        background_color: config.color_transparent.into(),
+                        ..Default::default()
+                    })
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle {
+                            text: Text::from_section(
+                                format!(
+                                    "UP/DOWN and {} to choose, {} for the menu",
+                                    bindings.shared_label(Action::Serve),
+                                    bindings.shared_label(Action::Back),
+                                ),
+                                TextStyle {
+                                    font: config.font.clone(),
+                                    font_size: 18.,
+                                    color: config.color_grey,
+                                },
+                            ),
+                            ..Default::default()
+                        });
+                    });
+            }
+        });
+}
+
+fn hover_game_over_buttons_system(
+    mut window: Query<&mut Window, With<PrimaryWindow>>,
+    interaction_query: Query<(&Interaction, &Children), With<GameOverButton>>,
+    mut text_query: Query<&mut Text>,
+    config: Res<Config>,
+) {
+    let mut window = window.get_single_mut().unwrap();
+    let mut hovered: bool = false;
+
+    for (interaction, children) in interaction_query.iter() {
+        let mut text = text_query.get_mut(children[0]).unwrap();
+        match *interaction {
+            Interaction::Hovered => {
+                text.sections[0].style.color = config.color_yellow;
+                hovered = true;
+            }
+            Interaction::None => {
+                text.sections[0].style.color = config.color_white;
+            }
+            _ => {}
+        }
+    }
+
+    window.cursor.icon = if hovered { CursorIcon::Hand } else { CursorIcon::Default };
+}
+
+fn click_game_over_buttons_system(
+    mut commands: Commands,
+    mut game_state: ResMut<NextState<GameState>>,
+    interaction_query: Query<(&Interaction, &GameOverAction), Changed<Interaction>>,
+    state: Res<State<GameState>>,
+    stats: Res<MatchStats>,
+) {
+    for (interaction, action) in interaction_query.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+
+        match action {
+            GameOverAction::Rematch => {
+                // The side that received first serves first this time
+                if let Some(side) = stats.first_service {
+                    commands.insert_resource(RematchService(side.opponent()));
+                }
+                commands.insert_resource(RestartState(state.get().clone()));
+                game_state.set(GameState::Restarting);
+            }
+            GameOverAction::Menu => game_state.set(GameState::Menu),
+            #[cfg(not(target_arch = "wasm32"))]
+            GameOverAction::SaveReplay => continue,
+        }
+        return;
+    }
+}
+
+/// Saves the replay once, the recorder is gone afterwards.
+#[cfg(not(target_arch = "wasm32"))]
+fn save_replay_button_system(
+    mut commands: Commands,
+    interaction_query: Query<(&Interaction, &GameOverAction, &Children), Changed<Interaction>>,
+    mut text_query: Query<&mut Text>,
+    recorder: Option<Res<MatchRecorder>>,
+    config: Res<Config>,
+) {
+    let Some(recorder) = recorder else {
+        return;
+    };
+
+    for (interaction, action, children) in interaction_query.iter() {
+        if *interaction != Interaction::Pressed || !matches!(action, GameOverAction::SaveReplay) {
+            continue;
+        }
+
+        match files::save(&recorder.0) {
+            Ok(path) => {
+                info!("Replay saved to {}", path.display());
+                spawn_toast(&mut commands, &config, "replay saved".to_string(), config.color_green);
+                text_query.get_mut(children[0]).unwrap().sections[0].value = "Replay saved".to_string();
+                commands.remove_resource::<MatchRecorder>();
+            }
+            Err(error) => {
+                warn!("Could not save replay: {}", error);
+                spawn_toast(&mut commands, &config, format!("could not save replay: {}", error), config.color_red);
+            }
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use bevy::ecs::system::SystemParam;
+use bevy::input::gamepad::{GamepadConnection, GamepadConnectionEvent};
+use bevy::prelude::*;
+
+use crate::config::*;
+use crate::input_map::*;
+use crate::loading::*;
+
+/// Gamepad of each player, given in the order they are plugged in.
+#[derive(Default, Resource)]
+pub struct GamepadAssignments {
+    one: Option<Gamepad>,
+    two: Option<Gamepad>,
+}
+
+impl GamepadAssignments {
+    pub fn get(&self, player: Player) -> Option<Gamepad> {
+        match player {
+            Player::One => self.one,
+            Player::Two => self.two,
+        }
+    }
+
+    fn slot(&mut self, player: Player) -> &mut Option<Gamepad> {
+        match player {
+            Player::One => &mut self.one,
+            Player::Two => &mut self.two,
+        }
+    }
+}
+
+/// Everything needed to read the gamepads in a system.
+#[derive(SystemParam)]
+pub struct GamepadInputs<'w> {
+    assignments: Res<'w, GamepadAssignments>,
+    gamepads: Res<'w, Gamepads>,
+    buttons: Res<'w, Input<GamepadButton>>,
+    axes: Res<'w, Axis<GamepadAxis>>,
+}
+
+impl<'w> GamepadInputs<'w> {
+    pub fn for_player(&self, player: Player) -> Option<GamepadState<'_>> {
+        self.assignments.get(player).map(|gamepad| self.state(gamepad))
+    }
+
+    /// Whether a button was just pressed on any gamepad, assigned or not.
+    pub fn any_just_pressed(&self, button_type: GamepadButtonType) -> bool {
+        self.gamepads.iter().any(|gamepad| self.state(gamepad).just_pressed(button_type))
+    }
+
+    /// Left stick pushed the furthest up or down, among all gamepads.
+    pub fn any_left_stick_y(&self) -> f32 {
+        self.gamepads.iter()
+            .filter_map(|gamepad| self.axes.get(GamepadAxis::new(gamepad, GamepadAxisType::LeftStickY)))
+            .max_by(|a, b| a.abs().total_cmp(&b.abs()))
+            .unwrap_or(0.)
+    }
+
+    fn state(&self, gamepad: Gamepad) -> GamepadState<'_> {
+        GamepadState { gamepad, buttons: &self.buttons, axes: &self.axes }
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct GamepadState<'a> {
+    gamepad: Gamepad,
+    buttons: &'a Input<GamepadButton>,
+    axes: &'a Axis<GamepadAxis>,
+}
+
+impl<'a> GamepadState<'a> {
+    pub fn pressed(&self, button_type: GamepadButtonType) -> bool {
+        self.buttons.pressed(GamepadButton::new(self.gamepad, button_type))
+    }
+
+    pub fn just_pressed(&self, button_type: GamepadButtonType) -> bool {
+        self.buttons.just_pressed(GamepadButton::new(self.gamepad, button_type))
+    }
+
+    pub fn just_released(&self, button_type: GamepadButtonType) -> bool {
+        self.buttons.just_released(GamepadButton::new(self.gamepad, button_type))
+    }
+
+    /// Vertical axis of a paddle: full speed with the D-pad, proportional with the left stick.
+    pub fn axis(&self) -> f32 {
+        if self.pressed(GamepadButtonType::DPadUp) {
+            return 1.;
+        }
+
+        if self.pressed(GamepadButtonType::DPadDown) {
+            return -1.;
+        }
+
+        self.axes
+            .get(GamepadAxis::new(self.gamepad, GamepadAxisType::LeftStickY))
+            .unwrap_or(0.)
+    }
+}
+
+pub struct GamepadPlugin;
+
+impl Plugin for GamepadPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<GamepadAssignments>()
+            .add_systems(Update, assign_gamepads_system);
+    }
+}
+
+/// Hot-plug: a new pad takes the first free player, and frees it once unplugged.
+fn assign_gamepads_system(
+    mut commands: Commands,
+    mut assignments: ResMut<GamepadAssignments>,
+    mut connection_events: EventReader<GamepadConnectionEvent>,
+    config: Res<Config>,
+) {
+    for event in connection_events.read() {
+        match &event.connection {
+            GamepadConnection::Connected(info) => {
+                if Player::ALL.iter().any(|&player| assignments.get(player) == Some(event.gamepad)) {
+                    continue;
+                }
+
+                let Some(player) = Player::ALL.into_iter().find(|&player| assignments.get(player).is_none()) else {
+                    info!("Gamepad {} connected, both players already have one", info.name);
+                    continue;
+                };
+
+                *assignments.slot(player) = Some(event.gamepad);
+                info!("Gamepad {} connected for {}", info.name, player.label());
+                spawn_toast(&mut commands, &config, format!("gamepad connected: {}", player.label()), config.color_green);
+            }
+            GamepadConnection::Disconnected => {
+                for player in Player::ALL {
+                    if assignments.get(player) == Some(event.gamepad) {
+                        *assignments.slot(player) = None;
+                        info!("Gamepad of {} disconnected", player.label());
+                        spawn_toast(&mut commands, &config, format!("gamepad disconnected: {}", player.label()), config.color_red);
+                    }
+                }
+            }
+        }
+    }
+}
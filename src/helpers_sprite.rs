@@ -1,85 +1,10 @@
 use bevy::prelude::*;
 
-pub fn create_top_wall_sprite(window_width: f32, window_height: f32, unit_size: f32, color: Color) -> SpriteBundle {
-    SpriteBundle {
-        transform: Transform {
-            translation: Vec3::new(0., window_height / 2. - unit_size / 2., 0.),
-            scale: Vec3::new(window_width, unit_size, 0.),
-            ..Default::default()
-        },
-        sprite: Sprite {
-            color,
-            ..Default::default()
-        },
-        ..Default::default()
-    }
-}
-
-pub fn create_bottom_wall_sprite(window_width: f32, window_height: f32, unit_size: f32, color: Color) -> SpriteBundle {
-    SpriteBundle {
-        transform: Transform {
-            translation: Vec3::new(0., -window_height / 2. + unit_size / 2., 0.),
-            scale: Vec3::new(window_width, unit_size, 0.),
-            ..Default::default()
-        },
-        sprite: Sprite {
-            color,
-            ..Default::default()
-        },
-        ..Default::default()
-    }
-}
-
-pub fn create_right_wall_sprite(window_width: f32, window_height: f32, unit_size: f32, color: Color) -> SpriteBundle {
-    SpriteBundle {
-        transform: Transform {
-            translation: Vec3::new(window_width / 2. - unit_size / 2., 0., 0.),
-            scale: Vec3::new(unit_size, window_height, 0.),
-            ..Default::default()
-        },
-        sprite: Sprite {
-            color,
-            ..Default::default()
-        },
-        ..Default::default()
-    }
-}
-
-pub fn create_left_paddle_sprite(window_width: f32, unit_size: f32, color: Color) -> SpriteBundle {
-    SpriteBundle {
-        transform: Transform {
-            translation: Vec3::new(-window_width / 2. + unit_size / 2. + unit_size, 0., 0.),
-            scale: Vec3::new(unit_size, unit_size * 4., 0.),
-            ..Default::default()
-        },
-        sprite: Sprite {
-            color,
-            ..Default::default()
-        },
-        ..Default::default()
-    }
-}
-
-pub fn create_right_paddle_sprite(window_width: f32, unit_size: f32, color: Color) -> SpriteBundle {
-    SpriteBundle {
-        transform: Transform {
-            translation: Vec3::new(window_width / 2. - unit_size / 2. - unit_size, 0., 0.),
-            scale: Vec3::new(unit_size, unit_size * 4., 0.),
-            ..Default::default()
-        },
-        sprite: Sprite {
-            color,
-            ..Default::default()
-        },
-        ..Default::default()
-    }
-}
-
-pub fn create_ball_sprite(unit_size: f32, position: Vec3, color: Color) -> SpriteBundle {
+pub fn create_net_sprite(pos_y: f32, unit_size: f32, color: Color) -> SpriteBundle {
     SpriteBundle {
         transform: Transform {
-            translation: Vec3::new(position.x, position.y, 0.),
-            scale: Vec3::new(unit_size, unit_size, 0.),
+            translation: Vec3::new(0., pos_y, 0.),
+            scale: Vec3::new(unit_size, unit_size * 2., 0.),
             ..Default::default()
         },
         sprite: Sprite {
@@ -90,11 +15,11 @@ pub fn create_ball_sprite(unit_size: f32, position: Vec3, color: Color) -> Sprit
     }
 }
 
-pub fn create_net_sprite(pos_y: f32, unit_size: f32, color: Color) -> SpriteBundle {
+pub fn create_rect_sprite(position: Vec2, size: Vec2, color: Color) -> SpriteBundle {
     SpriteBundle {
         transform: Transform {
-            translation: Vec3::new(0., pos_y, 0.),
-            scale: Vec3::new(unit_size, unit_size * 2., 0.),
+            translation: position.extend(0.),
+            scale: size.extend(0.),
             ..Default::default()
         },
         sprite: Sprite {
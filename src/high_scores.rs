@@ -0,0 +1,538 @@
+use bevy::prelude::*;
+use bevy::ui::UiSystem;
+use bevy::window::{PrimaryWindow, ReceivedCharacter};
+use serde::{Deserialize, Serialize};
+
+use crate::components::*;
+use crate::config::*;
+use crate::events::*;
+use crate::game_over::*;
+use crate::gamepad::*;
+use crate::navigation::*;
+use crate::pause::*;
+use crate::sim::*;
+use crate::storage::{self, Folder};
+use crate::systems_generic::*;
+use crate::state::*;
+
+const HIGH_SCORES_FILE: &str = "wall_scores";
+const HIGH_SCORES_MAX: usize = 10;
+const NAME_MAX_LENGTH: usize = 10;
+/// Name of a score confirmed without typing any.
+const DEFAULT_NAME: &str = "PLAYER";
+/// Top of the table on the Wall game-over panel, below its stats.
+pub const GAME_OVER_TABLE_TOP: f32 = 164.;
+
+/// Left offset from the window center and width of the rank, name, score, ball speed and date columns.
+const COLUMNS: [(f32, f32, JustifyContent); 5] = [
+    (-296., 48., JustifyContent::FlexEnd),
+    (-232., 208., JustifyContent::FlexStart),
+    (-24., 96., JustifyContent::FlexEnd),
+    (72., 96., JustifyContent::FlexEnd),
+    (168., 144., JustifyContent::FlexEnd),
+];
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HighScore {
+    pub name: String,
+    pub score: u32,
+    /// Fastest the ball went during the match, in pixels per second.
+    pub max_ball_speed: f32,
+    /// Unix time, in seconds.
+    pub date: u64,
+}
+
+/// Best Wall mode scores, highest first.
+#[derive(Default, Resource, Serialize, Deserialize)]
+pub struct HighScores {
+    pub entries: Vec<HighScore>,
+    /// Rank of the score of the last Wall match, if it made the table.
+    #[serde(skip)]
+    pub latest: Option<usize>,
+    /// Whether the name of the `latest` score is still being typed.
+    #[serde(skip)]
+    pub naming: bool,
+}
+
+impl HighScores {
+    pub fn load() -> Self {
+        storage::load::<HighScores>(Folder::Data, HIGH_SCORES_FILE).unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        if let Err(error) = storage::save(Folder::Data, HIGH_SCORES_FILE, self) {
+            warn!("Could not save the high scores: {}", error);
+        }
+    }
+
+    /// Adds a score if it makes the table, returns its rank.
+    pub fn insert(&mut self, entry: HighScore) -> Option<usize> {
+        if entry.score == 0 {
+            return None;
+        }
+
+        // Below the scores it ties with, they were there first
+        let rank = self.entries
+            .iter()
+            .position(|other| other.score < entry.score)
+            .unwrap_or(self.entries.len());
+        if rank >= HIGH_SCORES_MAX {
+            return None;
+        }
+
+        self.entries.insert(rank, entry);
+        self.entries.truncate(HIGH_SCORES_MAX);
+        Some(rank)
+    }
+
+    /// Name of the most recent score, offered again for the next one.
+    fn last_name(&self) -> String {
+        self.entries
+            .iter()
+            .max_by_key(|entry| entry.date)
+            .map(|entry| entry.name.clone())
+            .unwrap_or_default()
+    }
+
+    fn confirm_name(&mut self) {
+        self.naming = false;
+
+        if let Some(entry) = self.latest.and_then(|rank| self.entries.get_mut(rank)) {
+            if entry.name.trim().is_empty() {
+                entry.name = DEFAULT_NAME.to_string();
+            }
+        }
+
+        self.save();
+    }
+}
+
+#[derive(Component)]
+struct HighScoresEntity {}
+
+#[derive(Component)]
+struct HighScoresButton {}
+
+/// Name cell of the score being named, clicking it confirms the name.
+#[derive(Component)]
+struct NameEntryButton {}
+
+pub struct HighScoresPlugin;
+
+impl Plugin for HighScoresPlugin {
+    fn build(&self, app: &mut App) {
+        const GAME_STATE: GameState = GameState::HighScores;
+
+        app
+            .add_systems(Update, (
+                record_high_score_system,
+                setup_game_over_table_system,
+            ).chain().after(record_match_stats_system).run_if(in_state(GameState::ModeWall)).run_if(in_local_match))
+            .add_systems(Update, name_entry_system.after(record_high_score_system).run_if(in_state(GameState::ModeWall)))
+            .add_systems(OnExit(GameState::ModeWall), stop_naming_system)
+            .add_systems(OnEnter(GAME_STATE), setup_high_scores_system)
+            .add_systems(PreUpdate, button_navigation_system::<HighScoresButton>.after(UiSystem::Focus).run_if(in_state(GAME_STATE)))
+            .add_systems(Update, (
+                hover_high_scores_button_system,
+                click_high_scores_button_system,
+                back_to_menu_system,
+            ).run_if(in_state(GAME_STATE)))
+            .add_systems(OnExit(GAME_STATE), (
+                cleanup_entities::<HighScoresEntity>,
+                reset_cursor_system,
+            ));
+    }
+}
+
+pub fn naming_high_score(
+    high_scores: Res<HighScores>,
+) -> bool {
+    high_scores.naming
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn now_seconds() -> u64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs())
+}
+
+/// `SystemTime` panics in the browser.
+#[cfg(target_arch = "wasm32")]
+fn now_seconds() -> u64 {
+    (js_sys::Date::now() / 1000.) as u64
+}
+
+/// `YYYY-MM-DD` of a Unix time, in UTC.
+fn format_date(seconds: u64) -> String {
+    // Days to civil date, from Howard Hinnant's date algorithms
+    let z = (seconds / 86400) as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{}-{:02}-{:02}", year, month, day)
+}
+
+fn record_high_score_system(
+    mut match_events: EventReader<MatchEvent>,
+    mut high_scores: ResMut<HighScores>,
+    sim: Res<Sim>,
+    stats: Res<MatchStats>,
+) {
+    for event in match_events.read() {
+        let SimEvent::GameOver(_) = event.0 else {
+            continue;
+        };
+
+        let entry = HighScore {
+            name: high_scores.last_name(),
+            score: sim.0.left_score,
+            max_ball_speed: stats.fastest_ball_speed(),
+            date: now_seconds(),
+        };
+
+        // Saved right away, under the last name until another one is typed
+        high_scores.latest = high_scores.insert(entry);
+        high_scores.naming = high_scores.latest.is_some();
+        if high_scores.naming {
+            high_scores.save();
+        }
+    }
+}
+
+fn setup_game_over_table_system(
+    mut commands: Commands,
+    mut match_events: EventReader<MatchEvent>,
+    window: Query<&Window, With<PrimaryWindow>>,
+    high_scores: Res<HighScores>,
+    config: Res<Config>,
+) {
+    if !match_events.read().any(|event| matches!(event.0, SimEvent::GameOver(_))) {
+        return;
+    }
+
+    let window = window.get_single().unwrap();
+
+    // Above the backdrop of the game-over panel
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(100.),
+                height: Val::Percent(100.),
+                position_type: PositionType::Absolute,
+                ..Default::default()
+            },
+            z_index: ZIndex::Global(2),
+            ..Default::default()
+        })
+        .insert(GameModeEntity {})
+        .with_children(|parent| {
+            spawn_high_score_table(parent, &high_scores, GAME_OVER_TABLE_TOP, window, &config);
+        });
+}
+
+/// Header then a row per score, the latest one in yellow.
+fn spawn_high_score_table(
+    parent: &mut ChildBuilder,
+    high_scores: &HighScores,
+    top: f32,
+    window: &Window,
+    config: &Config,
+) {
+    let text_style = |font_size: f32, color: Color| TextStyle { font: config.font.clone(), font_size, color };
+
+    let header = ["#", "NAME", "SCORE", "SPEED", "DATE"].map(String::from);
+    spawn_row(parent, header, top, text_style(18., config.color_grey), false, window, config);
+
+    if high_scores.entries.is_empty() {
+        let mut cells: [String; 5] = Default::default();
+        cells[1] = "no score yet".to_string();
+        spawn_row(parent, cells, top + 24., text_style(20., config.color_grey), false, window, config);
+    }
+
+    for (rank, entry) in high_scores.entries.iter().enumerate() {
+        let latest = high_scores.latest == Some(rank);
+        let naming = latest && high_scores.naming;
+        let cells = [
+            format!("{}.", rank + 1),
+            if naming { format!("{}_", entry.name) } else { entry.name.clone() },
+            format!("{}", entry.score),
+            format!("{:.0}", entry.max_ball_speed),
+            format_date(entry.date),
+        ];
+        let color = if latest { config.color_yellow } else { config.color_white };
+
+        spawn_row(parent, cells, top + 24. + rank as f32 * 22., text_style(20., color), naming, window, config);
+    }
+}
+
+fn spawn_row(
+    parent: &mut ChildBuilder,
+    cells: [String; 5],
+    top: f32,
+    text_style: TextStyle,
+    naming: bool,
+    window: &Window,
+    config: &Config,
+) {
+    for (index, (text, (offset, width, justify_content))) in cells.into_iter().zip(COLUMNS).enumerate() {
+        let mut cell = parent.spawn(ButtonBundle {
+            style: Style {
+                width: Val::Px(width),
+                height: Val::Px(22.),
+                left: Val::Px(window.width() / 2. + offset),
+                top: Val::Px(top),
+                position_type: PositionType::Absolute,
+                justify_content,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            background_color: config.color_transparent.into(),
+            ..Default::default()
+        });
+
+        cell.with_children(|parent| {
+            parent.spawn(TextBundle {
+                text: Text::from_section(text, text_style.clone()),
+                ..Default::default()
+            });
+        });
+
+        if naming && index == 1 {
+            cell.insert(NameEntryButton {});
+        }
+    }
+}
+
+/// Letters and digits type the name, Enter, A or clicking it confirms.
+fn name_entry_system(
+    mut high_scores: ResMut<HighScores>,
+    mut characters: EventReader<ReceivedCharacter>,
+    name_query: Query<(Ref<Interaction>, &Children), With<NameEntryButton>>,
+    mut text_query: Query<&mut Text>,
+    keyboard: Res<Input<KeyCode>>,
+    gamepads: GamepadInputs,
+) {
+    // Keys typed while playing are no name
+    let Some(rank) = high_scores.latest.filter(|_| high_scores.naming) else {
+        characters.clear();
+        return;
+    };
+
+    let mut name = high_scores.entries[rank].name.clone();
+    for character in characters.read() {
+        if (character.char.is_ascii_alphanumeric() || character.char == ' ') && name.len() < NAME_MAX_LENGTH {
+            name.push(character.char.to_ascii_uppercase());
+        }
+    }
+    if keyboard.just_pressed(KeyCode::Back) {
+        name.pop();
+    }
+    high_scores.entries[rank].name = name.clone();
+
+    let clicked = name_query.iter().any(|(interaction, _)| interaction.is_changed() && *interaction == Interaction::Pressed);
+    let confirmed = clicked
+        || keyboard.any_just_pressed([KeyCode::Return, KeyCode::NumpadEnter])
+        || gamepads.any_just_pressed(GamepadButtonType::South);
+
+    if confirmed {
+        high_scores.confirm_name();
+    }
+
+    for (_, children) in name_query.iter() {
+        let mut text = text_query.get_mut(children[0]).unwrap();
+        text.sections[0].value = if high_scores.naming { format!("{}_", name) } else { high_scores.entries[rank].name.clone() };
+    }
+}
+
+/// Leaving the game over keeps the name typed so far.
+fn stop_naming_system(
+    mut high_scores: ResMut<HighScores>,
+) {
+    if high_scores.naming {
+        high_scores.confirm_name();
+    }
+}
+
+fn setup_high_scores_system(
+    mut commands: Commands,
+    window: Query<&Window, With<PrimaryWindow>>,
+    high_scores: Res<HighScores>,
+    config: Res<Config>,
+) {
+    let window = window.get_single().unwrap();
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(100.),
+                height: Val::Percent(100.),
+                position_type: PositionType::Absolute,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(HighScoresEntity {})
+        .with_children(|parent| {
+            // Title
+            parent
+                .spawn(ButtonBundle {
+                    style: Style {
+                        width: Val::Px(544.),
+                        height: Val::Px(96.),
+                        right: Val::Px((window.width() - 544.) / 2.),
+                        top: Val::Px(48.),
+                        position_type: PositionType::Absolute,
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    background_color: config.color_transparent.into(),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    parent.spawn(TextBundle {
+                        text: Text::from_section(
+                            "HIGH SCORES",
+                            TextStyle {
+                                font: config.font.clone(),
+                                font_size: 66.,
+                                color: config.color_white,
+                            },
+                        ),
+                        ..Default::default()
+                    });
+                });
+
+            spawn_high_score_table(parent, &high_scores, 160., window, &config);
+
+            // Back button
+            parent
+                .spawn(ButtonBundle {
+                    style: Style {
+                        width: Val::Px(256.),
+                        height: Val::Px(44.),
+                        right: Val::Px((window.width() - 256.) / 2.),
+                        top: Val::Px(448.),
+                        position_type: PositionType::Absolute,
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    background_color: config.color_transparent.into(),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    parent.spawn(TextBundle {
+                        text: Text::from_section(
+                            "Back",
+                            TextStyle {
+                                font: config.font.clone(),
+                                font_size: 36.,
+                                color: config.color_white,
+                            },
+                        ),
+                        ..Default::default()
+                    });
+                })
+                .insert(HighScoresButton {});
+        });
+}
+
+fn hover_high_scores_button_system(
+    mut window: Query<&mut Window, With<PrimaryWindow>>,
+    interaction_query: Query<(&Interaction, &Children), With<HighScoresButton>>,
+    mut text_query: Query<&mut Text>,
+    config: Res<Config>,
+) {
+    let mut window = window.get_single_mut().unwrap();
+    let mut hovered: bool = false;
+
+    for (interaction, children) in interaction_query.iter() {
+        let mut text = text_query.get_mut(children[0]).unwrap();
+        match *interaction {
+            Interaction::Hovered => {
+                text.sections[0].style.color = config.color_yellow;
+                hovered = true;
+            }
+            Interaction::None => {
+                text.sections[0].style.color = config.color_white;
+            }
+            _ => {}
+        }
+    }
+
+    window.cursor.icon = if hovered { CursorIcon::Hand } else { CursorIcon::Default };
+}
+
+fn click_high_scores_button_system(
+    mut state: ResMut<NextState<GameState>>,
+    interaction_query: Query<&Interaction, (Changed<Interaction>, With<HighScoresButton>)>,
+) {
+    if interaction_query.iter().any(|interaction| *interaction == Interaction::Pressed) {
+        state.set(GameState::Menu);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, score: u32) -> HighScore {
+        HighScore { name: name.to_string(), score, max_ball_speed: 500., date: 0 }
+    }
+
+    fn names(high_scores: &HighScores) -> Vec<&str> {
+        high_scores.entries.iter().map(|entry| entry.name.as_str()).collect()
+    }
+
+    #[test]
+    fn insert_keeps_the_top_ten() {
+        let mut high_scores = HighScores::default();
+        for score in 1..=12 {
+            high_scores.insert(entry(&score.to_string(), score));
+        }
+
+        let scores: Vec<u32> = high_scores.entries.iter().map(|entry| entry.score).collect();
+        assert_eq!(scores, vec![12, 11, 10, 9, 8, 7, 6, 5, 4, 3]);
+
+        // Too low for a full table
+        assert_eq!(high_scores.insert(entry("low", 3)), None);
+        assert_eq!(high_scores.insert(entry("high", 9)), Some(4));
+        assert_eq!(high_scores.entries.len(), HIGH_SCORES_MAX);
+        assert_eq!(high_scores.entries.last().unwrap().score, 4);
+    }
+
+    #[test]
+    fn ties_go_below_the_earlier_scores() {
+        let mut high_scores = HighScores::default();
+        high_scores.insert(entry("first", 5));
+        high_scores.insert(entry("better", 8));
+
+        assert_eq!(high_scores.insert(entry("second", 5)), Some(2));
+        assert_eq!(names(&high_scores), vec!["better", "first", "second"]);
+    }
+
+    #[test]
+    fn zero_is_not_a_score() {
+        let mut high_scores = HighScores::default();
+
+        assert_eq!(high_scores.insert(entry("none", 0)), None);
+        assert!(high_scores.entries.is_empty());
+    }
+
+    #[test]
+    fn format_date_in_utc() {
+        assert_eq!(format_date(0), "1970-01-01");
+        assert_eq!(format_date(946_684_799), "1999-12-31");
+        assert_eq!(format_date(951_868_799), "2000-02-29");
+        assert_eq!(format_date(1_709_251_199), "2024-02-29");
+        assert_eq!(format_date(4_107_628_799), "2100-03-01");
+    }
+}
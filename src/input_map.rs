@@ -0,0 +1,251 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::sim::Side;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    Serve,
+    Pause,
+    Back,
+}
+
+impl Action {
+    pub const ALL: [Action; 5] = [Action::MoveUp, Action::MoveDown, Action::Serve, Action::Pause, Action::Back];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Action::MoveUp => "move up",
+            Action::MoveDown => "move down",
+            Action::Serve => "serve",
+            Action::Pause => "pause",
+            Action::Back => "back",
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Player {
+    One,
+    Two,
+}
+
+impl Player {
+    pub const ALL: [Player; 2] = [Player::One, Player::Two];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Player::One => "player 1",
+            Player::Two => "player 2",
+        }
+    }
+}
+
+/// The left paddle is player 1's, the right one player 2's.
+/// In four players, the top one goes with the left and the bottom one with the right.
+impl From<Side> for Player {
+    fn from(side: Side) -> Self {
+        match side {
+            Side::Left | Side::Top => Player::One,
+            Side::Right | Side::Bottom => Player::Two,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct PlayerBindings {
+    pub move_up: KeyCode,
+    pub move_down: KeyCode,
+    pub serve: KeyCode,
+    pub pause: KeyCode,
+    pub back: KeyCode,
+}
+
+impl PlayerBindings {
+    pub fn get(&self, action: Action) -> KeyCode {
+        match action {
+            Action::MoveUp => self.move_up,
+            Action::MoveDown => self.move_down,
+            Action::Serve => self.serve,
+            Action::Pause => self.pause,
+            Action::Back => self.back,
+        }
+    }
+
+    fn get_mut(&mut self, action: Action) -> &mut KeyCode {
+        match action {
+            Action::MoveUp => &mut self.move_up,
+            Action::MoveDown => &mut self.move_down,
+            Action::Serve => &mut self.serve,
+            Action::Pause => &mut self.pause,
+            Action::Back => &mut self.back,
+        }
+    }
+}
+
+/// Keys of every action of both players and the mouse option, saved with the `Settings`.
+#[derive(Debug, Clone, Eq, PartialEq, Resource, Serialize, Deserialize)]
+pub struct KeyBindings {
+    pub one: PlayerBindings,
+    pub two: PlayerBindings,
+    /// Paddles follow the mouse cursor, and a click serves. Touch always works.
+    #[serde(default)]
+    pub follow_mouse: bool,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings {
+            one: PlayerBindings {
+                move_up: KeyCode::S,
+                move_down: KeyCode::X,
+                serve: KeyCode::Space,
+                pause: KeyCode::Return,
+                back: KeyCode::Escape,
+            },
+            two: PlayerBindings {
+                move_up: KeyCode::P,
+                move_down: KeyCode::L,
+                serve: KeyCode::Space,
+                pause: KeyCode::Return,
+                back: KeyCode::Escape,
+            },
+            follow_mouse: false,
+        }
+    }
+}
+
+/// Why a key could not be bound.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct BindingConflict {
+    pub key: KeyCode,
+    pub player: Player,
+    pub action: Action,
+}
+
+impl std::fmt::Display for BindingConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} is already {} of {}", key_label(self.key), self.action.label(), self.player.label())
+    }
+}
+
+impl KeyBindings {
+    pub fn player(&self, player: Player) -> &PlayerBindings {
+        match player {
+            Player::One => &self.one,
+            Player::Two => &self.two,
+        }
+    }
+
+    fn player_mut(&mut self, player: Player) -> &mut PlayerBindings {
+        match player {
+            Player::One => &mut self.one,
+            Player::Two => &mut self.two,
+        }
+    }
+
+    pub fn key(&self, player: Player, action: Action) -> KeyCode {
+        self.player(player).get(action)
+    }
+
+    /// Keys of an action, whoever it belongs to.
+    pub fn keys(&self, action: Action) -> impl Iterator<Item = KeyCode> + '_ {
+        Player::ALL.into_iter().map(move |player| self.key(player, action))
+    }
+
+    /// Binds `key` to the action of a player, unless it already triggers another action.
+    /// Both players may share a key for the same action, like serving with the spacebar.
+    pub fn bind(&mut self, player: Player, action: Action, key: KeyCode) -> Result<(), BindingConflict> {
+        if let Some(conflict) = self.conflict(action, key) {
+            return Err(conflict);
+        }
+
+        *self.player_mut(player).get_mut(action) = key;
+
+        Ok(())
+    }
+
+    fn conflict(&self, action: Action, key: KeyCode) -> Option<BindingConflict> {
+        Player::ALL.into_iter()
+            .flat_map(|other_player| Action::ALL.into_iter().map(move |other_action| (other_player, other_action)))
+            .find(|&(other_player, other_action)| other_action != action && self.key(other_player, other_action) == key)
+            .map(|(player, action)| BindingConflict { key, player, action })
+    }
+
+    pub fn conflicts(&self) -> impl Iterator<Item = BindingConflict> + '_ {
+        Player::ALL.into_iter()
+            .flat_map(|player| Action::ALL.into_iter().map(move |action| (player, action)))
+            .filter_map(|(player, action)| self.conflict(action, self.key(player, action)))
+    }
+
+    /// Instruction text for some actions of a player, e.g. "S or X".
+    pub fn label(&self, player: Player, actions: &[Action]) -> String {
+        actions.iter()
+            .map(|&action| key_label(self.key(player, action)))
+            .collect::<Vec<_>>()
+            .join(" or ")
+    }
+
+    /// Instruction text for an action of both players, the key once when they share it.
+    pub fn shared_label(&self, action: Action) -> String {
+        let mut labels: Vec<String> = self.keys(action).map(key_label).collect();
+        labels.dedup();
+        labels.join(" or ")
+    }
+}
+
+pub fn key_label(key: KeyCode) -> String {
+    match key {
+        KeyCode::Space => "SPACEBAR".to_string(),
+        KeyCode::Return => "ENTER".to_string(),
+        KeyCode::Escape => "ESC".to_string(),
+        KeyCode::Back => "BACKSPACE".to_string(),
+        _ => {
+            let name = format!("{:?}", key).to_uppercase();
+            // Digits are named Key0 to Key9
+            match name.strip_prefix("KEY") {
+                Some(digit) if digit.len() == 1 => digit.to_string(),
+                _ => name,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_of_another_action_is_refused() {
+        let mut bindings = KeyBindings::default();
+
+        let result = bindings.bind(Player::One, Action::MoveUp, KeyCode::L);
+
+        assert_eq!(result, Err(BindingConflict { key: KeyCode::L, player: Player::Two, action: Action::MoveDown }));
+        assert_eq!(bindings, KeyBindings::default());
+
+        // Also between two actions of the same player
+        assert!(bindings.bind(Player::Two, Action::Pause, KeyCode::P).is_err());
+    }
+
+    #[test]
+    fn players_may_share_a_key_for_the_same_action() {
+        let mut bindings = KeyBindings::default();
+
+        assert_eq!(bindings.bind(Player::One, Action::Pause, KeyCode::Tab), Ok(()));
+        assert_eq!(bindings.bind(Player::Two, Action::Pause, KeyCode::Tab), Ok(()));
+
+        assert_eq!(bindings.keys(Action::Pause).collect::<Vec<_>>(), vec![KeyCode::Tab, KeyCode::Tab]);
+        assert_eq!(bindings.shared_label(Action::Pause), key_label(KeyCode::Tab));
+    }
+
+    #[test]
+    fn rebinding_the_same_key_is_allowed() {
+        let mut bindings = KeyBindings::default();
+
+        assert_eq!(bindings.bind(Player::One, Action::MoveUp, KeyCode::S), Ok(()));
+        assert_eq!(bindings, KeyBindings::default());
+    }
+}
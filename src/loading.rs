@@ -0,0 +1,230 @@
+use bevy::asset::LoadState;
+use bevy::prelude::*;
+
+use crate::config::*;
+use crate::components::*;
+use crate::state::*;
+
+/// Seconds a toast stays on screen.
+const TOAST_SECONDS: f32 = 3.;
+
+/// Handle of the tuning file, kept so the asset stays loaded and is watched for changes.
+#[derive(Resource)]
+pub struct ConfigHandle(pub Handle<ConfigAsset>);
+
+/// Short message at the top of the screen, whatever the current state.
+#[derive(Component)]
+struct Toast {
+    timer: Timer,
+}
+
+pub struct LoadingPlugin;
+
+impl Plugin for LoadingPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_asset::<ConfigAsset>()
+            .init_asset_loader::<ConfigLoader>()
+            .init_asset::<ArenaAsset>()
+            .init_asset_loader::<ArenaLoader>()
+            .init_resource::<StartupState>()
+            .add_systems(Startup, load_config_system)
+            .add_systems(Update, apply_config_system.run_if(in_state(GameState::Loading)))
+            .add_systems(Update, (
+                reload_config_system,
+                expire_toasts_system,
+            ).run_if(not(in_state(GameState::Loading))));
+    }
+}
+
+fn load_config_system(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+) {
+    commands.insert_resource(ConfigHandle(asset_server.load(CONFIG_PATH)));
+}
+
+fn apply_config_system(
+    mut commands: Commands,
+    mut config: ResMut<Config>,
+    mut state: ResMut<NextState<GameState>>,
+    config_handle: Res<ConfigHandle>,
+    config_assets: Res<Assets<ConfigAsset>>,
+    startup_state: Res<StartupState>,
+    asset_server: Res<AssetServer>,
+) {
+    let file = match asset_server.get_load_state(&config_handle.0) {
+        Some(LoadState::Loaded) => match config_assets.get(&config_handle.0).map(|asset| &asset.0) {
+            Some(Ok(file)) => file.clone(),
+            Some(Err(error)) => {
+                error!("Using the default config, {} is invalid: {}", CONFIG_PATH, error);
+                ConfigFile::default()
+            }
+            None => return,
+        },
+        // The loader already logged why, the game still starts with the defaults
+        Some(LoadState::Failed) => {
+            warn!("Using the default config, {} could not be loaded", CONFIG_PATH);
+            ConfigFile::default()
+        }
+        _ => return,
+    };
+
+    config.apply(&file, &asset_server);
+    commands.insert_resource(Time::<Fixed>::from_hz(config.game_fixed_hz));
+
+    state.set(startup_state.0.clone());
+}
+
+/// Applies the tuning file again whenever it changes on disk (with the `hot_reload` feature).
+fn reload_config_system(
+    mut commands: Commands,
+    mut asset_events: EventReader<AssetEvent<ConfigAsset>>,
+    mut config: ResMut<Config>,
+    mut sprite_query: Query<&mut Sprite>,
+    mut text_query: Query<&mut Text>,
+    mut background_query: Query<&mut BackgroundColor>,
+    mut sim: Option<ResMut<Sim>>,
+    playback: Option<Res<ReplayPlayback>>,
+    recorder: Option<Res<MatchRecorder>>,
+    config_handle: Res<ConfigHandle>,
+    config_assets: Res<Assets<ConfigAsset>>,
+    state: Res<State<GameState>>,
+    asset_server: Res<AssetServer>,
+) {
+    for event in asset_events.read() {
+        if !event.is_modified(config_handle.0.id()) {
+            continue;
+        }
+
+        let Some(asset) = config_assets.get(&config_handle.0) else {
+            continue;
+        };
+
+        let file = match &asset.0 {
+            Ok(file) => file,
+            Err(error) => {
+                warn!("Config not reloaded, {} is invalid: {}", CONFIG_PATH, error);
+                spawn_toast(&mut commands, &config, format!("config error: {}", error), config.color_red);
+                continue;
+            }
+        };
+
+        let previous_colors = [
+            config.color_transparent,
+            config.color_white,
+            config.color_grey,
+            config.color_yellow,
+            config.color_green,
+            config.color_red,
+        ];
+        let previous_font = config.font.clone();
+        let previous_fixed_hz = config.game_fixed_hz;
+
+        config.apply(file, &asset_server);
+
+        // Everything on screen was drawn with the previous palette
+        let palette: Vec<(Color, Color)> = previous_colors.into_iter().zip([
+            config.color_transparent,
+            config.color_white,
+            config.color_grey,
+            config.color_yellow,
+            config.color_green,
+            config.color_red,
+        ]).collect();
+
+        for mut sprite in sprite_query.iter_mut() {
+            recolor(&mut sprite.color, &palette);
+        }
+
+        for mut background in background_query.iter_mut() {
+            recolor(&mut background.0, &palette);
+        }
+
+        for mut text in text_query.iter_mut() {
+            for section in text.sections.iter_mut() {
+                recolor(&mut section.style.color, &palette);
+                if section.style.font == previous_font {
+                    section.style.font = config.font.clone();
+                }
+            }
+        }
+
+        let mut message = "config reloaded".to_string();
+
+        // Matches that must step exactly like elsewhere keep their tuning and tick rate,
+        // leaving them goes back to the rate of the config
+        let deterministic = playback.is_some() || *state.get() == GameState::Online;
+        let fixed_hz_changed = config.game_fixed_hz != previous_fixed_hz;
+        if deterministic {
+            if sim.is_some() {
+                message += ", speeds apply next match";
+            }
+            if fixed_hz_changed {
+                message += ", tick rate applies next match";
+            }
+        } else {
+            let mut retuned = false;
+
+            if fixed_hz_changed {
+                commands.insert_resource(Time::<Fixed>::from_hz(config.game_fixed_hz));
+                retuned = true;
+            }
+
+            if let Some(sim) = sim.as_mut() {
+                let settings = config.sim_settings(state.get(), sim.0.settings.court_size);
+                sim.0.retune(&settings);
+                retuned = true;
+            }
+
+            if retuned && recorder.is_some() {
+                // Its settings no longer match, it would not play back the same
+                commands.remove_resource::<MatchRecorder>();
+                message += ", replay discarded";
+            }
+        }
+
+        info!("Config reloaded from {}", CONFIG_PATH);
+        spawn_toast(&mut commands, &config, message, config.color_green);
+    }
+}
+
+fn recolor(color: &mut Color, palette: &[(Color, Color)]) {
+    if let Some((_, new_color)) = palette.iter().find(|(old_color, _)| old_color == color) {
+        *color = *new_color;
+    }
+}
+
+pub fn spawn_toast(commands: &mut Commands, config: &Config, message: String, color: Color) {
+    commands
+        .spawn(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(config.sprite_unit_size * 2.),
+                right: Val::Px(config.sprite_unit_size * 2.),
+                ..Default::default()
+            },
+            text: Text::from_section(
+                message,
+                TextStyle {
+                    font: config.font.clone(),
+                    font_size: 18.,
+                    color,
+                },
+            ),
+            ..Default::default()
+        })
+        .insert(Toast { timer: Timer::from_seconds(TOAST_SECONDS, TimerMode::Once) });
+}
+
+fn expire_toasts_system(
+    mut commands: Commands,
+    mut toast_query: Query<(Entity, &mut Toast)>,
+    time: Res<Time<Real>>,
+) {
+    for (entity, mut toast) in toast_query.iter_mut() {
+        if toast.timer.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
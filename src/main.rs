@@ -1,72 +1,113 @@
 mod config;
+mod collision;
+mod arena;
+mod sim;
+mod ai;
+mod controller;
+mod input_map;
+mod gamepad;
+mod pointer;
+mod navigation;
+mod pause;
+mod game_over;
+mod high_scores;
+mod storage;
+mod settings;
 mod systems_generic;
 mod systems_1v1;
 mod components;
 mod events;
 mod state;
 mod helpers_sprite;
+mod loading;
 mod menu;
+mod controls;
+mod options;
+mod match_setup;
 mod mode_1p;
 mod mode_2p;
 mod mode_wall;
+mod mode_4p;
+mod mode_2v2;
+mod mode_watch;
+mod replay;
+#[cfg(not(target_arch = "wasm32"))]
+mod mode_replay;
+#[cfg(not(target_arch = "wasm32"))]
+mod netcode;
+#[cfg(not(target_arch = "wasm32"))]
+mod mode_online;
 
 use bevy::app::{App, PluginGroup};
-use bevy::asset::AssetServer;
 use bevy::DefaultPlugins;
-use bevy::prelude::{Camera2dBundle, ClearColor, Color, Commands, Res, ResMut, Startup, WindowPlugin};
+use bevy::prelude::{Camera2dBundle, ClearColor, Color, Commands, ResMut, Startup, WindowPlugin};
 use bevy::window::{ExitCondition, Window, WindowMode};
 use bevy_kira_audio::AudioPlugin;
 use config::*;
+use components::{AiDifficulty, WatchDifficulties};
+use high_scores::HighScores;
+use settings::Settings;
 
+use crate::loading::LoadingPlugin;
 use crate::menu::MenuPlugin;
+use crate::controls::ControlsPlugin;
+use crate::options::OptionsPlugin;
+use crate::match_setup::MatchSetupPlugin;
+use crate::settings::SettingsPlugin;
+use crate::gamepad::GamepadPlugin;
+use crate::pause::PausePlugin;
+use crate::game_over::GameOverPlugin;
+use crate::high_scores::HighScoresPlugin;
 use crate::mode_1p::Mode1PPlugin;
 use crate::mode_2p::Mode2PPlugin;
 use crate::mode_wall::ModeWallPlugin;
+use crate::mode_4p::Mode4PPlugin;
+use crate::mode_2v2::Mode2v2Plugin;
+use crate::mode_watch::ModeWatchPlugin;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::mode_replay::ReplayPlugin;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::mode_online::OnlinePlugin;
 use crate::state::GameState;
 
 fn setup_system(
     mut commands: Commands,
     mut config: ResMut<Config>,
-    asset_server: Res<AssetServer>,
 ) {
     commands.spawn(Camera2dBundle::default());
 
-    config.game_paddle_speed = 400.;
-    config.game_ball_speed_min = 500.;
-    config.game_ball_speed_max = 750.;
-    config.game_ball_speed_incr = 5.;
-    config.game_ball_oob_x = 200.;
-    config.game_1v1_score_to_win = 9;
-
-    config.sprite_unit_size = 16.;
-
-    //config.color_transparent = Color::rgba_u8(0, 128, 0, 64); // DEBUG
-    config.color_transparent = Color::rgba_u8(0, 0, 0, 0);
-    config.color_white = Color::WHITE;
-    config.color_grey = Color::rgb_u8(100, 100, 100);
-    config.color_yellow = Color::rgb_u8(221, 173, 29);
-    config.color_green = Color::rgb_u8(69, 183, 130);
-    config.color_red = Color::rgb_u8(196, 89, 73);
+    // Everything else comes from the config file, see `LoadingPlugin`
+    config.game_seed = seed_from_args();
+}
 
-    config.font = asset_server.load("fonts/Volter__28Goldfish_29.ttf");
+/// Reads `--seed <u64>` from the command line, to replay a reported match.
+fn seed_from_args() -> Option<u64> {
+    let args: Vec<String> = std::env::args().collect();
 
-    config.audio_paddle_left = asset_server.load("sounds/left.wav");
-    config.audio_paddle_right = asset_server.load("sounds/right.wav");
-    config.audio_wall = asset_server.load("sounds/wall.wav");
+    args.iter()
+        .position(|arg| arg == "--seed")
+        .and_then(|index| args.get(index + 1))
+        .and_then(|seed| seed.parse().ok())
 }
 
 fn main() {
-    // TODO fixed update
+    let mut app = App::new();
+    let settings = Settings::load();
 
-    App::new()
+    app
         .insert_resource(ClearColor(Color::BLACK))
         .init_resource::<Config>()
+        .insert_resource(AiDifficulty(settings.ai_difficulty))
+        .init_resource::<WatchDifficulties>()
+        .insert_resource(settings.bindings.clone())
+        .insert_resource(settings)
+        .insert_resource(HighScores::load())
         .add_systems(Startup, setup_system)
         .add_plugins(DefaultPlugins
             .set(WindowPlugin {
                 primary_window: Some(Window {
                     title: "Pong".to_string(),
-                    resolution: (768., 576.).into(),
+                    resolution: (COURT_SIZE.x, COURT_SIZE.y).into(),
                     resizable: false,
                     mode: WindowMode::Windowed,
                     ..Default::default()
@@ -76,10 +117,28 @@ fn main() {
             })
         )
         .add_plugins(AudioPlugin)
+        .add_plugins(LoadingPlugin)
+        .add_plugins(GamepadPlugin)
         .add_plugins(MenuPlugin)
+        .add_plugins(ControlsPlugin)
+        .add_plugins(SettingsPlugin)
+        .add_plugins(OptionsPlugin)
+        .add_plugins(MatchSetupPlugin)
+        .add_plugins(PausePlugin)
+        .add_plugins(GameOverPlugin)
+        .add_plugins(HighScoresPlugin)
         .add_plugins(Mode1PPlugin)
         .add_plugins(Mode2PPlugin)
         .add_plugins(ModeWallPlugin)
-        .add_state::<GameState>()
-        .run();
+        .add_plugins(Mode4PPlugin)
+        .add_plugins(Mode2v2Plugin)
+        .add_plugins(ModeWatchPlugin)
+        .add_state::<GameState>();
+
+    #[cfg(not(target_arch = "wasm32"))]
+    app
+        .add_plugins(ReplayPlugin)
+        .add_plugins(OnlinePlugin);
+
+    app.run();
 }
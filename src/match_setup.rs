@@ -0,0 +1,538 @@
+use bevy::ecs::system::EntityCommands;
+use bevy::prelude::*;
+use bevy::ui::UiSystem;
+use bevy::window::PrimaryWindow;
+
+use crate::config::*;
+use crate::input_map::*;
+use crate::navigation::*;
+use crate::pause::*;
+use crate::settings::*;
+use crate::sim::*;
+use crate::systems_generic::*;
+use crate::state::*;
+
+/// Rules are listed from here, one row each.
+const ROWS_TOP: f32 = 112.;
+const ROW_STEP: f32 = 34.;
+/// Power-up toggles are laid out below the rules, three per line.
+const POWER_UPS_TOP: f32 = 352.;
+const POWER_UP_STEP: f32 = 28.;
+
+#[derive(Component)]
+struct MatchSetupEntity {}
+
+#[derive(Component)]
+struct MatchSetupButton {}
+
+#[derive(Component, Copy, Clone)]
+enum MatchSetupAction {
+    /// Moves a rule by a step, back for a negative one.
+    Change(RuleRow, i32),
+    Start,
+    Back,
+}
+
+/// Rule set on the screen, its value text is refreshed whenever the settings change.
+#[derive(Component, Copy, Clone, PartialEq)]
+enum RuleRow {
+    ScoreToWin,
+    WinByTwo,
+    BestOf,
+    Serve,
+    ServeTimeLimit,
+    MultiBall,
+    Lives,
+    /// Control of the paddle of a side, in four players.
+    Seat(Side),
+    /// Control of a paddle of a team, in doubles.
+    TeamSeat(Side, PaddleLine),
+    /// Whether a power-up may appear, in versus.
+    PowerUp(PowerUpKind),
+    /// Court layout, in 1 and 2 players.
+    Arena,
+    Summary,
+}
+
+impl RuleRow {
+    fn rows(mode: &GameState) -> Vec<RuleRow> {
+        match mode {
+            GameState::Mode4P => vec![
+                RuleRow::Lives,
+                RuleRow::Seat(Side::Left),
+                RuleRow::Seat(Side::Right),
+                RuleRow::Seat(Side::Top),
+                RuleRow::Seat(Side::Bottom),
+            ],
+            // The other rules are the ones of 2 players, shown in the summary
+            GameState::Mode2v2 => vec![
+                RuleRow::ScoreToWin,
+                RuleRow::MultiBall,
+                RuleRow::TeamSeat(Side::Left, PaddleLine::Back),
+                RuleRow::TeamSeat(Side::Left, PaddleLine::Forward),
+                RuleRow::TeamSeat(Side::Right, PaddleLine::Back),
+                RuleRow::TeamSeat(Side::Right, PaddleLine::Forward),
+            ],
+            _ => vec![
+                RuleRow::ScoreToWin,
+                RuleRow::WinByTwo,
+                RuleRow::BestOf,
+                RuleRow::Serve,
+                RuleRow::ServeTimeLimit,
+                RuleRow::MultiBall,
+                RuleRow::Arena,
+            ],
+        }
+    }
+
+    /// Counts are lowered and raised with arrows on both sides, the other rules cycle when pressed.
+    fn is_count(self) -> bool {
+        matches!(self, RuleRow::ScoreToWin | RuleRow::Lives)
+    }
+
+    fn label(self) -> String {
+        match self {
+            RuleRow::ScoreToWin => "Score to win".to_string(),
+            RuleRow::WinByTwo => "Win by two".to_string(),
+            RuleRow::BestOf => "Sets".to_string(),
+            RuleRow::Serve => "Serve".to_string(),
+            RuleRow::ServeTimeLimit => "Time to serve".to_string(),
+            RuleRow::MultiBall => "Multi-ball".to_string(),
+            RuleRow::Arena => "Arena".to_string(),
+            RuleRow::Lives => "Lives".to_string(),
+            RuleRow::Seat(side) => {
+                let mut label = format!("{} paddle", side.label());
+                label[..1].make_ascii_uppercase();
+                label
+            }
+            RuleRow::TeamSeat(side, line) => {
+                let mut label = format!("{} {}", side.label(), line.label());
+                label[..1].make_ascii_uppercase();
+                label
+            }
+            RuleRow::PowerUp(_) | RuleRow::Summary => String::new(),
+        }
+    }
+
+    fn text(self, settings: &Settings, mode: &GameState, score_to_win: u32) -> String {
+        let rules = &settings.match_rules;
+        match self {
+            RuleRow::ScoreToWin => format!("{}", score_to_win),
+            RuleRow::WinByTwo => if rules.win_by_two { "on" } else { "off" }.to_string(),
+            RuleRow::BestOf => match rules.best_of {
+                1 => "single set".to_string(),
+                best_of => format!("best of {}", best_of),
+            },
+            RuleRow::Serve => match rules.serve {
+                ServeRule::Winner => "winner".to_string(),
+                ServeRule::Loser => "loser".to_string(),
+                ServeRule::Alternate { points } => format!("alternate {}", points),
+            },
+            RuleRow::ServeTimeLimit => match rules.serve_time_limit {
+                Some(serve_time_limit) => format!("{}s", serve_time_limit),
+                None => "off".to_string(),
+            },
+            RuleRow::MultiBall => match rules.multi_ball {
+                Some(hits) => format!("every {} hits", hits),
+                None => "off".to_string(),
+            },
+            RuleRow::Lives => format!("{}", rules.lives),
+            RuleRow::Seat(side) => settings.four_players.seat(side).label().to_string(),
+            RuleRow::TeamSeat(side, line) => settings.doubles.seat(side, line).label().to_string(),
+            RuleRow::PowerUp(kind) => {
+                format!("{} {}", kind.label(), if rules.power_ups.contains(kind) { "on" } else { "off" })
+            }
+            RuleRow::Arena => settings.arena.clone().unwrap_or_else(|| "classic".to_string()),
+            RuleRow::Summary => match mode {
+                GameState::Mode4P => format!("{} lives each, the last side standing wins", rules.lives),
+                _ => rules.describe(score_to_win),
+            },
+        }
+    }
+
+    /// `arenas` are the names of the arenas loaded, after the classic court.
+    fn change(self, settings: &mut Settings, score_to_win: u32, arenas: &[String], step: i32) {
+        let rules = &mut settings.match_rules;
+        match self {
+            RuleRow::ScoreToWin => {
+                settings.score_to_win = Some(score_to_win.saturating_add_signed(step).clamp(1, MAX_SCORE_TO_WIN));
+            }
+            RuleRow::WinByTwo => rules.win_by_two = !rules.win_by_two,
+            RuleRow::BestOf => {
+                let index = BEST_OF_CHOICES.iter().position(|&best_of| best_of == rules.best_of).unwrap_or(0);
+                rules.best_of = BEST_OF_CHOICES[(index + 1) % BEST_OF_CHOICES.len()];
+            }
+            RuleRow::Serve => {
+                let index = SERVE_RULE_CHOICES.iter().position(|&serve| serve == rules.serve).unwrap_or(0);
+                rules.serve = SERVE_RULE_CHOICES[(index + 1) % SERVE_RULE_CHOICES.len()];
+            }
+            RuleRow::ServeTimeLimit => {
+                let index = SERVE_TIME_LIMIT_CHOICES.iter().position(|&limit| limit == rules.serve_time_limit).unwrap_or(0);
+                rules.serve_time_limit = SERVE_TIME_LIMIT_CHOICES[(index + 1) % SERVE_TIME_LIMIT_CHOICES.len()];
+            }
+            RuleRow::MultiBall => {
+                let index = MULTI_BALL_CHOICES.iter().position(|&hits| hits == rules.multi_ball).unwrap_or(0);
+                rules.multi_ball = MULTI_BALL_CHOICES[(index + 1) % MULTI_BALL_CHOICES.len()];
+            }
+            RuleRow::Lives => rules.lives = rules.lives.saturating_add_signed(step).clamp(1, MAX_LIVES),
+            RuleRow::Seat(side) => settings.four_players.next_seat(side),
+            RuleRow::TeamSeat(side, line) => settings.doubles.next_seat(side, line),
+            RuleRow::PowerUp(kind) => rules.power_ups.toggle(kind),
+            RuleRow::Arena => {
+                let index = arenas.iter().position(|name| Some(name) == settings.arena.as_ref()).map_or(0, |index| index + 1);
+                settings.arena = arenas.get(index).cloned();
+            }
+            RuleRow::Summary => {}
+        }
+    }
+}
+
+pub struct MatchSetupPlugin;
+
+impl Plugin for MatchSetupPlugin {
+    fn build(&self, app: &mut App) {
+        const GAME_STATE: GameState = GameState::MatchSetup;
+
+        app
+            .add_systems(OnEnter(GAME_STATE), setup_match_setup_system)
+            .add_systems(PreUpdate, button_navigation_system::<MatchSetupButton>.after(UiSystem::Focus).run_if(in_state(GAME_STATE)))
+            .add_systems(Update, (
+                hover_match_setup_buttons_system,
+                click_match_setup_buttons_system,
+                update_rule_values_system.after(click_match_setup_buttons_system),
+                back_to_menu_system,
+            ).run_if(in_state(GAME_STATE)))
+            .add_systems(OnExit(GAME_STATE), (
+                cleanup_entities::<MatchSetupEntity>,
+                reset_cursor_system,
+            ));
+    }
+}
+
+/// Points to win a set of the mode, the saved setting or else the config file.
+fn score_to_win(settings: &Settings, mode: &MatchSetupMode, config: &Config) -> u32 {
+    settings.score_to_win.unwrap_or_else(|| config.sim_settings(&mode.0, Vec2::ZERO).score_to_win)
+}
+
+/// Transparent button holding a single text.
+fn spawn_text_button<'w, 's, 'a>(
+    parent: &'a mut ChildBuilder<'w, 's, '_>,
+    style: Style,
+    text: String,
+    text_style: TextStyle,
+    config: &Config,
+) -> EntityCommands<'w, 's, 'a> {
+    let mut button = parent.spawn(ButtonBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            align_items: AlignItems::Center,
+            ..style
+        },
+        background_color: config.color_transparent.into(),
+        ..Default::default()
+    });
+
+    button.with_children(|parent| {
+        parent.spawn(TextBundle {
+            text: Text::from_section(text, text_style),
+            ..Default::default()
+        });
+    });
+
+    button
+}
+
+fn setup_match_setup_system(
+    mut commands: Commands,
+    window: Query<&Window, With<PrimaryWindow>>,
+    settings: Res<Settings>,
+    mode: Res<MatchSetupMode>,
+    bindings: Res<KeyBindings>,
+    config: Res<Config>,
+) {
+    let window = window.get_single().unwrap();
+    let score_to_win = score_to_win(&settings, &mode, &config);
+
+    let text_style = |font_size: f32, color: Color| TextStyle {
+        font: config.font.clone(),
+        font_size,
+        color,
+    };
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(100.),
+                height: Val::Percent(100.),
+                position_type: PositionType::Absolute,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(MatchSetupEntity {})
+        .with_children(|parent| {
+            // Title
+            spawn_text_button(
+                parent,
+                Style {
+                    width: Val::Px(544.),
+                    height: Val::Px(80.),
+                    right: Val::Px((window.width() - 544.) / 2.),
+                    top: Val::Px(24.),
+                    justify_content: JustifyContent::Center,
+                    ..Default::default()
+                },
+                "MATCH RULES".to_string(),
+                text_style(66., config.color_white),
+                &config,
+            );
+
+            let value_left = window.width() / 2. + 16.;
+            for (index, row) in RuleRow::rows(&mode.0).into_iter().enumerate() {
+                let top = ROWS_TOP + index as f32 * ROW_STEP;
+
+                // Label
+                spawn_text_button(
+                    parent,
+                    Style {
+                        width: Val::Px(window.width() / 2. - 16.),
+                        height: Val::Px(ROW_STEP),
+                        left: Val::Px(0.),
+                        top: Val::Px(top),
+                        justify_content: JustifyContent::FlexEnd,
+                        ..Default::default()
+                    },
+                    row.label(),
+                    text_style(30., config.color_yellow),
+                    &config,
+                );
+
+                let value = row.text(&settings, &mode.0, score_to_win);
+                if !row.is_count() {
+                    spawn_text_button(
+                        parent,
+                        Style {
+                            width: Val::Px(window.width() / 2. - 16.),
+                            height: Val::Px(ROW_STEP),
+                            left: Val::Px(value_left),
+                            top: Val::Px(top),
+                            justify_content: JustifyContent::FlexStart,
+                            ..Default::default()
+                        },
+                        value,
+                        text_style(30., config.color_white),
+                        &config,
+                    )
+                    .insert(MatchSetupButton {})
+                    .insert(MatchSetupAction::Change(row, 1))
+                    .insert(row);
+                    continue;
+                }
+
+                for (step, text, left) in [(-1, "<", value_left), (1, ">", value_left + 116.)] {
+                    spawn_text_button(
+                        parent,
+                        Style {
+                            width: Val::Px(44.),
+                            height: Val::Px(ROW_STEP),
+                            left: Val::Px(left),
+                            top: Val::Px(top),
+                            justify_content: JustifyContent::Center,
+                            ..Default::default()
+                        },
+                        text.to_string(),
+                        text_style(30., config.color_white),
+                        &config,
+                    )
+                    .insert(MatchSetupButton {})
+                    .insert(MatchSetupAction::Change(row, step));
+                }
+
+                spawn_text_button(
+                    parent,
+                    Style {
+                        width: Val::Px(72.),
+                        height: Val::Px(ROW_STEP),
+                        left: Val::Px(value_left + 44.),
+                        top: Val::Px(top),
+                        justify_content: JustifyContent::Center,
+                        ..Default::default()
+                    },
+                    value,
+                    text_style(30., config.color_white),
+                    &config,
+                )
+                .insert(row);
+            }
+
+            // Power-ups, after a label taking the first place of the grid
+            if mode.0 != GameState::Mode4P {
+                let column_width = window.width() / 3.;
+
+                spawn_text_button(
+                    parent,
+                    Style {
+                        width: Val::Px(column_width),
+                        height: Val::Px(POWER_UP_STEP),
+                        left: Val::Px(0.),
+                        top: Val::Px(POWER_UPS_TOP),
+                        justify_content: JustifyContent::Center,
+                        ..Default::default()
+                    },
+                    "Power-ups".to_string(),
+                    text_style(21., config.color_yellow),
+                    &config,
+                );
+
+                for (index, kind) in PowerUpKind::ALL.into_iter().enumerate() {
+                    let row = RuleRow::PowerUp(kind);
+                    let place = index + 1;
+
+                    spawn_text_button(
+                        parent,
+                        Style {
+                            width: Val::Px(column_width),
+                            height: Val::Px(POWER_UP_STEP),
+                            left: Val::Px((place % 3) as f32 * column_width),
+                            top: Val::Px(POWER_UPS_TOP + (place / 3) as f32 * POWER_UP_STEP),
+                            justify_content: JustifyContent::Center,
+                            ..Default::default()
+                        },
+                        row.text(&settings, &mode.0, score_to_win),
+                        text_style(21., config.color_white),
+                        &config,
+                    )
+                    .insert(MatchSetupButton {})
+                    .insert(MatchSetupAction::Change(row, 1))
+                    .insert(row);
+                }
+            }
+
+            spawn_text_button(
+                parent,
+                Style {
+                    width: Val::Px(window.width()),
+                    height: Val::Px(32.),
+                    left: Val::Px(0.),
+                    top: Val::Px(440.),
+                    justify_content: JustifyContent::Center,
+                    ..Default::default()
+                },
+                RuleRow::Summary.text(&settings, &mode.0, score_to_win),
+                text_style(18., config.color_grey),
+                &config,
+            )
+            .insert(RuleRow::Summary);
+
+            // Side by side, to leave room for the power-ups
+            for (index, (action, text)) in [
+                (MatchSetupAction::Start, "Start"),
+                (MatchSetupAction::Back, "Back"),
+            ].into_iter().enumerate() {
+                spawn_text_button(
+                    parent,
+                    Style {
+                        width: Val::Px(256.),
+                        height: Val::Px(44.),
+                        left: Val::Px(window.width() / 2. - 256. + index as f32 * 256.),
+                        top: Val::Px(472.),
+                        justify_content: JustifyContent::Center,
+                        ..Default::default()
+                    },
+                    text.to_string(),
+                    text_style(36., config.color_white),
+                    &config,
+                )
+                .insert(MatchSetupButton {})
+                .insert(action);
+            }
+
+            // Keys
+            spawn_text_button(
+                parent,
+                Style {
+                    width: Val::Px(window.width()),
+                    height: Val::Px(32.),
+                    left: Val::Px(0.),
+                    top: Val::Px(524.),
+                    justify_content: JustifyContent::Center,
+                    ..Default::default()
+                },
+                format!(
+                    "UP/DOWN and {} to change, {} to go back",
+                    bindings.shared_label(Action::Serve),
+                    bindings.shared_label(Action::Back),
+                ),
+                text_style(18., config.color_grey),
+                &config,
+            );
+        });
+}
+
+fn hover_match_setup_buttons_system(
+    mut window: Query<&mut Window, With<PrimaryWindow>>,
+    interaction_query: Query<(&Interaction, &Children), With<MatchSetupButton>>,
+    mut text_query: Query<&mut Text>,
+    config: Res<Config>,
+) {
+    let mut window = window.get_single_mut().unwrap();
+    let mut hovered: bool = false;
+
+    for (interaction, children) in interaction_query.iter() {
+        let mut text = text_query.get_mut(children[0]).unwrap();
+        match *interaction {
+            Interaction::Hovered => {
+                text.sections[0].style.color = config.color_yellow;
+                hovered = true;
+            }
+            Interaction::None => {
+                text.sections[0].style.color = config.color_white;
+            }
+            _ => {}
+        }
+    }
+
+    window.cursor.icon = if hovered { CursorIcon::Hand } else { CursorIcon::Default };
+}
+
+fn click_match_setup_buttons_system(
+    mut state: ResMut<NextState<GameState>>,
+    mut settings: ResMut<Settings>,
+    interaction_query: Query<(&Interaction, &MatchSetupAction), Changed<Interaction>>,
+    mode: Res<MatchSetupMode>,
+    config: Res<Config>,
+    arena_assets: Res<Assets<ArenaAsset>>,
+) {
+    for (interaction, action) in interaction_query.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+
+        match *action {
+            MatchSetupAction::Change(row, step) => {
+                let score_to_win = score_to_win(&settings, &mode, &config);
+                let arenas: Vec<String> = config.loaded_arenas(&arena_assets).map(|arena| arena.name.clone()).collect();
+                row.change(&mut settings, score_to_win, &arenas, step);
+            }
+            MatchSetupAction::Start => state.set(mode.0.clone()),
+            MatchSetupAction::Back => state.set(GameState::Menu),
+        }
+    }
+}
+
+fn update_rule_values_system(
+    row_query: Query<(&RuleRow, &Children)>,
+    mut text_query: Query<&mut Text>,
+    settings: Res<Settings>,
+    mode: Res<MatchSetupMode>,
+    config: Res<Config>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+
+    let score_to_win = score_to_win(&settings, &mode, &config);
+    for (row, children) in row_query.iter() {
+        text_query.get_mut(children[0]).unwrap().sections[0].value = row.text(&settings, &mode.0, score_to_win);
+    }
+}
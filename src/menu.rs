@@ -1,15 +1,26 @@
 use bevy::prelude::*;
+use bevy::ui::UiSystem;
 
 #[cfg(not(target_arch = "wasm32"))]
 use bevy::app::*;
 use bevy::window::PrimaryWindow;
+use rand::random;
 
+use crate::ai::*;
+use crate::components::*;
 use crate::config::*;
+use crate::controller::*;
+use crate::events::*;
+use crate::navigation::*;
 use crate::systems_generic::*;
+use crate::sim::*;
 use crate::state::*;
 use crate::helpers_sprite::*;
 
-#[derive(Component)]
+/// Level of both AIs playing in the menu background.
+const ATTRACT_DIFFICULTY: Difficulty = Difficulty::Hard;
+
+#[derive(Component, Clone)]
 struct MenuEntity {}
 
 #[derive(Component)]
@@ -18,12 +29,49 @@ struct MenuButton {}
 #[derive(Component)]
 struct MenuButton1Player {}
 
+#[derive(Component)]
+struct MenuButtonDifficulty {}
+
 #[derive(Component)]
 struct MenuButton2Players {}
 
+/// Four players, next to the 2 players button.
+#[derive(Component)]
+struct MenuButton4Players {}
+
+/// Doubles, on the other side of the 2 players button.
+#[derive(Component)]
+struct MenuButton2v2 {}
+
 #[derive(Component)]
 struct MenuButtonWallMode {}
 
+/// High scores, next to the Wall mode button.
+#[derive(Component)]
+struct MenuButtonHighScores {}
+
+#[derive(Component)]
+struct MenuButtonWatch {}
+
+/// AI level selector of one side of Watch mode.
+#[derive(Component)]
+struct MenuButtonWatchDifficulty {
+    side: Side,
+}
+
+#[derive(Component)]
+struct MenuButtonReplays {}
+
+/// Online play, next to the Replays button.
+#[derive(Component)]
+struct MenuButtonOnline {}
+
+#[derive(Component)]
+struct MenuButtonControls {}
+
+#[derive(Component)]
+struct MenuButtonOptions {}
+
 #[derive(Component)]
 struct MenuButtonQuit {}
 
@@ -34,25 +82,46 @@ impl Plugin for MenuPlugin {
         const GAME_STATE: GameState = GameState::Menu;
 
         app
+            .add_event::<MatchEvent>()
             .add_systems(OnEnter(GAME_STATE), (
                 setup_background_system,
                 setup_title_system,
                 setup_copyright_system,
                 setup_buttons_system
             ))
+            .add_systems(PreUpdate, button_navigation_system::<MenuButton>.after(UiSystem::Focus).run_if(in_state(GAME_STATE)))
             .add_systems(Update, (
                 hover_buttons_system,
                 click_1_player_button_system,
+                click_difficulty_button_system,
                 click_2_players_button_system,
-                click_wall_mode_button_system
+                click_4_players_button_system,
+                click_2v2_button_system,
+                click_wall_mode_button_system,
+                click_high_scores_button_system,
+                click_watch_button_system,
+                click_watch_difficulty_button_system,
+                click_controls_button_system,
+                click_options_button_system,
+            ).run_if(in_state(GAME_STATE)))
+            .add_systems(FixedUpdate, drive_paddles_system.in_set(SimSet::Input).run_if(in_state(GAME_STATE)))
+            .add_systems(FixedUpdate, step_sim_system.in_set(SimSet::Step).after(SimSet::Input).run_if(in_state(GAME_STATE)))
+            .add_systems(Update, (
+                sync_transforms_system,
+                restart_attract_system,
             ).run_if(in_state(GAME_STATE)))
             .add_systems(OnExit(GAME_STATE), cleanup_entities::<MenuEntity>);
 
         #[cfg(not(target_arch = "wasm32"))]
-        app.add_systems(Update, click_quit_button_system.run_if(in_state(GAME_STATE)));
+        app.add_systems(Update, (
+            click_replays_button_system,
+            click_online_button_system,
+            click_quit_button_system
+        ).run_if(in_state(GAME_STATE)));
     }
 }
 
+/// Attract mode: two AIs rally behind the menu, in grey.
 fn setup_background_system(
     mut commands: Commands,
     window: Query<&Window, With<PrimaryWindow>>,
@@ -62,21 +131,20 @@ fn setup_background_system(
     let color = config.color_grey;
     let unit_size = config.sprite_unit_size;
 
-    commands
-        .spawn(create_top_wall_sprite(window.width(), window.height(), unit_size, color))
-        .insert(MenuEntity {});
+    let seed = random();
+    let sim = PongSim::new(config.sim_settings(&GameState::Menu, Vec2::new(window.width(), window.height())), SimMode::Versus, seed);
 
-    commands
-        .spawn(create_bottom_wall_sprite(window.width(), window.height(), unit_size, color))
-        .insert(MenuEntity {});
+    spawn_body_sprites(&mut commands, &sim, color, color, MenuEntity {});
 
-    commands
-        .spawn(create_left_paddle_sprite(window.width(), unit_size, color))
-        .insert(MenuEntity {});
+    commands.insert_resource(GameRng::new(seed));
+    commands.insert_resource(Sim(sim));
+    commands.insert_resource(PendingInputs::default());
 
-    commands
-        .spawn(create_right_paddle_sprite(window.width(), unit_size, color))
-        .insert(MenuEntity {});
+    for side in [Side::Left, Side::Right] {
+        commands
+            .spawn(PaddleController { side, line: PaddleLine::Back, kind: ControllerKind::Ai(PaddleAi::new(ATTRACT_DIFFICULTY)) })
+            .insert(MenuEntity {});
+    }
 
     // Net
     {
@@ -99,6 +167,21 @@ fn setup_background_system(
     }
 }
 
+/// Starts another rally once the background match is over, it never ends.
+fn restart_attract_system(
+    mut commands: Commands,
+    mut match_events: EventReader<MatchEvent>,
+    mut sim: ResMut<Sim>,
+) {
+    for event in match_events.read() {
+        if let SimEvent::GameOver(_) = event.0 {
+            let seed = random();
+            sim.0 = PongSim::new(sim.0.settings.clone(), SimMode::Versus, seed);
+            commands.insert_resource(GameRng::new(seed));
+        }
+    }
+}
+
 fn setup_title_system(
     mut commands: Commands,
     window: Query<&Window, With<PrimaryWindow>>,
@@ -166,6 +249,8 @@ fn setup_copyright_system(
 fn setup_buttons_system(
     mut commands: Commands,
     window: Query<&Window, With<PrimaryWindow>>,
+    ai_difficulty: Res<AiDifficulty>,
+    watch_difficulties: Res<WatchDifficulties>,
     config: Res<Config>,
 ) {
     let window = window.get_single().unwrap();
@@ -175,9 +260,9 @@ fn setup_buttons_system(
         .spawn(ButtonBundle {
             style: Style {
                 width: Val::Px(256.),
-                height: Val::Px(48.),
+                height: Val::Px(44.),
                 right: Val::Px((window.width() - 256.) / 2.),
-                top: Val::Px(272.),
+                top: Val::Px(260.),
                 position_type: PositionType::Absolute,
                 justify_content: JustifyContent::Center,
                 align_items: AlignItems::Center,
@@ -203,14 +288,47 @@ fn setup_buttons_system(
         .insert(MenuButton {})
         .insert(MenuButton1Player {});
 
+    // AI difficulty selector, next to the 1 player button
+    commands
+        .spawn(ButtonBundle {
+            style: Style {
+                width: Val::Px(128.),
+                height: Val::Px(44.),
+                left: Val::Px(window.width() / 2. + 128.),
+                top: Val::Px(260.),
+                position_type: PositionType::Absolute,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            background_color: config.color_transparent.into(),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent.spawn(TextBundle {
+                text: Text::from_section(
+                    ai_difficulty.0.label(),
+                    TextStyle {
+                        font: config.font.clone(),
+                        font_size: 24.,
+                        color: config.color_white,
+                    },
+                ),
+                ..Default::default()
+            });
+        })
+        .insert(MenuEntity {})
+        .insert(MenuButton {})
+        .insert(MenuButtonDifficulty {});
+
     // 2 players button
     commands
         .spawn(ButtonBundle {
             style: Style {
                 width: Val::Px(256.),
-                height: Val::Px(48.),
+                height: Val::Px(44.),
                 right: Val::Px((window.width() - 256.) / 2.),
-                top: Val::Px(336.),
+                top: Val::Px(304.),
                 position_type: PositionType::Absolute,
                 justify_content: JustifyContent::Center,
                 align_items: AlignItems::Center,
@@ -236,14 +354,80 @@ fn setup_buttons_system(
         .insert(MenuButton {})
         .insert(MenuButton2Players {});
 
+    // 4 players button, next to the 2 players button
+    commands
+        .spawn(ButtonBundle {
+            style: Style {
+                width: Val::Px(128.),
+                height: Val::Px(44.),
+                left: Val::Px(window.width() / 2. + 128.),
+                top: Val::Px(304.),
+                position_type: PositionType::Absolute,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            background_color: config.color_transparent.into(),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent.spawn(TextBundle {
+                text: Text::from_section(
+                    "4 players",
+                    TextStyle {
+                        font: config.font.clone(),
+                        font_size: 24.,
+                        color: config.color_white,
+                    },
+                ),
+                ..Default::default()
+            });
+        })
+        .insert(MenuEntity {})
+        .insert(MenuButton {})
+        .insert(MenuButton4Players {});
+
+    // 2 vs 2 button, on the other side of the 2 players button
+    commands
+        .spawn(ButtonBundle {
+            style: Style {
+                width: Val::Px(128.),
+                height: Val::Px(44.),
+                left: Val::Px(window.width() / 2. - 256.),
+                top: Val::Px(304.),
+                position_type: PositionType::Absolute,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            background_color: config.color_transparent.into(),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent.spawn(TextBundle {
+                text: Text::from_section(
+                    "2 vs 2",
+                    TextStyle {
+                        font: config.font.clone(),
+                        font_size: 24.,
+                        color: config.color_white,
+                    },
+                ),
+                ..Default::default()
+            });
+        })
+        .insert(MenuEntity {})
+        .insert(MenuButton {})
+        .insert(MenuButton2v2 {});
+
     // Wall mode button
     commands
         .spawn(ButtonBundle {
             style: Style {
                 width: Val::Px(256.),
-                height: Val::Px(48.),
+                height: Val::Px(44.),
                 right: Val::Px((window.width() - 256.) / 2.),
-                top: Val::Px(400.),
+                top: Val::Px(348.),
                 position_type: PositionType::Absolute,
                 justify_content: JustifyContent::Center,
                 align_items: AlignItems::Center,
@@ -269,15 +453,285 @@ fn setup_buttons_system(
         .insert(MenuButton {})
         .insert(MenuButtonWallMode {});
 
+    // High scores button, next to the Wall mode button
+    commands
+        .spawn(ButtonBundle {
+            style: Style {
+                width: Val::Px(128.),
+                height: Val::Px(44.),
+                left: Val::Px(window.width() / 2. + 128.),
+                top: Val::Px(348.),
+                position_type: PositionType::Absolute,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            background_color: config.color_transparent.into(),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent.spawn(TextBundle {
+                text: Text::from_section(
+                    "Scores",
+                    TextStyle {
+                        font: config.font.clone(),
+                        font_size: 24.,
+                        color: config.color_white,
+                    },
+                ),
+                ..Default::default()
+            });
+        })
+        .insert(MenuEntity {})
+        .insert(MenuButton {})
+        .insert(MenuButtonHighScores {});
+
+    // Watch button
+    commands
+        .spawn(ButtonBundle {
+            style: Style {
+                width: Val::Px(256.),
+                height: Val::Px(44.),
+                right: Val::Px((window.width() - 256.) / 2.),
+                top: Val::Px(392.),
+                position_type: PositionType::Absolute,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            background_color: config.color_transparent.into(),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent.spawn(TextBundle {
+                text: Text::from_section(
+                    "Watch",
+                    TextStyle {
+                        font: config.font.clone(),
+                        font_size: 36.,
+                        color: config.color_white,
+                    },
+                ),
+                ..Default::default()
+            });
+        })
+        .insert(MenuEntity {})
+        .insert(MenuButton {})
+        .insert(MenuButtonWatch {});
+
+    // Watch AI difficulty selectors, on the side of the paddle they drive
+    for (side, difficulty, left) in [
+        (Side::Left, watch_difficulties.left, window.width() / 2. - 256.),
+        (Side::Right, watch_difficulties.right, window.width() / 2. + 128.),
+    ] {
+        commands
+            .spawn(ButtonBundle {
+                style: Style {
+                    width: Val::Px(128.),
+                    height: Val::Px(44.),
+                    left: Val::Px(left),
+                    top: Val::Px(392.),
+                    position_type: PositionType::Absolute,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..Default::default()
+                },
+                background_color: config.color_transparent.into(),
+                ..Default::default()
+            })
+            .with_children(|parent| {
+                parent.spawn(TextBundle {
+                    text: Text::from_section(
+                        difficulty.label(),
+                        TextStyle {
+                            font: config.font.clone(),
+                            font_size: 24.,
+                            color: config.color_white,
+                        },
+                    ),
+                    ..Default::default()
+                });
+            })
+            .insert(MenuEntity {})
+            .insert(MenuButton {})
+            .insert(MenuButtonWatchDifficulty { side });
+    }
+
+    // Controls button, out of the way like the copyright
+    commands
+        .spawn(ButtonBundle {
+            style: Style {
+                width: Val::Px(160.),
+                height: Val::Px(18.),
+                left: Val::Px(config.sprite_unit_size),
+                bottom: Val::Px(config.sprite_unit_size * 2.),
+                position_type: PositionType::Absolute,
+                justify_content: JustifyContent::FlexStart,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            background_color: config.color_transparent.into(),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent.spawn(TextBundle {
+                text: Text::from_section(
+                    "Controls",
+                    TextStyle {
+                        font: config.font.clone(),
+                        font_size: 18.,
+                        color: config.color_white,
+                    },
+                ),
+                ..Default::default()
+            });
+        })
+        .insert(MenuEntity {})
+        .insert(MenuButton {})
+        .insert(MenuButtonControls {});
+
+    // Options button, above the controls one
+    commands
+        .spawn(ButtonBundle {
+            style: Style {
+                width: Val::Px(160.),
+                height: Val::Px(18.),
+                left: Val::Px(config.sprite_unit_size),
+                bottom: Val::Px(config.sprite_unit_size * 2. + 24.),
+                position_type: PositionType::Absolute,
+                justify_content: JustifyContent::FlexStart,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            background_color: config.color_transparent.into(),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent.spawn(TextBundle {
+                text: Text::from_section(
+                    "Options",
+                    TextStyle {
+                        font: config.font.clone(),
+                        font_size: 18.,
+                        color: config.color_white,
+                    },
+                ),
+                ..Default::default()
+            });
+        })
+        .insert(MenuEntity {})
+        .insert(MenuButton {})
+        .insert(MenuButtonOptions {});
+
+    // Replays button
+    #[cfg(not(target_arch = "wasm32"))]
+    commands
+        .spawn(ButtonBundle {
+            style: Style {
+                width: Val::Px(256.),
+                height: Val::Px(44.),
+                right: Val::Px((window.width() - 256.) / 2.),
+                top: Val::Px(436.),
+                position_type: PositionType::Absolute,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            background_color: config.color_transparent.into(),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent.spawn(TextBundle {
+                text: Text::from_section(
+                    "Replays",
+                    TextStyle {
+                        font: config.font.clone(),
+                        font_size: 36.,
+                        color: config.color_white,
+                    },
+                ),
+                ..Default::default()
+            });
+        })
+        .insert(MenuEntity {})
+        .insert(MenuButton {})
+        .insert(MenuButtonReplays {});
+
+    // Online button, next to the Replays button
+    #[cfg(not(target_arch = "wasm32"))]
+    commands
+        .spawn(ButtonBundle {
+            style: Style {
+                width: Val::Px(128.),
+                height: Val::Px(44.),
+                left: Val::Px(window.width() / 2. + 128.),
+                top: Val::Px(436.),
+                position_type: PositionType::Absolute,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            background_color: config.color_transparent.into(),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent.spawn(TextBundle {
+                text: Text::from_section(
+                    "Online",
+                    TextStyle {
+                        font: config.font.clone(),
+                        font_size: 24.,
+                        color: config.color_white,
+                    },
+                ),
+                ..Default::default()
+            });
+        })
+        .insert(MenuEntity {})
+        .insert(MenuButton {})
+        .insert(MenuButtonOnline {});
+
+    // In place of the replays button, the web build does not record matches
+    #[cfg(target_arch = "wasm32")]
+    commands
+        .spawn(ButtonBundle {
+            style: Style {
+                width: Val::Px(256.),
+                height: Val::Px(44.),
+                right: Val::Px((window.width() - 256.) / 2.),
+                top: Val::Px(436.),
+                position_type: PositionType::Absolute,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            background_color: config.color_transparent.into(),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent.spawn(TextBundle {
+                text: Text::from_section(
+                    "Replays: desktop only",
+                    TextStyle {
+                        font: config.font.clone(),
+                        font_size: 18.,
+                        color: config.color_grey,
+                    },
+                ),
+                ..Default::default()
+            });
+        })
+        .insert(MenuEntity {});
+
     // Quit button
     #[cfg(not(target_arch = "wasm32"))]
     commands
         .spawn(ButtonBundle {
             style: Style {
                 width: Val::Px(256.),
-                height: Val::Px(48.),
+                height: Val::Px(44.),
                 right: Val::Px((window.width() - 256.) / 2.),
-                top: Val::Px(464.),
+                top: Val::Px(480.),
                 position_type: PositionType::Absolute,
                 justify_content: JustifyContent::Center,
                 align_items: AlignItems::Center,
@@ -331,29 +785,68 @@ fn hover_buttons_system(
 }
 
 fn click_1_player_button_system(
+    mut commands: Commands,
     mut state: ResMut<NextState<GameState>>,
     interaction_query: Query<&Interaction, With<MenuButton1Player>>,
 ) {
     match *interaction_query.single() {
         Interaction::Pressed => {
-            state.set(GameState::Mode1P);
+            commands.insert_resource(MatchSetupMode(GameState::Mode1P));
+            state.set(GameState::MatchSetup);
         }
         _ => {}
     }
 }
 
+fn click_difficulty_button_system(
+    mut ai_difficulty: ResMut<AiDifficulty>,
+    interaction_query: Query<(&Interaction, &Children), (Changed<Interaction>, With<MenuButtonDifficulty>)>,
+    mut text_query: Query<&mut Text>,
+) {
+    for (interaction, children) in interaction_query.iter() {
+        if *interaction == Interaction::Pressed {
+            ai_difficulty.0 = ai_difficulty.0.next();
+            text_query.get_mut(children[0]).unwrap().sections[0].value = ai_difficulty.0.label().to_string();
+        }
+    }
+}
+
 fn click_2_players_button_system(
+    mut commands: Commands,
     mut state: ResMut<NextState<GameState>>,
     interaction_query: Query<&Interaction, With<MenuButton2Players>>,
 ) {
     match *interaction_query.single() {
         Interaction::Pressed => {
-            state.set(GameState::Mode2P);
+            commands.insert_resource(MatchSetupMode(GameState::Mode2P));
+            state.set(GameState::MatchSetup);
         }
         _ => {}
     }
 }
 
+fn click_4_players_button_system(
+    mut commands: Commands,
+    mut state: ResMut<NextState<GameState>>,
+    interaction_query: Query<&Interaction, With<MenuButton4Players>>,
+) {
+    if *interaction_query.single() == Interaction::Pressed {
+        commands.insert_resource(MatchSetupMode(GameState::Mode4P));
+        state.set(GameState::MatchSetup);
+    }
+}
+
+fn click_2v2_button_system(
+    mut commands: Commands,
+    mut state: ResMut<NextState<GameState>>,
+    interaction_query: Query<&Interaction, With<MenuButton2v2>>,
+) {
+    if *interaction_query.single() == Interaction::Pressed {
+        commands.insert_resource(MatchSetupMode(GameState::Mode2v2));
+        state.set(GameState::MatchSetup);
+    }
+}
+
 fn click_wall_mode_button_system(
     mut state: ResMut<NextState<GameState>>,
     interaction_query: Query<&Interaction, With<MenuButtonWallMode>>,
@@ -366,6 +859,91 @@ fn click_wall_mode_button_system(
     }
 }
 
+fn click_high_scores_button_system(
+    mut state: ResMut<NextState<GameState>>,
+    interaction_query: Query<&Interaction, With<MenuButtonHighScores>>,
+) {
+    if *interaction_query.single() == Interaction::Pressed {
+        state.set(GameState::HighScores);
+    }
+}
+
+fn click_watch_button_system(
+    mut state: ResMut<NextState<GameState>>,
+    interaction_query: Query<&Interaction, With<MenuButtonWatch>>,
+) {
+    match *interaction_query.single() {
+        Interaction::Pressed => {
+            state.set(GameState::Watch);
+        }
+        _ => {}
+    }
+}
+
+fn click_watch_difficulty_button_system(
+    mut watch_difficulties: ResMut<WatchDifficulties>,
+    interaction_query: Query<(&Interaction, &Children, &MenuButtonWatchDifficulty), Changed<Interaction>>,
+    mut text_query: Query<&mut Text>,
+) {
+    for (interaction, children, button) in interaction_query.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+
+        let difficulty = match button.side {
+            Side::Left => &mut watch_difficulties.left,
+            Side::Right => &mut watch_difficulties.right,
+            Side::Top | Side::Bottom => continue,
+        };
+        *difficulty = difficulty.next();
+        text_query.get_mut(children[0]).unwrap().sections[0].value = difficulty.label().to_string();
+    }
+}
+
+fn click_options_button_system(
+    mut state: ResMut<NextState<GameState>>,
+    interaction_query: Query<&Interaction, With<MenuButtonOptions>>,
+) {
+    if *interaction_query.single() == Interaction::Pressed {
+        state.set(GameState::Options);
+    }
+}
+
+fn click_controls_button_system(
+    mut state: ResMut<NextState<GameState>>,
+    interaction_query: Query<&Interaction, With<MenuButtonControls>>,
+) {
+    match *interaction_query.single() {
+        Interaction::Pressed => {
+            state.set(GameState::Controls);
+        }
+        _ => {}
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn click_replays_button_system(
+    mut state: ResMut<NextState<GameState>>,
+    interaction_query: Query<&Interaction, With<MenuButtonReplays>>,
+) {
+    match *interaction_query.single() {
+        Interaction::Pressed => {
+            state.set(GameState::Replays);
+        }
+        _ => {}
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn click_online_button_system(
+    mut state: ResMut<NextState<GameState>>,
+    interaction_query: Query<&Interaction, With<MenuButtonOnline>>,
+) {
+    if *interaction_query.single() == Interaction::Pressed {
+        state.set(GameState::Online);
+    }
+}
+
 #[cfg(not(target_arch = "wasm32"))]
 fn click_quit_button_system(
     mut app_exit_events: EventWriter<AppExit>,
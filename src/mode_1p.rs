@@ -1,25 +1,20 @@
 use bevy::prelude::*;
-use bevy::window::PrimaryWindow;
-use rand::*;
 
+use crate::ai::*;
 use crate::config::*;
+use crate::controller::*;
 use crate::systems_generic::*;
 use crate::systems_1v1::*;
 use crate::components::*;
 use crate::events::*;
+use crate::input_map::*;
 use crate::state::*;
 
 pub struct Mode1PPlugin;
 
 #[derive(Debug, Eq, PartialEq, Clone, Hash, SystemSet)]
 enum Set {
-    ResetGameData,
-    SetupPaddle,
-    MoveBall,
-    Move,
-    IncrementScore,
-    CheckBallOut,
-    CheckGameOver,
+    SetupSim,
     Back,
 }
 
@@ -28,42 +23,48 @@ impl Plugin for Mode1PPlugin {
         const GAME_STATE: GameState = GameState::Mode1P;
 
         app
-            .init_resource::<GameData>()
-            .add_event::<GameOverEvent>()
-            .add_event::<BallOutEvent>()
-            .add_event::<BallHitPaddleEvent>()
+            .add_event::<MatchEvent>()
             .add_systems(OnEnter(GAME_STATE), (
-                reset_game_data_system.in_set(Set::ResetGameData),
+                setup_sim_system.in_set(Set::SetupSim),
+                setup_controllers_system,
                 setup_court_system,
-                setup_scores_system,
+                setup_scores_system.after(Set::SetupSim),
+                setup_power_ups_system.after(Set::SetupSim),
                 setup_instructions_system,
-                setup_left_paddle_system.in_set(Set::SetupPaddle).after(Set::ResetGameData),
-                setup_right_paddle_system.in_set(Set::SetupPaddle).after(Set::ResetGameData),
-                setup_ball_system.after(Set::SetupPaddle)
+                setup_rules_instruction_system.after(Set::SetupSim),
+                setup_bodies_system.after(Set::SetupSim),
+                start_recording_system.after(Set::SetupSim)
             ))
+            .add_systems(FixedUpdate, drive_paddles_system.in_set(SimSet::Input).run_if(in_state(GAME_STATE)))
+            .add_systems(FixedUpdate, step_sim_system.in_set(SimSet::Step).after(SimSet::Input).run_if(in_state(GAME_STATE)))
+            .add_systems(Update, latch_serve_system.run_if(in_state(GAME_STATE)).run_if(in_state(PauseState::Running)))
             .add_systems(Update, (
-                service_system,
-                launch_ball_system,
-                move_left_paddle_with_keyboard_system,
-                move_ball_system.in_set(Set::MoveBall),
-                move_right_paddle_with_ai_system.after(Set::MoveBall)
-            ).run_if(in_state(GAME_STATE)).in_set(Set::Move).before(Set::Back))
-            .add_systems(
-                Update, (
-                    check_ball_collision_system,
-                    check_ball_out_system.in_set(Set::CheckBallOut),
-                    increment_score_system.in_set(Set::IncrementScore).after(Set::CheckBallOut),
-                    check_game_over_system.in_set(Set::CheckGameOver).after(Set::IncrementScore),
-                    game_over_system.after(Set::CheckGameOver),
-                ).run_if(in_state(GAME_STATE)).after(Set::Move).before(Set::Back))
-            .add_systems(Update, back_to_menu_system.in_set(Set::Back).run_if(in_state(GAME_STATE)))
+                sync_transforms_system,
+                sync_obstacles_system,
+                sync_ball_views_system,
+                sync_power_up_views_system,
+                update_scores_system,
+                update_set_counters_system,
+                update_power_ups_system,
+                play_sounds_system,
+                hide_instructions_system,
+                game_over_system,
+                show_seed_system,
+            ).run_if(in_state(GAME_STATE)).before(Set::Back))
+            // Outside playback the back key pauses the match, see `PausePlugin`
+            .add_systems(Update, back_to_menu_system.in_set(Set::Back).run_if(in_state(GAME_STATE)).run_if(resource_exists::<ReplayPlayback>()))
             .add_systems(OnExit(GAME_STATE), cleanup_entities::<GameModeEntity>);
+
+        #[cfg(not(target_arch = "wasm32"))]
+        app.add_systems(OnExit(GAME_STATE), save_replay_system);
     }
 }
 
 fn setup_instructions_system(
     mut commands: Commands,
+    ai_difficulty: Res<AiDifficulty>,
     config: Res<Config>,
+    bindings: Res<KeyBindings>,
 ) {
     // Goal label
     commands
@@ -116,7 +117,7 @@ fn setup_instructions_system(
         .with_children(|parent| {
             parent.spawn(TextBundle {
                 text: Text::from_section(
-                    "beat the AI!",
+                    format!("beat the {} AI!", ai_difficulty.0.label().to_lowercase()),
                     TextStyle {
                         font: config.font.clone(),
                         font_size: 30.,
@@ -148,7 +149,7 @@ fn setup_instructions_system(
         .with_children(|parent| {
             parent.spawn(TextBundle {
                 text: Text::from_section(
-                    "S or X",
+                    bindings.label(Player::One, &[Action::MoveUp, Action::MoveDown]),
                     TextStyle {
                         font: config.font.clone(),
                         font_size: 30.,
@@ -212,7 +213,7 @@ fn setup_instructions_system(
         .with_children(|parent| {
             parent.spawn(TextBundle {
                 text: Text::from_section(
-                    "SPACEBAR",
+                    bindings.label(Player::One, &[Action::Serve]),
                     TextStyle {
                         font: config.font.clone(),
                         font_size: 30.,
@@ -258,41 +259,14 @@ fn setup_instructions_system(
         .insert(Instruction {});
 }
 
-fn move_right_paddle_with_ai_system(
-    mut paddle_query: Query<(&mut RightPaddle, &mut Transform), Without<Ball>>,
-    ball_query: Query<(&Ball, &Transform)>,
-    window: Query<&Window, With<PrimaryWindow>>,
-    time: Res<Time>,
-    config: Res<Config>,
+fn setup_controllers_system(
+    mut commands: Commands,
+    playback: Option<Res<ReplayPlayback>>,
+    ai_difficulty: Res<AiDifficulty>,
+    bindings: Res<KeyBindings>,
 ) {
-    let (ball, ball_transform) = ball_query.single();
-    let (mut paddle_entity, mut paddle_transform) = paddle_query.single_mut();
-
-    if ball.velocity.x <= 0. {
-        paddle_entity.velocity.y = 0.;
-        return;
-    }
-
-    let window = window.get_single().unwrap();
-
-    if ball_transform.translation.x > window.width() / 2. {
-        paddle_entity.velocity.y = 0.;
-        return;
-    }
-
-    let ball_y = ball_transform.translation.y;
-    let paddle_translation_y = paddle_transform.translation.y;
-    let dist: f32 = (paddle_translation_y - ball_y).abs();
-    let rnd = random::<u32>() % (paddle_transform.scale.y / 2.) as u32 + 1;
-
-    if dist < rnd as f32 {
-        paddle_entity.velocity.y = 0.;
-        return;
-    }
-
-    let dir = if ball_y > paddle_translation_y { config.game_paddle_speed } else { -config.game_paddle_speed };
-    let bound_y = window.height() / 2. - config.sprite_unit_size - paddle_transform.scale.y / 2.;
-
-    paddle_transform.translation.y += dir * time.delta_seconds();
-    paddle_transform.translation.y = paddle_transform.translation.y.min(bound_y).max(-bound_y);
+    spawn_controllers(&mut commands, playback.is_some(), [
+        (Side::Left, ControllerKind::Human(HumanControls::for_player(&bindings, Player::One))),
+        (Side::Right, ControllerKind::Ai(PaddleAi::new(ai_difficulty.0))),
+    ]);
 }
@@ -0,0 +1,183 @@
+use bevy::prelude::*;
+
+use crate::ai::*;
+use crate::config::*;
+use crate::controller::*;
+use crate::systems_generic::*;
+use crate::systems_1v1::*;
+use crate::components::*;
+use crate::events::*;
+use crate::input_map::*;
+use crate::pointer::*;
+use crate::settings::*;
+use crate::state::*;
+use crate::sim::*;
+
+pub struct Mode2v2Plugin;
+
+#[derive(Debug, Eq, PartialEq, Clone, Hash, SystemSet)]
+enum Set {
+    SetupSim,
+    Back,
+}
+
+impl Plugin for Mode2v2Plugin {
+    fn build(&self, app: &mut App) {
+        const GAME_STATE: GameState = GameState::Mode2v2;
+
+        app
+            .add_event::<MatchEvent>()
+            .add_systems(OnEnter(GAME_STATE), (
+                setup_sim_system.in_set(Set::SetupSim),
+                setup_controllers_system,
+                setup_court_system,
+                setup_scores_system.after(Set::SetupSim),
+                setup_power_ups_system.after(Set::SetupSim),
+                setup_instructions_system,
+                setup_rules_instruction_system.after(Set::SetupSim),
+                setup_bodies_system.after(Set::SetupSim),
+                start_recording_system.after(Set::SetupSim)
+            ))
+            .add_systems(FixedUpdate, drive_paddles_system.in_set(SimSet::Input).run_if(in_state(GAME_STATE)))
+            .add_systems(FixedUpdate, step_sim_system.in_set(SimSet::Step).after(SimSet::Input).run_if(in_state(GAME_STATE)))
+            .add_systems(Update, latch_serve_system.run_if(in_state(GAME_STATE)).run_if(in_state(PauseState::Running)))
+            .add_systems(Update, (
+                sync_transforms_system,
+                sync_ball_views_system,
+                sync_power_up_views_system,
+                update_scores_system,
+                update_set_counters_system,
+                update_power_ups_system,
+                play_sounds_system,
+                hide_instructions_system,
+                game_over_system,
+                show_seed_system,
+            ).run_if(in_state(GAME_STATE)).before(Set::Back))
+            // Outside playback the back key pauses the match, see `PausePlugin`
+            .add_systems(Update, back_to_menu_system.in_set(Set::Back).run_if(in_state(GAME_STATE)).run_if(resource_exists::<ReplayPlayback>()))
+            .add_systems(OnExit(GAME_STATE), cleanup_entities::<GameModeEntity>);
+
+        #[cfg(not(target_arch = "wasm32"))]
+        app.add_systems(OnExit(GAME_STATE), save_replay_system);
+    }
+}
+
+fn setup_controllers_system(
+    mut commands: Commands,
+    playback: Option<Res<ReplayPlayback>>,
+    settings: Res<Settings>,
+    ai_difficulty: Res<AiDifficulty>,
+    bindings: Res<KeyBindings>,
+) {
+    let mut controllers = Vec::new();
+    for side in [Side::Left, Side::Right] {
+        // Each team plays on its half of the screen with the pointer
+        let zone = if side == Side::Left { PointerZone::LeftHalf } else { PointerZone::RightHalf };
+
+        for line in PaddleLine::ALL {
+            let kind = match settings.doubles.seat(side, line) {
+                SeatControl::PlayerOne => ControllerKind::Human(HumanControls::for_player(&bindings, Player::One).with_zone(zone)),
+                SeatControl::PlayerTwo => ControllerKind::Human(HumanControls::for_player(&bindings, Player::Two).with_zone(zone)),
+                SeatControl::Ai => ControllerKind::Ai(PaddleAi::new(ai_difficulty.0)),
+            };
+            controllers.push((side, line, kind));
+        }
+    }
+
+    spawn_team_controllers(&mut commands, playback.is_some(), controllers);
+}
+
+fn setup_instructions_system(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    config: Res<Config>,
+    bindings: Res<KeyBindings>,
+) {
+    let mut rows = vec![("Goal".to_string(), "beat the other team!".to_string())];
+
+    for side in [Side::Left, Side::Right] {
+        for line in PaddleLine::ALL {
+            let player = match settings.doubles.seat(side, line) {
+                SeatControl::PlayerOne => Player::One,
+                SeatControl::PlayerTwo => Player::Two,
+                SeatControl::Ai => continue,
+            };
+
+            rows.push((
+                bindings.label(player, &[Action::MoveUp, Action::MoveDown]),
+                format!("move {} {} paddle", side.label(), line.label()),
+            ));
+        }
+    }
+
+    rows.push((bindings.shared_label(Action::Serve), "launch the ball".to_string()));
+
+    let count = rows.len();
+    for (index, (label, text)) in rows.into_iter().enumerate() {
+        let bottom = 48. + (count - 1 - index) as f32 * 48.;
+
+        // Label
+        commands
+            .spawn(ButtonBundle {
+                style: Style {
+                    width: Val::Px(352.),
+                    height: Val::Px(48.),
+                    bottom: Val::Px(bottom),
+                    left: Val::Px(0.),
+                    position_type: PositionType::Absolute,
+                    justify_content: JustifyContent::FlexEnd,
+                    align_items: AlignItems::Center,
+                    ..Default::default()
+                },
+                background_color: config.color_transparent.into(),
+                ..Default::default()
+            })
+            .with_children(|parent| {
+                parent.spawn(TextBundle {
+                    text: Text::from_section(
+                        label,
+                        TextStyle {
+                            font: config.font.clone(),
+                            font_size: 30.,
+                            color: config.color_yellow,
+                        },
+                    ),
+                    ..Default::default()
+                });
+            })
+            .insert(GameModeEntity {})
+            .insert(Instruction {});
+
+        // Text
+        commands
+            .spawn(ButtonBundle {
+                style: Style {
+                    width: Val::Px(352.),
+                    height: Val::Px(48.),
+                    bottom: Val::Px(bottom),
+                    left: Val::Px(416.),
+                    position_type: PositionType::Absolute,
+                    justify_content: JustifyContent::FlexStart,
+                    align_items: AlignItems::Center,
+                    ..Default::default()
+                },
+                background_color: config.color_transparent.into(),
+                ..Default::default()
+            })
+            .with_children(|parent| {
+                parent.spawn(TextBundle {
+                    text: Text::from_section(
+                        text,
+                        TextStyle {
+                            font: config.font.clone(),
+                            font_size: 30.,
+                            color: config.color_grey,
+                        },
+                    ),
+                    ..Default::default()
+                });
+            })
+            .insert(GameModeEntity {})
+            .insert(Instruction {});
+    }
+}
@@ -0,0 +1,359 @@
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+use rand::random;
+
+use crate::ai::*;
+use crate::config::*;
+use crate::controller::*;
+use crate::systems_generic::*;
+use crate::components::*;
+use crate::events::*;
+use crate::helpers_sprite::*;
+use crate::input_map::*;
+use crate::pointer::*;
+use crate::settings::*;
+use crate::state::*;
+use crate::sim::*;
+
+pub struct Mode4PPlugin;
+
+#[derive(Debug, Eq, PartialEq, Clone, Hash, SystemSet)]
+enum Set {
+    SetupSim,
+    Back,
+}
+
+/// Lives left of a side, next to its edge of the court.
+#[derive(Component)]
+struct LivesText {
+    side: Side,
+}
+
+/// Sprite of the wall closing the side of an eliminated paddle.
+#[derive(Component)]
+struct SideWallView {
+    side: Side,
+}
+
+impl Plugin for Mode4PPlugin {
+    fn build(&self, app: &mut App) {
+        const GAME_STATE: GameState = GameState::Mode4P;
+
+        app
+            .add_event::<MatchEvent>()
+            .add_systems(OnEnter(GAME_STATE), (
+                setup_sim_system.in_set(Set::SetupSim),
+                setup_controllers_system,
+                setup_lives_system,
+                setup_instructions_system.after(Set::SetupSim),
+                setup_bodies_system.after(Set::SetupSim),
+                start_recording_system.after(Set::SetupSim)
+            ))
+            .add_systems(FixedUpdate, drive_paddles_system.in_set(SimSet::Input).run_if(in_state(GAME_STATE)))
+            .add_systems(FixedUpdate, step_sim_system.in_set(SimSet::Step).after(SimSet::Input).run_if(in_state(GAME_STATE)))
+            .add_systems(Update, latch_serve_system.run_if(in_state(GAME_STATE)).run_if(in_state(PauseState::Running)))
+            .add_systems(Update, (
+                sync_transforms_system,
+                sync_sides_system,
+                update_lives_system,
+                play_sounds_system,
+                hide_instructions_system,
+                game_over_system,
+                show_seed_system,
+            ).run_if(in_state(GAME_STATE)).before(Set::Back))
+            // Outside playback the back key pauses the match, see `PausePlugin`
+            .add_systems(Update, back_to_menu_system.in_set(Set::Back).run_if(in_state(GAME_STATE)).run_if(resource_exists::<ReplayPlayback>()))
+            .add_systems(OnExit(GAME_STATE), cleanup_entities::<GameModeEntity>);
+
+        #[cfg(not(target_arch = "wasm32"))]
+        app.add_systems(OnExit(GAME_STATE), save_replay_system);
+    }
+}
+
+fn setup_sim_system(
+    mut commands: Commands,
+    window: Query<&Window, With<PrimaryWindow>>,
+    playback: Option<Res<ReplayPlayback>>,
+    user_settings: Res<Settings>,
+    config: Res<Config>,
+) {
+    let sim = match playback {
+        Some(playback) => playback.replay.new_sim(),
+        None => {
+            let window = window.get_single().unwrap();
+            let mut settings = config.sim_settings(&GameState::Mode4P, Vec2::new(window.width(), window.height()));
+            settings.rules = user_settings.match_rules;
+            // A random side serves first, rematches included
+            commands.remove_resource::<RematchService>();
+
+            PongSim::new(settings, SimMode::FourPlayers, config.game_seed.unwrap_or_else(random))
+        }
+    };
+
+    commands.insert_resource(GameRng::new(sim.seed));
+    commands.insert_resource(Sim(sim));
+    commands.insert_resource(PendingInputs::default());
+}
+
+fn setup_controllers_system(
+    mut commands: Commands,
+    playback: Option<Res<ReplayPlayback>>,
+    settings: Res<Settings>,
+    ai_difficulty: Res<AiDifficulty>,
+    bindings: Res<KeyBindings>,
+) {
+    let controllers = Side::ALL.map(|side| {
+        let player = match settings.four_players.seat(side) {
+            SeatControl::PlayerOne => Player::One,
+            SeatControl::PlayerTwo => Player::Two,
+            SeatControl::Ai => return (side, ControllerKind::Ai(PaddleAi::new(ai_difficulty.0))),
+        };
+
+        // The pointer only drives the left and right paddles, each on its half of the screen
+        let zone = match side {
+            Side::Left => PointerZone::LeftHalf,
+            Side::Right => PointerZone::RightHalf,
+            Side::Top | Side::Bottom => PointerZone::Whole,
+        };
+        (side, ControllerKind::Human(HumanControls::for_player(&bindings, player).with_zone(zone)))
+    });
+
+    spawn_controllers(&mut commands, playback.is_some(), controllers);
+}
+
+fn setup_lives_system(
+    mut commands: Commands,
+    config: Res<Config>,
+) {
+    for side in Side::ALL {
+        let style = match side {
+            Side::Left => Style { left: Val::Px(48.), top: Val::Px(264.), ..Default::default() },
+            Side::Right => Style { right: Val::Px(48.), top: Val::Px(264.), ..Default::default() },
+            Side::Top => Style { left: Val::Px(148.), top: Val::Px(40.), ..Default::default() },
+            Side::Bottom => Style { left: Val::Px(148.), bottom: Val::Px(40.), ..Default::default() },
+        };
+
+        commands
+            .spawn(TextBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    ..style
+                },
+                text: Text::from_section(
+                    "",
+                    TextStyle {
+                        font: config.font.clone(),
+                        font_size: 21.,
+                        color: config.color_grey,
+                    },
+                ),
+                ..Default::default()
+            })
+            .insert(GameModeEntity {})
+            .insert(LivesText { side });
+    }
+}
+
+/// Follows the sim rather than its events, so seeking a replay is covered too.
+fn update_lives_system(
+    mut lives_query: Query<(&LivesText, &mut Text)>,
+    sim: Res<Sim>,
+    config: Res<Config>,
+) {
+    for (lives_text, mut text) in lives_query.iter_mut() {
+        let lives = sim.0.lives(lives_text.side);
+        let (value, color) = match lives {
+            0 => ("out".to_string(), config.color_red),
+            1 => ("1 life".to_string(), config.color_grey),
+            _ => (format!("{} lives", lives), config.color_grey),
+        };
+
+        if text.sections[0].value != value {
+            text.sections[0].value = value;
+            text.sections[0].style.color = color;
+        }
+    }
+}
+
+/// Walls up the eliminated sides, and opens them again when a replay seeks back.
+fn sync_sides_system(
+    mut commands: Commands,
+    paddle_query: Query<(Entity, &PaddleView)>,
+    wall_query: Query<(Entity, &SideWallView)>,
+    sim: Res<Sim>,
+    config: Res<Config>,
+) {
+    for (entity, paddle_view) in paddle_query.iter() {
+        if sim.0.paddle(paddle_view.side).is_none() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+
+    for (entity, wall_view) in wall_query.iter() {
+        if sim.0.lives(wall_view.side) > 0 {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+
+    for side in Side::ALL {
+        if let Some(paddle) = sim.0.paddle(side) {
+            if !paddle_query.iter().any(|(_, paddle_view)| paddle_view.side == side) {
+                commands
+                    .spawn(create_rect_sprite(paddle.body.position, paddle.body.size, config.color_white))
+                    .insert(GameModeEntity {})
+                    .insert(PaddleView { side, line: PaddleLine::Back });
+            }
+        } else if sim.0.lives(side) == 0 && !wall_query.iter().any(|(_, wall_view)| wall_view.side == side) {
+            let wall = sim.0.side_wall(side);
+            commands
+                .spawn(create_rect_sprite(wall.center, wall.half_size * 2., config.color_white))
+                .insert(GameModeEntity {})
+                .insert(SideWallView { side });
+        }
+    }
+}
+
+fn setup_instructions_system(
+    mut commands: Commands,
+    sim: Res<Sim>,
+    settings: Res<Settings>,
+    config: Res<Config>,
+    bindings: Res<KeyBindings>,
+) {
+    let mut rows = vec![(
+        "Goal".to_string(),
+        format!("{} lives each, last one standing!", sim.0.settings.rules.lives),
+    )];
+
+    for side in Side::ALL {
+        let player = match settings.four_players.seat(side) {
+            SeatControl::PlayerOne => Player::One,
+            SeatControl::PlayerTwo => Player::Two,
+            SeatControl::Ai => continue,
+        };
+
+        let text = if side.is_vertical() {
+            format!("move {} paddle", side.label())
+        } else {
+            format!("move {} paddle, up is left", side.label())
+        };
+        rows.push((bindings.label(player, &[Action::MoveUp, Action::MoveDown]), text));
+    }
+
+    rows.push((bindings.shared_label(Action::Serve), "launch the ball".to_string()));
+
+    let count = rows.len();
+    for (index, (label, text)) in rows.into_iter().enumerate() {
+        let bottom = 48. + (count - 1 - index) as f32 * 48.;
+
+        // Label
+        commands
+            .spawn(ButtonBundle {
+                style: Style {
+                    width: Val::Px(352.),
+                    height: Val::Px(48.),
+                    bottom: Val::Px(bottom),
+                    left: Val::Px(0.),
+                    position_type: PositionType::Absolute,
+                    justify_content: JustifyContent::FlexEnd,
+                    align_items: AlignItems::Center,
+                    ..Default::default()
+                },
+                background_color: config.color_transparent.into(),
+                ..Default::default()
+            })
+            .with_children(|parent| {
+                parent.spawn(TextBundle {
+                    text: Text::from_section(
+                        label,
+                        TextStyle {
+                            font: config.font.clone(),
+                            font_size: 30.,
+                            color: config.color_yellow,
+                        },
+                    ),
+                    ..Default::default()
+                });
+            })
+            .insert(GameModeEntity {})
+            .insert(Instruction {});
+
+        // Text
+        commands
+            .spawn(ButtonBundle {
+                style: Style {
+                    width: Val::Px(352.),
+                    height: Val::Px(48.),
+                    bottom: Val::Px(bottom),
+                    left: Val::Px(416.),
+                    position_type: PositionType::Absolute,
+                    justify_content: JustifyContent::FlexStart,
+                    align_items: AlignItems::Center,
+                    ..Default::default()
+                },
+                background_color: config.color_transparent.into(),
+                ..Default::default()
+            })
+            .with_children(|parent| {
+                parent.spawn(TextBundle {
+                    text: Text::from_section(
+                        text,
+                        TextStyle {
+                            font: config.font.clone(),
+                            font_size: 30.,
+                            color: config.color_grey,
+                        },
+                    ),
+                    ..Default::default()
+                });
+            })
+            .insert(GameModeEntity {})
+            .insert(Instruction {});
+    }
+}
+
+fn game_over_system(
+    mut commands: Commands,
+    mut match_events: EventReader<MatchEvent>,
+    window: Query<&Window, With<PrimaryWindow>>,
+    config: Res<Config>,
+) {
+    let window = window.get_single().unwrap();
+
+    for event in match_events.read() {
+        let SimEvent::GameOver(winner) = event.0 else {
+            continue;
+        };
+
+        commands
+            .spawn(ButtonBundle {
+                style: Style {
+                    width: Val::Px(544.),
+                    height: Val::Px(128.),
+                    right: Val::Px((window.width() - 544.) / 2.),
+                    top: Val::Px(128.),
+                    position_type: PositionType::Absolute,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..Default::default()
+                },
+                background_color: config.color_transparent.into(),
+                ..Default::default()
+            })
+            .with_children(|parent| {
+                parent.spawn(TextBundle {
+                    text: Text::from_section(
+                        format!("{} WINS", winner.label().to_uppercase()),
+                        TextStyle {
+                            font: config.font.clone(),
+                            font_size: 66.,
+                            color: config.color_green,
+                        },
+                    ),
+                    ..Default::default()
+                });
+            })
+            .insert(GameModeEntity {})
+            .insert(GameOverText {});
+    }
+}
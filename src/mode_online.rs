@@ -0,0 +1,662 @@
+use std::net::{SocketAddr, UdpSocket};
+use bevy::prelude::*;
+use bevy::ui::UiSystem;
+use bevy::window::{PrimaryWindow, ReceivedCharacter};
+use rand::random;
+
+use crate::config::*;
+use crate::controller::*;
+use crate::systems_generic::*;
+use crate::systems_1v1::*;
+use crate::components::*;
+use crate::events::*;
+use crate::input_map::*;
+use crate::navigation::*;
+use crate::netcode::*;
+use crate::sim::*;
+use crate::state::*;
+
+/// Seconds between two hellos while joining.
+const HELLO_INTERVAL: f32 = 0.5;
+/// Seconds without any datagram from the peer before the match is considered lost.
+const CONNECTION_TIMEOUT: f64 = 5.;
+/// Large enough for a full `NetMessage::Inputs`.
+const DATAGRAM_SIZE: usize = 4096;
+/// Port of the matches hosted from the online screen.
+const DEFAULT_PORT: u16 = 7777;
+/// Longest address typed on the online screen, an IPv6 one with its port fits.
+const ADDRESS_MAX_LENGTH: usize = 47;
+
+/// Role picked on the command line, or on the online screen.
+#[derive(Resource)]
+struct OnlineRole(NetRole);
+
+/// Address of the host typed on the online screen, kept for the next visit.
+#[derive(Resource)]
+struct JoinAddress(String);
+
+impl Default for JoinAddress {
+    fn default() -> Self {
+        JoinAddress(format!("127.0.0.1:{}", DEFAULT_PORT))
+    }
+}
+
+/// Socket of an online match and what is known about the other peer.
+#[derive(Resource)]
+struct NetSocket {
+    socket: UdpSocket,
+    role: NetRole,
+    peer: Option<SocketAddr>,
+    /// Sent again by the host whenever the joining peer says hello, in case it was lost.
+    welcome: Option<NetMessage>,
+    /// Elapsed real seconds at the last datagram from the peer.
+    last_received: f64,
+}
+
+/// Set when the socket could not be opened.
+#[derive(Resource)]
+struct NetError(String);
+
+#[derive(Resource)]
+struct OnlineSession(RollbackSession);
+
+/// Set when the peer stopped answering before the end of the match, which stops there.
+#[derive(Resource)]
+struct ConnectionLost;
+
+#[derive(Component)]
+struct OnlineStatus {}
+
+/// Host and join choices, shown until a socket is open.
+#[derive(Component)]
+struct OnlineLobby {}
+
+#[derive(Component)]
+struct OnlineButton {}
+
+#[derive(Component, Copy, Clone)]
+enum OnlineAction {
+    Host,
+    Join,
+    /// Once the connection is lost.
+    Menu,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Hash, SystemSet)]
+enum Set {
+    Handshake,
+    Back,
+}
+
+pub struct OnlinePlugin;
+
+impl Plugin for OnlinePlugin {
+    fn build(&self, app: &mut App) {
+        const GAME_STATE: GameState = GameState::Online;
+
+        if let Some(role) = NetRole::from_args() {
+            app
+                .insert_resource(OnlineRole(role))
+                .insert_resource(StartupState(GameState::Online));
+        }
+
+        app
+            .add_event::<MatchEvent>()
+            .init_resource::<JoinAddress>()
+            .add_systems(OnEnter(GAME_STATE), (
+                setup_socket_system,
+                setup_status_system,
+                setup_lobby_system,
+            ))
+            .add_systems(PreUpdate, button_navigation_system::<OnlineButton>.after(UiSystem::Focus).run_if(in_state(GAME_STATE)))
+            .add_systems(Update, (
+                hover_buttons_system,
+                click_buttons_system,
+            ).run_if(in_state(GAME_STATE)))
+            .add_systems(Update, type_address_system
+                .run_if(in_state(GAME_STATE))
+                .run_if(not(resource_exists::<NetSocket>())))
+            .add_systems(Update, cleanup_entities::<OnlineLobby>
+                .run_if(in_state(GAME_STATE))
+                .run_if(resource_added::<NetSocket>()))
+            .add_systems(Update, handshake_system
+                .in_set(Set::Handshake)
+                .run_if(in_state(GAME_STATE))
+                .run_if(resource_exists::<NetSocket>())
+                .run_if(not(resource_exists::<OnlineSession>())))
+            .add_systems(Update, (
+                setup_controllers_system,
+                setup_court_system,
+                setup_scores_system,
+                setup_bodies_system,
+            ).after(Set::Handshake).run_if(in_state(GAME_STATE)).run_if(resource_added::<OnlineSession>()))
+            .add_systems(Update, latch_serve_system
+                .run_if(in_state(GAME_STATE))
+                .run_if(resource_exists::<OnlineSession>()))
+            .add_systems(FixedUpdate, drive_paddles_system
+                .in_set(SimSet::Input)
+                .run_if(in_state(GAME_STATE))
+                .run_if(resource_exists::<OnlineSession>()))
+            .add_systems(FixedUpdate, online_step_system
+                .in_set(SimSet::Step)
+                .after(SimSet::Input)
+                .run_if(in_state(GAME_STATE))
+                .run_if(resource_exists::<OnlineSession>())
+                .run_if(not(resource_exists::<ConnectionLost>())))
+            .add_systems(Update, connection_lost_system
+                .run_if(in_state(GAME_STATE))
+                .run_if(resource_added::<ConnectionLost>()))
+            .add_systems(Update, (
+                sync_transforms_system,
+                update_scores_system,
+                play_sounds_system,
+                game_over_system,
+                show_seed_system,
+            ).after(Set::Handshake).run_if(in_state(GAME_STATE)).run_if(resource_exists::<OnlineSession>()).before(Set::Back))
+            .add_systems(Update, update_status_system.run_if(in_state(GAME_STATE)).before(Set::Back))
+            .add_systems(Update, back_to_menu_system.in_set(Set::Back).run_if(in_state(GAME_STATE)))
+            .add_systems(OnExit(GAME_STATE), (
+                cleanup_entities::<GameModeEntity>,
+                close_socket_system,
+            ));
+    }
+}
+
+fn setup_socket_system(
+    mut commands: Commands,
+    role: Option<Res<OnlineRole>>,
+    time: Res<Time<Real>>,
+) {
+    if let Some(role) = role {
+        open_socket(&mut commands, &role.0, time.elapsed_seconds_f64());
+    }
+}
+
+/// Opens the socket of `role`, or reports why it could not.
+fn open_socket(commands: &mut Commands, role: &NetRole, now: f64) {
+    let bind_address: SocketAddr = match role {
+        NetRole::Host(port) => ([0, 0, 0, 0], *port).into(),
+        NetRole::Join(_) => ([0, 0, 0, 0], 0).into(),
+    };
+
+    let socket = match UdpSocket::bind(bind_address).and_then(|socket| socket.set_nonblocking(true).map(|_| socket)) {
+        Ok(socket) => socket,
+        Err(error) => {
+            warn!("Could not open {}: {}", bind_address, error);
+            commands.insert_resource(NetError(format!("could not open {}", bind_address)));
+            return;
+        }
+    };
+
+    let peer = match role {
+        NetRole::Host(_) => None,
+        NetRole::Join(address) => Some(*address),
+    };
+
+    commands.remove_resource::<NetError>();
+    commands.insert_resource(NetSocket {
+        socket,
+        role: role.clone(),
+        peer,
+        welcome: None,
+        last_received: now,
+    });
+}
+
+fn close_socket_system(
+    mut commands: Commands,
+    config: Res<Config>,
+) {
+    commands.remove_resource::<NetSocket>();
+    commands.remove_resource::<NetError>();
+    commands.remove_resource::<OnlineSession>();
+    commands.remove_resource::<ConnectionLost>();
+    // The online screen asks again next time, even after a match started from the command line
+    commands.remove_resource::<OnlineRole>();
+
+    // The joining peer runs at the rate of the host
+    commands.insert_resource(Time::<Fixed>::from_hz(config.game_fixed_hz));
+}
+
+fn setup_status_system(
+    mut commands: Commands,
+    config: Res<Config>,
+) {
+    commands
+        .spawn(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                bottom: Val::Px(config.sprite_unit_size * 2.),
+                right: Val::Px(config.sprite_unit_size),
+                ..Default::default()
+            },
+            text: Text::from_section(
+                "",
+                TextStyle {
+                    font: config.font.clone(),
+                    font_size: 18.,
+                    color: config.color_grey,
+                },
+            ),
+            ..Default::default()
+        })
+        .insert(GameModeEntity {})
+        .insert(OnlineStatus {});
+}
+
+/// Host and join buttons, unless the command line already picked.
+fn setup_lobby_system(
+    mut commands: Commands,
+    window: Query<&Window, With<PrimaryWindow>>,
+    role: Option<Res<OnlineRole>>,
+    address: Res<JoinAddress>,
+    bindings: Res<KeyBindings>,
+    config: Res<Config>,
+) {
+    if role.is_some() {
+        return;
+    }
+
+    let window = window.get_single().unwrap();
+
+    for (action, label, top) in [
+        (OnlineAction::Host, format!("Host on port {}", DEFAULT_PORT), 260.),
+        (OnlineAction::Join, join_label(&address.0), 304.),
+    ] {
+        commands
+            .spawn(ButtonBundle {
+                style: Style {
+                    width: Val::Px(512.),
+                    height: Val::Px(44.),
+                    right: Val::Px((window.width() - 512.) / 2.),
+                    top: Val::Px(top),
+                    position_type: PositionType::Absolute,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..Default::default()
+                },
+                background_color: config.color_transparent.into(),
+                ..Default::default()
+            })
+            .with_children(|parent| {
+                parent.spawn(TextBundle {
+                    text: Text::from_section(
+                        label,
+                        TextStyle {
+                            font: config.font.clone(),
+                            font_size: 36.,
+                            color: config.color_white,
+                        },
+                    ),
+                    ..Default::default()
+                });
+            })
+            .insert(GameModeEntity {})
+            .insert(OnlineLobby {})
+            .insert(OnlineButton {})
+            .insert(action);
+    }
+
+    // Keys
+    commands
+        .spawn(ButtonBundle {
+            style: Style {
+                width: Val::Px(window.width()),
+                height: Val::Px(32.),
+                left: Val::Px(0.),
+                top: Val::Px(364.),
+                position_type: PositionType::Absolute,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            background_color: config.color_transparent.into(),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent.spawn(TextBundle {
+                text: Text::from_section(
+                    format!(
+                        "Type the address of the host to join it, UP/DOWN and {} to choose, {} for the menu",
+                        bindings.shared_label(Action::Serve),
+                        bindings.shared_label(Action::Back),
+                    ),
+                    TextStyle {
+                        font: config.font.clone(),
+                        font_size: 18.,
+                        color: config.color_grey,
+                    },
+                ),
+                ..Default::default()
+            });
+        })
+        .insert(GameModeEntity {})
+        .insert(OnlineLobby {});
+}
+
+fn join_label(address: &str) -> String {
+    format!("Join {}_", address)
+}
+
+/// Edits the address of the join button, digits, dots and colons only.
+fn type_address_system(
+    mut address: ResMut<JoinAddress>,
+    mut characters: EventReader<ReceivedCharacter>,
+    button_query: Query<(&OnlineAction, &Children)>,
+    mut text_query: Query<&mut Text>,
+    keyboard: Res<Input<KeyCode>>,
+) {
+    let mut typed = address.0.clone();
+    for character in characters.read() {
+        let allowed = character.char.is_ascii_hexdigit() || matches!(character.char, '.' | ':' | '[' | ']');
+        if allowed && typed.len() < ADDRESS_MAX_LENGTH {
+            typed.push(character.char);
+        }
+    }
+    if keyboard.just_pressed(KeyCode::Back) {
+        typed.pop();
+    }
+
+    if typed == address.0 {
+        return;
+    }
+    address.0 = typed;
+
+    for (action, children) in button_query.iter() {
+        if let OnlineAction::Join = action {
+            text_query.get_mut(children[0]).unwrap().sections[0].value = join_label(&address.0);
+        }
+    }
+}
+
+fn hover_buttons_system(
+    mut window: Query<&mut Window, With<PrimaryWindow>>,
+    interaction_query: Query<(&Interaction, &Children), With<OnlineButton>>,
+    mut text_query: Query<&mut Text>,
+    config: Res<Config>,
+) {
+    let mut window = window.get_single_mut().unwrap();
+    let mut hovered: bool = false;
+
+    for (interaction, children) in interaction_query.iter() {
+        let mut text = text_query.get_mut(children[0]).unwrap();
+        match *interaction {
+            Interaction::Hovered => {
+                text.sections[0].style.color = config.color_yellow;
+                hovered = true;
+            }
+            Interaction::None => {
+                text.sections[0].style.color = config.color_white;
+            }
+            _ => {}
+        }
+    }
+
+    window.cursor.icon = if hovered { CursorIcon::Hand } else { CursorIcon::Default };
+}
+
+/// Hosts or joins from the online screen, Enter joining too, and leaves once the connection is lost.
+fn click_buttons_system(
+    mut commands: Commands,
+    mut state: ResMut<NextState<GameState>>,
+    interaction_query: Query<(&Interaction, &OnlineAction), Changed<Interaction>>,
+    net: Option<Res<NetSocket>>,
+    address: Res<JoinAddress>,
+    keyboard: Res<Input<KeyCode>>,
+    time: Res<Time<Real>>,
+) {
+    let clicked = interaction_query
+        .iter()
+        .find(|(interaction, _)| **interaction == Interaction::Pressed)
+        .map(|(_, action)| *action);
+    let entered = net.is_none() && keyboard.any_just_pressed([KeyCode::Return, KeyCode::NumpadEnter]);
+    let Some(action) = clicked.or(entered.then_some(OnlineAction::Join)) else {
+        return;
+    };
+
+    let role = match action {
+        OnlineAction::Host => NetRole::Host(DEFAULT_PORT),
+        OnlineAction::Join => match address.0.parse() {
+            Ok(address) => NetRole::Join(address),
+            Err(_) => {
+                commands.insert_resource(NetError(format!("{} is not an address and port", address.0)));
+                return;
+            }
+        },
+        OnlineAction::Menu => {
+            state.set(GameState::Menu);
+            return;
+        }
+    };
+
+    open_socket(&mut commands, &role, time.elapsed_seconds_f64());
+    commands.insert_resource(OnlineRole(role));
+}
+
+fn handshake_system(
+    mut commands: Commands,
+    mut net: ResMut<NetSocket>,
+    mut hello_timer: Local<f32>,
+    window: Query<&Window, With<PrimaryWindow>>,
+    config: Res<Config>,
+    time: Res<Time<Real>>,
+) {
+    if let NetRole::Join(address) = net.role {
+        *hello_timer -= time.delta_seconds();
+        if *hello_timer <= 0. {
+            *hello_timer = HELLO_INTERVAL;
+            let _ = net.socket.send_to(&NetMessage::Hello.to_bytes(), address);
+        }
+    }
+
+    let mut buffer = [0u8; DATAGRAM_SIZE];
+    while let Ok((length, from)) = net.socket.recv_from(&mut buffer) {
+        let Some(message) = NetMessage::from_bytes(&buffer[..length]) else {
+            continue;
+        };
+
+        let problems = message.validate();
+        if !problems.is_empty() {
+            warn!("Dropped a message from {}: {}", from, problems.join(", "));
+            continue;
+        }
+
+        let (sim, fixed_hz) = match (&net.role, message) {
+            (NetRole::Host(_), NetMessage::Hello) => {
+                let window = window.get_single().unwrap();
+                let settings = config.sim_settings(&GameState::Online, Vec2::new(window.width(), window.height()));
+                let seed = config.game_seed.unwrap_or_else(random);
+
+                let welcome = NetMessage::Welcome { seed, fixed_hz: config.game_fixed_hz, settings: settings.clone() };
+                let _ = net.socket.send_to(&welcome.to_bytes(), from);
+
+                net.peer = Some(from);
+                net.welcome = Some(welcome);
+                (PongSim::new(settings, SimMode::Versus, seed), config.game_fixed_hz)
+            }
+            (NetRole::Join(address), NetMessage::Welcome { seed, fixed_hz, settings }) if from == *address => {
+                commands.insert_resource(Time::<Fixed>::from_hz(fixed_hz));
+                (PongSim::new(settings, SimMode::Versus, seed), fixed_hz)
+            }
+            _ => continue,
+        };
+
+        info!("Online match started with {} (seed {})", from, sim.seed);
+        net.last_received = time.elapsed_seconds_f64();
+
+        // Computed like `Time<Fixed>` does, both peers must step with the very same value
+        let dt = std::time::Duration::from_secs_f64(1. / fixed_hz).as_secs_f32();
+        let session = RollbackSession::new(sim.clone(), net.role.side(), input_delay_from_args(), dt);
+
+        commands.insert_resource(GameRng::new(sim.seed));
+        commands.insert_resource(Sim(sim));
+        commands.insert_resource(PendingInputs::default());
+        commands.insert_resource(OnlineSession(session));
+        return;
+    }
+}
+
+fn setup_controllers_system(
+    mut commands: Commands,
+    session: Res<OnlineSession>,
+    bindings: Res<KeyBindings>,
+) {
+    let local_side = session.0.local_side;
+    let mut local_controls = HumanControls::for_player(&bindings, local_side.into());
+    // Whichever paddle it plays, a peer uses its first gamepad
+    local_controls.pad = Player::One;
+
+    spawn_controllers(&mut commands, false, [
+        (local_side, ControllerKind::Human(local_controls)),
+        (local_side.opponent(), ControllerKind::Remote),
+    ]);
+}
+
+fn online_step_system(
+    mut commands: Commands,
+    mut net: ResMut<NetSocket>,
+    mut session: ResMut<OnlineSession>,
+    mut sim: ResMut<Sim>,
+    mut pending_inputs: ResMut<PendingInputs>,
+    mut match_events: EventWriter<MatchEvent>,
+    time: Res<Time<Real>>,
+) {
+    let mut buffer = [0u8; DATAGRAM_SIZE];
+    while let Ok((length, from)) = net.socket.recv_from(&mut buffer) {
+        if Some(from) != net.peer {
+            continue;
+        }
+
+        match NetMessage::from_bytes(&buffer[..length]) {
+            Some(NetMessage::Hello) => {
+                if let Some(welcome) = &net.welcome {
+                    let _ = net.socket.send_to(&welcome.to_bytes(), from);
+                }
+            }
+            Some(NetMessage::Inputs { start_tick, inputs, ack, checksums }) => {
+                let events = session.0.receive(start_tick, &inputs, ack, &checksums);
+                match_events.send_batch(events.into_iter().map(MatchEvent));
+            }
+            _ => continue,
+        }
+
+        net.last_received = time.elapsed_seconds_f64();
+    }
+
+    // Once the match is over, the peer leaving is no news
+    if time.elapsed_seconds_f64() - net.last_received > CONNECTION_TIMEOUT {
+        if session.0.sim().game_over.is_none() {
+            warn!("Lost the connection to {:?}", net.peer);
+            commands.insert_resource(ConnectionLost);
+        }
+        return;
+    }
+
+    let local_side = session.0.local_side;
+    if let Some(events) = session.0.advance(*pending_inputs.0.get(local_side)) {
+        pending_inputs.0.get_mut(local_side).serve = false;
+        match_events.send_batch(events.into_iter().map(MatchEvent));
+    }
+
+    if let Some(peer) = net.peer {
+        let _ = net.socket.send_to(&session.0.inputs_message().to_bytes(), peer);
+    }
+
+    sim.0 = session.0.sim().clone();
+}
+
+/// Stops the match for good, with a way back to the menu.
+fn connection_lost_system(
+    mut commands: Commands,
+    window: Query<&Window, With<PrimaryWindow>>,
+    config: Res<Config>,
+) {
+    let window = window.get_single().unwrap();
+
+    for (label, font_size, color, top, action) in [
+        ("CONNECTION LOST", 66., config.color_red, 128., None),
+        ("Back to menu", 36., config.color_white, 260., Some(OnlineAction::Menu)),
+    ] {
+        let mut entity = commands.spawn(ButtonBundle {
+            style: Style {
+                width: Val::Px(544.),
+                height: Val::Px(font_size + 8.),
+                right: Val::Px((window.width() - 544.) / 2.),
+                top: Val::Px(top),
+                position_type: PositionType::Absolute,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            background_color: config.color_transparent.into(),
+            ..Default::default()
+        });
+        entity
+            .with_children(|parent| {
+                parent.spawn(TextBundle {
+                    text: Text::from_section(
+                        label,
+                        TextStyle {
+                            font: config.font.clone(),
+                            font_size,
+                            color,
+                        },
+                    ),
+                    ..Default::default()
+                });
+            })
+            .insert(GameModeEntity {});
+
+        if let Some(action) = action {
+            entity.insert(OnlineButton {}).insert(action);
+        }
+    }
+}
+
+fn update_status_system(
+    mut status_query: Query<&mut Text, With<OnlineStatus>>,
+    mut reported_desync: Local<bool>,
+    net: Option<Res<NetSocket>>,
+    error: Option<Res<NetError>>,
+    session: Option<Res<OnlineSession>>,
+    config: Res<Config>,
+    time: Res<Time<Real>>,
+) {
+    let (status, color) = match (&net, &error, &session) {
+        (_, Some(error), _) => (error.0.clone(), config.color_red),
+        (Some(net), _, None) => match net.role {
+            NetRole::Host(port) => (format!("waiting for a player on port {}", port), config.color_grey),
+            NetRole::Join(address) => (format!("joining {}", address), config.color_grey),
+        },
+        (Some(net), _, Some(session)) => {
+            if let Some(tick) = session.0.desync_tick {
+                if !*reported_desync {
+                    *reported_desync = true;
+                    error!("Online match desynchronized at tick {} (seed {})", tick, session.0.sim().seed);
+                }
+                (format!("DESYNC at tick {}", tick), config.color_red)
+            } else if time.elapsed_seconds_f64() - net.last_received > CONNECTION_TIMEOUT {
+                ("connection lost".to_string(), config.color_red)
+            } else {
+                (
+                    format!(
+                        "online  {} paddle  rollback {}",
+                        if session.0.local_side == Side::Left { "left" } else { "right" },
+                        session.0.rollback_ticks,
+                    ),
+                    config.color_grey,
+                )
+            }
+        }
+        (None, None, _) => return,
+    };
+
+    if session.is_none() {
+        *reported_desync = false;
+    }
+
+    for mut text in status_query.iter_mut() {
+        text.sections[0].value = status.clone();
+        text.sections[0].style.color = color;
+    }
+}
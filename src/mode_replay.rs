@@ -0,0 +1,375 @@
+use std::path::PathBuf;
+use bevy::prelude::*;
+use bevy::ui::UiSystem;
+use bevy::window::PrimaryWindow;
+
+use crate::config::*;
+use crate::systems_generic::*;
+use crate::components::*;
+use crate::events::*;
+use crate::navigation::*;
+use crate::pause::LOCAL_MATCH_STATES;
+use crate::replay::*;
+use crate::sim::*;
+use crate::state::*;
+
+/// Playback speeds selectable with the up and down arrows.
+const REPLAY_SPEEDS: [f32; 5] = [0.25, 0.5, 1., 2., 4.];
+/// Index of the 1x speed in `REPLAY_SPEEDS`.
+const REPLAY_SPEED_DEFAULT: usize = 2;
+/// Seconds skipped by the left and right arrows.
+const REPLAY_SEEK_SECONDS: f64 = 5.;
+/// Most recent replays listed on the replays screen.
+const REPLAY_LIST_MAX: usize = 8;
+
+#[derive(Component)]
+struct ReplayListEntity {}
+
+#[derive(Component)]
+struct ReplayButton {
+    path: PathBuf,
+}
+
+#[derive(Component)]
+struct ReplayOverlay {}
+
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        const GAME_STATE: GameState = GameState::Replays;
+
+        app
+            .add_systems(OnEnter(GAME_STATE), setup_replay_list_system)
+            .add_systems(PreUpdate, button_navigation_system::<ReplayButton>.after(UiSystem::Focus).run_if(in_state(GAME_STATE)))
+            .add_systems(Update, (
+                hover_replay_buttons_system,
+                click_replay_button_system,
+                back_to_menu_system,
+            ).run_if(in_state(GAME_STATE)))
+            .add_systems(OnExit(GAME_STATE), cleanup_entities::<ReplayListEntity>)
+            .add_systems(FixedUpdate, pause_at_replay_end_system
+                .in_set(SimSet::Input)
+                .run_if(resource_exists::<ReplayPlayback>()))
+            .add_systems(Update, (
+                replay_controls_system,
+                update_replay_overlay_system,
+            ).run_if(resource_exists::<ReplayPlayback>()))
+            .add_systems(OnEnter(GameState::Menu), stop_playback_system);
+
+        for state in LOCAL_MATCH_STATES {
+            app.add_systems(OnEnter(state), setup_replay_overlay_system.run_if(resource_exists::<ReplayPlayback>()));
+        }
+    }
+}
+
+fn setup_replay_list_system(
+    mut commands: Commands,
+    window: Query<&Window, With<PrimaryWindow>>,
+    config: Res<Config>,
+) {
+    let window = window.get_single().unwrap();
+
+    // Title
+    commands
+        .spawn(ButtonBundle {
+            style: Style {
+                width: Val::Px(416.),
+                height: Val::Px(96.),
+                right: Val::Px((window.width() - 416.) / 2.),
+                top: Val::Px(48.),
+                position_type: PositionType::Absolute,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            background_color: config.color_transparent.into(),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent.spawn(TextBundle {
+                text: Text::from_section(
+                    "REPLAYS",
+                    TextStyle {
+                        font: config.font.clone(),
+                        font_size: 66.,
+                        color: config.color_white,
+                    },
+                ),
+                ..Default::default()
+            });
+        })
+        .insert(ReplayListEntity {});
+
+    let paths = files::list();
+    if paths.is_empty() {
+        commands
+            .spawn(TextBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(176.),
+                    left: Val::Px(window.width() / 2. - 128.),
+                    ..Default::default()
+                },
+                text: Text::from_section(
+                    "no replay yet",
+                    TextStyle {
+                        font: config.font.clone(),
+                        font_size: 30.,
+                        color: config.color_grey,
+                    },
+                ),
+                ..Default::default()
+            })
+            .insert(ReplayListEntity {});
+    }
+
+    for (index, path) in paths.into_iter().take(REPLAY_LIST_MAX).enumerate() {
+        // File names are "<timestamp>-<mode>-<seed>"
+        let label = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().replace('-', "  "))
+            .unwrap_or_default();
+
+        commands
+            .spawn(ButtonBundle {
+                style: Style {
+                    width: Val::Px(512.),
+                    height: Val::Px(40.),
+                    right: Val::Px((window.width() - 512.) / 2.),
+                    top: Val::Px(160. + index as f32 * 44.),
+                    position_type: PositionType::Absolute,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..Default::default()
+                },
+                background_color: config.color_transparent.into(),
+                ..Default::default()
+            })
+            .with_children(|parent| {
+                parent.spawn(TextBundle {
+                    text: Text::from_section(
+                        label,
+                        TextStyle {
+                            font: config.font.clone(),
+                            font_size: 24.,
+                            color: config.color_white,
+                        },
+                    ),
+                    ..Default::default()
+                });
+            })
+            .insert(ReplayListEntity {})
+            .insert(ReplayButton { path });
+    }
+}
+
+fn hover_replay_buttons_system(
+    mut window: Query<&mut Window, With<PrimaryWindow>>,
+    interaction_query: Query<(&Interaction, &Children), With<ReplayButton>>,
+    mut text_query: Query<&mut Text>,
+    config: Res<Config>,
+) {
+    let mut window = window.get_single_mut().unwrap();
+    let mut hovered: bool = false;
+
+    for (interaction, children) in interaction_query.iter() {
+        let mut text = text_query.get_mut(children[0]).unwrap();
+        match *interaction {
+            Interaction::Hovered => {
+                text.sections[0].style.color = config.color_yellow;
+                hovered = true;
+            }
+            Interaction::None => {
+                text.sections[0].style.color = config.color_white;
+            }
+            _ => {}
+        }
+    }
+
+    window.cursor.icon = if hovered { CursorIcon::Hand } else { CursorIcon::Default };
+}
+
+fn click_replay_button_system(
+    mut commands: Commands,
+    mut state: ResMut<NextState<GameState>>,
+    mut window: Query<&mut Window, With<PrimaryWindow>>,
+    interaction_query: Query<(&Interaction, &ReplayButton), Changed<Interaction>>,
+) {
+    for (interaction, button) in interaction_query.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+
+        let replay = match files::load(&button.path) {
+            Ok(replay) => replay,
+            Err(error) => {
+                warn!("Could not load replay {}: {}", button.path.display(), error);
+                continue;
+            }
+        };
+
+        window.get_single_mut().unwrap().cursor.icon = CursorIcon::Default;
+
+        // Same timestep as the recording, or the simulation would diverge
+        commands.insert_resource(Time::<Fixed>::from_hz(replay.fixed_hz));
+        state.set(GameState::from_match_kind(replay.kind));
+        commands.insert_resource(ReplayPlayback { replay, speed_index: REPLAY_SPEED_DEFAULT });
+        return;
+    }
+}
+
+fn stop_playback_system(
+    mut commands: Commands,
+    mut virtual_time: ResMut<Time<Virtual>>,
+    playback: Option<Res<ReplayPlayback>>,
+    config: Res<Config>,
+) {
+    if playback.is_none() {
+        return;
+    }
+
+    virtual_time.unpause();
+    virtual_time.set_relative_speed(1.);
+
+    commands.insert_resource(Time::<Fixed>::from_hz(config.game_fixed_hz));
+    commands.remove_resource::<ReplayPlayback>();
+}
+
+fn pause_at_replay_end_system(
+    mut virtual_time: ResMut<Time<Virtual>>,
+    playback: Res<ReplayPlayback>,
+    sim: Res<Sim>,
+) {
+    // `step_sim_system` stops there too
+    if sim.0.tick >= playback.replay.ticks() {
+        virtual_time.pause();
+    }
+}
+
+fn setup_replay_overlay_system(
+    mut commands: Commands,
+    config: Res<Config>,
+) {
+    commands
+        .spawn(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(config.sprite_unit_size * 2.),
+                left: Val::Px(config.sprite_unit_size * 2.),
+                ..Default::default()
+            },
+            text: Text::from_sections([
+                TextSection::new(
+                    "",
+                    TextStyle {
+                        font: config.font.clone(),
+                        font_size: 18.,
+                        color: config.color_yellow,
+                    },
+                ),
+                TextSection::new(
+                    "\nSPACE pause  LEFT/RIGHT seek\nUP/DOWN speed  . step",
+                    TextStyle {
+                        font: config.font.clone(),
+                        font_size: 18.,
+                        color: config.color_grey,
+                    },
+                ),
+            ]),
+            ..Default::default()
+        })
+        .insert(GameModeEntity {})
+        .insert(ReplayOverlay {});
+}
+
+fn update_replay_overlay_system(
+    mut overlay_query: Query<&mut Text, With<ReplayOverlay>>,
+    playback: Res<ReplayPlayback>,
+    virtual_time: Res<Time<Virtual>>,
+    sim: Res<Sim>,
+) {
+    let replay = &playback.replay;
+    let elapsed = sim.0.tick as f64 / replay.fixed_hz;
+    let duration = replay.ticks() as f64 / replay.fixed_hz;
+
+    for mut text in overlay_query.iter_mut() {
+        text.sections[0].value = format!(
+            "REPLAY x{}  {:.1} / {:.1} s{}",
+            REPLAY_SPEEDS[playback.speed_index],
+            elapsed,
+            duration,
+            if virtual_time.is_paused() { "  PAUSED" } else { "" },
+        );
+    }
+}
+
+fn replay_controls_system(
+    mut commands: Commands,
+    mut playback: ResMut<ReplayPlayback>,
+    mut sim: ResMut<Sim>,
+    mut virtual_time: ResMut<Time<Virtual>>,
+    mut match_events: EventWriter<MatchEvent>,
+    mut left_score_query: Query<&mut Text, (With<LeftScore>, Without<RightScore>)>,
+    mut right_score_query: Query<&mut Text, (With<RightScore>, Without<LeftScore>)>,
+    game_over_query: Query<Entity, With<GameOverText>>,
+    keyboard: Res<Input<KeyCode>>,
+) {
+    if keyboard.just_pressed(KeyCode::Space) {
+        if virtual_time.is_paused() {
+            virtual_time.unpause();
+        } else {
+            virtual_time.pause();
+        }
+    }
+
+    if keyboard.just_pressed(KeyCode::Up) && playback.speed_index + 1 < REPLAY_SPEEDS.len() {
+        playback.speed_index += 1;
+        virtual_time.set_relative_speed(REPLAY_SPEEDS[playback.speed_index]);
+    }
+
+    if keyboard.just_pressed(KeyCode::Down) && playback.speed_index > 0 {
+        playback.speed_index -= 1;
+        virtual_time.set_relative_speed(REPLAY_SPEEDS[playback.speed_index]);
+    }
+
+    let seek_ticks = (playback.replay.fixed_hz * REPLAY_SEEK_SECONDS) as u64;
+    let tick = sim.0.tick;
+
+    let target_tick = if keyboard.just_pressed(KeyCode::Left) {
+        tick.saturating_sub(seek_ticks)
+    } else if keyboard.just_pressed(KeyCode::Right) {
+        tick + seek_ticks
+    } else if keyboard.just_pressed(KeyCode::Period) && virtual_time.is_paused() {
+        tick + 1
+    } else {
+        return;
+    };
+    let target_tick = target_tick.min(playback.replay.ticks());
+
+    // Going back means simulating again from the start
+    if target_tick < tick {
+        sim.0 = playback.replay.new_sim();
+
+        for entity in game_over_query.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+
+    let events = playback.replay.fast_forward(&mut sim.0, target_tick);
+
+    // Only the result matters when jumping, not the hits along the way
+    for event in events {
+        if let SimEvent::GameOver(_) = event {
+            match_events.send(MatchEvent(event));
+        }
+    }
+
+    for mut text in left_score_query.iter_mut() {
+        text.sections[0].value = format!("{}", sim.0.left_score);
+    }
+
+    for mut text in right_score_query.iter_mut() {
+        text.sections[0].value = format!("{}", sim.0.right_score);
+    }
+}
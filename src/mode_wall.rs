@@ -1,25 +1,22 @@
 use bevy::prelude::*;
 use bevy::window::PrimaryWindow;
-use rand::*;
+use rand::random;
 
 use crate::config::*;
+use crate::controller::*;
 use crate::systems_generic::*;
 use crate::components::*;
 use crate::events::*;
+use crate::input_map::*;
 use crate::state::*;
-use crate::helpers_sprite::*;
+use crate::sim::*;
 
 pub struct ModeWallPlugin;
 
 #[derive(Debug, Eq, PartialEq, Clone, Hash, SystemSet)]
 enum Set {
-    ResetGameData,
-    SetupPaddle,
-    MoveBall,
-    Move,
+    SetupSim,
     Back,
-    CheckBallOut,
-    CheckGameOver,
 }
 
 impl Plugin for ModeWallPlugin {
@@ -27,70 +24,66 @@ impl Plugin for ModeWallPlugin {
         const GAME_STATE: GameState = GameState::ModeWall;
 
         app
-            .init_resource::<GameData>()
-            .add_event::<GameOverEvent>()
-            .add_event::<BallOutEvent>()
-            .add_event::<BallHitPaddleEvent>()
+            .add_event::<MatchEvent>()
             .add_systems(OnEnter(GAME_STATE), (
-                reset_game_data_system.in_set(Set::ResetGameData),
-                setup_court_system,
+                setup_sim_system.in_set(Set::SetupSim),
+                setup_controllers_system,
                 setup_score_system,
                 setup_instructions_system,
-                setup_left_paddle_system.in_set(Set::SetupPaddle).after(Set::ResetGameData),
-                setup_ball_system.after(Set::SetupPaddle)
+                setup_bodies_system.after(Set::SetupSim),
+                start_recording_system.after(Set::SetupSim)
             ))
+            .add_systems(FixedUpdate, drive_paddles_system.in_set(SimSet::Input).run_if(in_state(GAME_STATE)))
+            .add_systems(FixedUpdate, step_sim_system.in_set(SimSet::Step).after(SimSet::Input).run_if(in_state(GAME_STATE)))
+            .add_systems(Update, latch_serve_system.run_if(in_state(GAME_STATE)).run_if(in_state(PauseState::Running)))
             .add_systems(Update, (
-                launch_ball_system,
-                move_left_paddle_with_keyboard_system,
-                move_ball_system.in_set(Set::MoveBall)
-            ).run_if(in_state(GAME_STATE)).in_set(Set::Move).before(Set::Back))
-            .add_systems(Update, (
-                check_ball_collision_system,
-                increment_score_system.after(Set::CheckBallOut),
-                check_ball_out_system.in_set(Set::CheckBallOut),
-                check_game_over_system.in_set(Set::CheckGameOver).after(Set::CheckBallOut),
-                game_over_system.after(Set::CheckGameOver)
-            ).run_if(in_state(GAME_STATE)).after(Set::Move).before(Set::Back))
+                sync_transforms_system,
+                update_score_system,
+                play_sounds_system,
+                hide_instructions_system,
+                game_over_system,
+                show_seed_system,
+            ).run_if(in_state(GAME_STATE)).before(Set::Back))
+            // Outside playback the back key pauses the match, see `PausePlugin`
             .add_systems(Update, (
                 back_to_menu_system.in_set(Set::Back)
-            ).run_if(in_state(GAME_STATE)))
+            ).run_if(in_state(GAME_STATE)).run_if(resource_exists::<ReplayPlayback>()))
             .add_systems(OnExit(GAME_STATE), cleanup_entities::<GameModeEntity>);
-    }
-}
 
-fn reset_game_data_system(
-    mut game_data: ResMut<GameData>,
-) {
-    game_data.left_score = 0;
-    game_data.game_over = None;
+        #[cfg(not(target_arch = "wasm32"))]
+        app.add_systems(OnExit(GAME_STATE), save_replay_system);
+    }
 }
 
-fn setup_court_system(
+fn setup_sim_system(
     mut commands: Commands,
     window: Query<&Window, With<PrimaryWindow>>,
+    playback: Option<Res<ReplayPlayback>>,
     config: Res<Config>,
 ) {
-    let window = window.get_single().unwrap();
-    let color = config.color_white;
-    let unit_size = config.sprite_unit_size;
-
-    commands
-        .spawn(create_top_wall_sprite(window.width(), window.height(), unit_size, color))
-        .insert(GameModeEntity {})
-        .insert(SoundEmitter { source: config.audio_wall.clone() })
-        .insert(Collider::Wall);
-
-    commands
-        .spawn(create_bottom_wall_sprite(window.width(), window.height(), unit_size, color))
-        .insert(GameModeEntity {})
-        .insert(SoundEmitter { source: config.audio_wall.clone() })
-        .insert(Collider::Wall);
+    let sim = match playback {
+        Some(playback) => playback.replay.new_sim(),
+        None => {
+            let window = window.get_single().unwrap();
+            let settings = config.sim_settings(&GameState::ModeWall, Vec2::new(window.width(), window.height()));
+
+            PongSim::new(settings, SimMode::Wall, config.game_seed.unwrap_or_else(random))
+        }
+    };
+
+    commands.insert_resource(GameRng::new(sim.seed));
+    commands.insert_resource(Sim(sim));
+    commands.insert_resource(PendingInputs::default());
+}
 
-    commands
-        .spawn(create_right_wall_sprite(window.width(), window.height(), unit_size, color))
-        .insert(GameModeEntity {})
-        .insert(SoundEmitter { source: config.audio_wall.clone() })
-        .insert(Collider::Wall);
+fn setup_controllers_system(
+    mut commands: Commands,
+    playback: Option<Res<ReplayPlayback>>,
+    bindings: Res<KeyBindings>,
+) {
+    spawn_controllers(&mut commands, playback.is_some(), [
+        (Side::Left, ControllerKind::Human(HumanControls::for_player(&bindings, Player::One))),
+    ]);
 }
 
 fn setup_score_system(
@@ -136,6 +129,7 @@ fn setup_score_system(
 fn setup_instructions_system(
     mut commands: Commands,
     config: Res<Config>,
+    bindings: Res<KeyBindings>,
 ) {
     // Goal label
     commands
@@ -220,7 +214,7 @@ fn setup_instructions_system(
         .with_children(|parent| {
             parent.spawn(TextBundle {
                 text: Text::from_section(
-                    "S or X",
+                    bindings.label(Player::One, &[Action::MoveUp, Action::MoveDown]),
                     TextStyle {
                         font: config.font.clone(),
                         font_size: 30.,
@@ -284,7 +278,7 @@ fn setup_instructions_system(
         .with_children(|parent| {
             parent.spawn(TextBundle {
                 text: Text::from_section(
-                    "SPACEBAR",
+                    bindings.label(Player::One, &[Action::Serve]),
                     TextStyle {
                         font: config.font.clone(),
                         font_size: 30.,
@@ -330,115 +324,31 @@ fn setup_instructions_system(
         .insert(Instruction {});
 }
 
-fn setup_left_paddle_system(
-    mut commands: Commands,
-    window: Query<&Window, With<PrimaryWindow>>,
-    config: Res<Config>,
-) {
-    let window = window.get_single().unwrap();
-
-    commands
-        .spawn(create_left_paddle_sprite(window.width(), config.sprite_unit_size, config.color_white))
-        .insert(GameModeEntity {})
-        .insert(LeftPaddle { speed: config.game_paddle_speed })
-        .insert(SoundEmitter { source: config.audio_paddle_left.clone() })
-        .insert(Collider::Paddle)
-        .insert(Service {});
-}
-
-fn setup_ball_system(
-    mut commands: Commands,
-    config: Res<Config>,
-) {
-    commands
-        .spawn(create_ball_sprite(config.sprite_unit_size, Vec3::new(-128., -80., 0.), config.color_yellow))
-        .insert(GameModeEntity {})
-        .insert(Ball { speed: config.game_ball_speed_min, velocity: Vec3::default() });
-}
-
-fn launch_ball_system(
-    mut commands: Commands,
-    mut ball_query: Query<&mut Ball, With<Ball>>,
-    paddle_query: Query<Entity, With<Service>>,
-    instructions_query: Query<Entity, With<Instruction>>,
-    keyboard: Res<Input<KeyCode>>,
-    game_data: Res<GameData>,
-) {
-    if game_data.game_over.is_some() {
-        return;
-    }
-
-    if !keyboard.just_released(KeyCode::Space) {
-        return;
-    }
-
-    let paddle = paddle_query.get_single();
-    if paddle.is_err() {
-        // Already launched?
-        return;
-    }
-
-    let paddle_entity = paddle.unwrap();
-    let mut ball = ball_query.single_mut();
-
-    if random::<i32>() % 2 == 0 {
-        ball.velocity.y = -0.25;
-    } else {
-        ball.velocity.y = 0.25;
-    }
-
-    ball.velocity.x = 1.;
-
-    ball.velocity = ball.velocity.normalize();
-    ball.velocity.x *= ball.speed;
-    ball.velocity.y *= ball.speed;
-
-    commands.entity(paddle_entity).remove::<Service>();
-
-    // Also hide instructions
-    for instruction_entity in instructions_query.iter() {
-        commands.entity(instruction_entity).despawn_recursive();
-    }
-}
-
-fn increment_score_system(
-    mut ball_hit_paddle_event: EventReader<BallHitPaddleEvent>,
-    mut left_score_query: Query<&mut Text, (With<LeftScore>, Without<RightScore>)>,
-    mut game_data: ResMut<GameData>,
+fn update_score_system(
+    mut match_events: EventReader<MatchEvent>,
+    mut left_score_query: Query<&mut Text, With<LeftScore>>,
+    sim: Res<Sim>,
 ) {
-    if game_data.game_over.is_some() {
-        return;
-    }
-
-    for _ in ball_hit_paddle_event.read() {
-        game_data.left_score += 1;
-        left_score_query.single_mut().sections[0].value = format!("{}", game_data.left_score);
-    }
-}
-
-fn check_game_over_system(
-    mut ball_out_event: EventReader<BallOutEvent>,
-    mut game_over_event: EventWriter<GameOverEvent>,
-    game_data: Res<GameData>,
-) {
-    if game_data.game_over.is_some() {
-        return;
-    }
-
-    for _ in ball_out_event.read() {
-        game_over_event.send(GameOverEvent(Side::Right));
+    for event in match_events.read() {
+        if let SimEvent::Scored(Side::Left) = event.0 {
+            left_score_query.single_mut().sections[0].value = format!("{}", sim.0.left_score);
+        }
     }
 }
 
 fn game_over_system(
     mut commands: Commands,
-    mut game_over_event: EventReader<GameOverEvent>,
+    mut match_events: EventReader<MatchEvent>,
     window: Query<&Window, With<PrimaryWindow>>,
     config: Res<Config>,
 ) {
     let window = window.get_single().unwrap();
 
-    for _ in game_over_event.read() {
+    for event in match_events.read() {
+        let SimEvent::GameOver(_) = event.0 else {
+            continue;
+        };
+
         commands
             .spawn(ButtonBundle {
                 style: Style {
@@ -467,6 +377,7 @@ fn game_over_system(
                     ..Default::default()
                 });
             })
-            .insert(GameModeEntity {});
+            .insert(GameModeEntity {})
+            .insert(GameOverText {});
     }
 }
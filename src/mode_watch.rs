@@ -0,0 +1,209 @@
+use bevy::prelude::*;
+use rand::random;
+
+use crate::ai::*;
+use crate::config::*;
+use crate::controller::*;
+use crate::systems_generic::*;
+use crate::systems_1v1::*;
+use crate::components::*;
+use crate::events::*;
+use crate::sim::*;
+use crate::state::*;
+
+/// Seconds the result stays on screen before the next match starts.
+const NEXT_MATCH_DELAY: f32 = 3.;
+
+pub struct ModeWatchPlugin;
+
+/// Matches played since entering the mode, and how many broke a simulation invariant.
+#[derive(Default, Resource)]
+struct WatchStats {
+    matches: u32,
+    failed_matches: u32,
+    /// Only the first failure of a match is reported, it usually repeats on the following ticks.
+    current_failed: bool,
+}
+
+#[derive(Resource)]
+struct NextMatchTimer(Timer);
+
+#[derive(Component)]
+struct WatchLabel {}
+
+#[derive(Debug, Eq, PartialEq, Clone, Hash, SystemSet)]
+enum Set {
+    SetupSim,
+    Back,
+}
+
+impl Plugin for ModeWatchPlugin {
+    fn build(&self, app: &mut App) {
+        const GAME_STATE: GameState = GameState::Watch;
+
+        app
+            .add_event::<MatchEvent>()
+            .add_systems(OnEnter(GAME_STATE), (
+                setup_sim_system.in_set(Set::SetupSim),
+                setup_controllers_system,
+                setup_stats_system,
+                setup_court_system,
+                setup_scores_system.after(Set::SetupSim),
+                setup_power_ups_system.after(Set::SetupSim),
+                setup_label_system,
+                setup_bodies_system.after(Set::SetupSim),
+            ))
+            .add_systems(FixedUpdate, drive_paddles_system.in_set(SimSet::Input).run_if(in_state(GAME_STATE)))
+            .add_systems(FixedUpdate, step_sim_system.in_set(SimSet::Step).after(SimSet::Input).run_if(in_state(GAME_STATE)))
+            .add_systems(FixedUpdate, check_sim_system.after(SimSet::Step).run_if(in_state(GAME_STATE)))
+            .add_systems(Update, (
+                sync_transforms_system,
+                sync_ball_views_system,
+                sync_power_up_views_system,
+                update_scores_system,
+                update_set_counters_system,
+                update_power_ups_system,
+                play_sounds_system,
+                game_over_system,
+                show_seed_system,
+                start_next_match_timer_system,
+                next_match_system,
+                update_label_system,
+            ).run_if(in_state(GAME_STATE)).before(Set::Back))
+            .add_systems(Update, back_to_menu_system.in_set(Set::Back).run_if(in_state(GAME_STATE)))
+            .add_systems(OnExit(GAME_STATE), cleanup_entities::<GameModeEntity>);
+    }
+}
+
+fn setup_controllers_system(
+    mut commands: Commands,
+    difficulties: Res<WatchDifficulties>,
+) {
+    spawn_controllers(&mut commands, false, [
+        (Side::Left, ControllerKind::Ai(PaddleAi::new(difficulties.left))),
+        (Side::Right, ControllerKind::Ai(PaddleAi::new(difficulties.right))),
+    ]);
+}
+
+fn setup_stats_system(
+    mut commands: Commands,
+) {
+    commands.insert_resource(WatchStats { matches: 1, ..Default::default() });
+    commands.remove_resource::<NextMatchTimer>();
+}
+
+fn setup_label_system(
+    mut commands: Commands,
+    config: Res<Config>,
+) {
+    commands
+        .spawn(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                bottom: Val::Px(config.sprite_unit_size * 2.),
+                right: Val::Px(config.sprite_unit_size),
+                ..Default::default()
+            },
+            text: Text::from_section(
+                "",
+                TextStyle {
+                    font: config.font.clone(),
+                    font_size: 18.,
+                    color: config.color_grey,
+                },
+            ),
+            ..Default::default()
+        })
+        .insert(GameModeEntity {})
+        .insert(WatchLabel {});
+}
+
+fn update_label_system(
+    mut label_query: Query<&mut Text, With<WatchLabel>>,
+    difficulties: Res<WatchDifficulties>,
+    stats: Res<WatchStats>,
+) {
+    if !stats.is_changed() && !difficulties.is_changed() {
+        return;
+    }
+
+    for mut text in label_query.iter_mut() {
+        text.sections[0].value = format!(
+            "{} vs {}  match {}{}",
+            difficulties.left.label(),
+            difficulties.right.label(),
+            stats.matches,
+            if stats.failed_matches > 0 { format!("  {} failed", stats.failed_matches) } else { String::new() },
+        );
+    }
+}
+
+/// Soak test: every tick of an AI match must keep the court consistent.
+fn check_sim_system(
+    mut stats: ResMut<WatchStats>,
+    sim: Res<Sim>,
+) {
+    if stats.current_failed {
+        return;
+    }
+
+    if let Some(violation) = sim.0.check_invariants() {
+        error!("Simulation check failed (seed {}, tick {}): {}", sim.0.seed, sim.0.tick, violation);
+        stats.current_failed = true;
+        stats.failed_matches += 1;
+    }
+}
+
+fn start_next_match_timer_system(
+    mut commands: Commands,
+    mut match_events: EventReader<MatchEvent>,
+) {
+    for event in match_events.read() {
+        if let SimEvent::GameOver(_) = event.0 {
+            commands.insert_resource(NextMatchTimer(Timer::from_seconds(NEXT_MATCH_DELAY, TimerMode::Once)));
+        }
+    }
+}
+
+fn next_match_system(
+    mut commands: Commands,
+    mut sim: ResMut<Sim>,
+    mut stats: ResMut<WatchStats>,
+    mut controller_query: Query<&mut PaddleController>,
+    mut left_score_query: Query<&mut Text, (With<LeftScore>, Without<RightScore>)>,
+    mut right_score_query: Query<&mut Text, (With<RightScore>, Without<LeftScore>)>,
+    game_over_query: Query<Entity, With<GameOverText>>,
+    timer: Option<ResMut<NextMatchTimer>>,
+    time: Res<Time>,
+) {
+    let Some(mut timer) = timer else {
+        return;
+    };
+
+    if !timer.0.tick(time.delta()).finished() {
+        return;
+    }
+
+    commands.remove_resource::<NextMatchTimer>();
+
+    // A fresh seed every match, so the soak test covers new rallies
+    let seed = random();
+    sim.0 = PongSim::new(sim.0.settings.clone(), SimMode::Versus, seed);
+    commands.insert_resource(GameRng::new(seed));
+
+    for mut controller in controller_query.iter_mut() {
+        if let ControllerKind::Ai(ai) = &mut controller.kind {
+            *ai = PaddleAi::new(ai.difficulty);
+        }
+    }
+
+    stats.matches += 1;
+    stats.current_failed = false;
+
+    for entity in game_over_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    left_score_query.single_mut().sections[0].value = format!("{}", 0);
+    right_score_query.single_mut().sections[0].value = format!("{}", 0);
+}
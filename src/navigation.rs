@@ -0,0 +1,75 @@
+use bevy::prelude::*;
+
+use crate::gamepad::*;
+use crate::input_map::*;
+
+/// Stick deflection, past which it moves the focus between buttons like the D-pad.
+const STICK_NAVIGATION_THRESHOLD: f32 = 0.5;
+
+/// Button focused with the keyboard or a gamepad on a screen, and the one it pressed on the previous frame.
+#[derive(Default)]
+pub struct ButtonFocus {
+    focused: Option<Entity>,
+    pressed: Option<Entity>,
+    /// Stick direction on the previous frame, so holding it moves the focus only once.
+    stick_direction: i32,
+}
+
+/// Moves between the buttons marked `T` with the arrows, the D-pad or a stick, and presses them with a serve key or A.
+/// The focused button looks hovered, so the screens need nothing else to react to it.
+/// Runs after `UiSystem::Focus`, which would otherwise undo it.
+pub fn button_navigation_system<T: Component>(
+    mut focus: Local<ButtonFocus>,
+    mut button_query: Query<(Entity, &mut Interaction, &GlobalTransform), With<T>>,
+    keyboard: Res<Input<KeyCode>>,
+    gamepads: GamepadInputs,
+    bindings: Res<KeyBindings>,
+) {
+    // A press lasts a single frame, the mouse would release it otherwise
+    if let Some(entity) = focus.pressed.take() {
+        if let Ok((_, mut interaction, _)) = button_query.get_mut(entity) {
+            if *interaction == Interaction::Pressed {
+                *interaction = Interaction::Hovered;
+            }
+        }
+    }
+
+    // Top to bottom, then left to right
+    let mut buttons: Vec<(Entity, Vec3)> = button_query
+        .iter()
+        .map(|(entity, _, transform)| (entity, transform.translation()))
+        .collect();
+    buttons.sort_by(|(_, a), (_, b)| a.y.total_cmp(&b.y).then(a.x.total_cmp(&b.x)));
+
+    let stick = gamepads.any_left_stick_y();
+    let stick_direction = if stick.abs() > STICK_NAVIGATION_THRESHOLD { stick.signum() as i32 } else { 0 };
+    let stick_moved = stick_direction != focus.stick_direction;
+    focus.stick_direction = stick_direction;
+
+    // Stick up is positive, while the buttons are sorted downwards
+    let up = keyboard.just_pressed(KeyCode::Up) || gamepads.any_just_pressed(GamepadButtonType::DPadUp) || (stick_moved && stick_direction > 0);
+    let down = keyboard.just_pressed(KeyCode::Down) || gamepads.any_just_pressed(GamepadButtonType::DPadDown) || (stick_moved && stick_direction < 0);
+    let step: i32 = if up { -1 } else if down { 1 } else { 0 };
+    let press = bindings.keys(Action::Serve).any(|key| keyboard.just_pressed(key)) || gamepads.any_just_pressed(GamepadButtonType::South);
+
+    let index = focus.focused.and_then(|focused| buttons.iter().position(|(entity, _)| *entity == focused));
+    let Some(index) = index else {
+        // The first input only shows where the focus starts
+        if step != 0 || press {
+            focus.focused = buttons.first().map(|(entity, _)| *entity);
+        }
+        return;
+    };
+
+    let index = (index as i32 + step).clamp(0, buttons.len() as i32 - 1) as usize;
+    let entity = buttons[index].0;
+    focus.focused = Some(entity);
+
+    let (_, mut interaction, _) = button_query.get_mut(entity).unwrap();
+    if press {
+        *interaction = Interaction::Pressed;
+        focus.pressed = Some(entity);
+    } else if *interaction == Interaction::None {
+        *interaction = Interaction::Hovered;
+    }
+}
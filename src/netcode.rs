@@ -0,0 +1,573 @@
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use serde::{Deserialize, Serialize};
+
+use crate::config::FIXED_HZ_RANGE;
+use crate::sim::*;
+
+/// Ticks between two local inputs and the tick they apply to, hides most of the latency on its own.
+pub const DEFAULT_INPUT_DELAY: u64 = 2;
+/// Ticks the prediction may run ahead of the last tick both inputs are known for, stalls beyond.
+const MAX_PREDICTION: u64 = 16;
+/// Most inputs resent in a single packet.
+const MAX_INPUTS_PER_MESSAGE: usize = 64;
+/// Latest checksums resent in each packet, a lost one or a jump of the confirmed tick skips none.
+const CHECKSUMS_PER_MESSAGE: usize = MAX_PREDICTION as usize;
+/// Checksums kept to compare with the late ones of the peer.
+const CHECKSUM_HISTORY: usize = 128;
+
+/// How this process takes part in an online match, from `--host <port>` or `--join <address>`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum NetRole {
+    Host(u16),
+    Join(SocketAddr),
+}
+
+impl NetRole {
+    pub fn from_args() -> Option<NetRole> {
+        let args: Vec<String> = std::env::args().collect();
+        let value_of = |flag: &str| {
+            args.iter()
+                .position(|arg| arg == flag)
+                .and_then(|index| args.get(index + 1))
+        };
+
+        if let Some(port) = value_of("--host").and_then(|port| port.parse().ok()) {
+            return Some(NetRole::Host(port));
+        }
+
+        value_of("--join")
+            .and_then(|address| address.parse().ok())
+            .map(NetRole::Join)
+    }
+
+    /// The host plays on the left.
+    pub fn side(&self) -> Side {
+        match self {
+            NetRole::Host(_) => Side::Left,
+            NetRole::Join(_) => Side::Right,
+        }
+    }
+}
+
+/// Reads `--input-delay <ticks>` from the command line.
+pub fn input_delay_from_args() -> u64 {
+    let args: Vec<String> = std::env::args().collect();
+
+    args.iter()
+        .position(|arg| arg == "--input-delay")
+        .and_then(|index| args.get(index + 1))
+        .and_then(|delay| delay.parse().ok())
+        .unwrap_or(DEFAULT_INPUT_DELAY)
+}
+
+/// Datagrams exchanged by the peers, RON encoded.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum NetMessage {
+    /// Sent by the joining peer until it is welcomed.
+    Hello,
+    /// Everything the joining peer needs to build the same simulation as the host.
+    Welcome {
+        seed: u64,
+        fixed_hz: f64,
+        settings: SimSettings,
+    },
+    /// Local inputs from `start_tick` on, resent until acknowledged.
+    Inputs {
+        start_tick: u64,
+        inputs: Vec<PaddleInput>,
+        /// Next tick the sender is missing the inputs of.
+        ack: u64,
+        /// Latest confirmed checksums of the sender, one per tick, as (tick, checksum).
+        checksums: Vec<(u64, u64)>,
+    },
+}
+
+impl NetMessage {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        ron::to_string(self).unwrap_or_default().into_bytes()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<NetMessage> {
+        std::str::from_utf8(bytes).ok().and_then(|text| ron::from_str(text).ok())
+    }
+
+    /// Values of a welcome that would break the match of the joining peer, each naming the offending field.
+    /// Anyone can send one, so it is checked like the config file. Empty for the other messages.
+    pub fn validate(&self) -> Vec<String> {
+        let NetMessage::Welcome { fixed_hz, settings, .. } = self else {
+            return Vec::new();
+        };
+
+        let mut problems = settings.validate();
+        if !FIXED_HZ_RANGE.contains(fixed_hz) {
+            problems.push(format!(
+                "fixed_hz: must be from {} to {}, got {}",
+                FIXED_HZ_RANGE.start(), FIXED_HZ_RANGE.end(), fixed_hz,
+            ));
+        }
+        problems
+    }
+}
+
+/// Rollback netcode for two peers: the local paddle moves right away, the remote one is predicted
+/// from its last known input, and the match is simulated again when a prediction turns out wrong.
+pub struct RollbackSession {
+    pub local_side: Side,
+    dt: f32,
+    /// State of the last tick both inputs are known for.
+    confirmed: PongSim,
+    /// State shown on screen, ahead of `confirmed`.
+    predicted: PongSim,
+    /// The first `input_delay` ticks are idle.
+    local_inputs: TickInputs,
+    /// As received so far.
+    remote_inputs: TickInputs,
+    /// Remote inputs `predicted` was stepped with.
+    used_remote_inputs: TickInputs,
+    /// First local tick the peer has not acknowledged yet.
+    remote_ack: u64,
+    local_checksums: VecDeque<(u64, u64)>,
+    remote_checksums: VecDeque<(u64, u64)>,
+    /// First tick the peers disagreed on.
+    pub desync_tick: Option<u64>,
+    /// Ticks simulated again after a misprediction, for the status line.
+    pub rollback_ticks: u64,
+}
+
+impl RollbackSession {
+    pub fn new(sim: PongSim, local_side: Side, input_delay: u64, dt: f32) -> Self {
+        RollbackSession {
+            local_side,
+            dt,
+            confirmed: sim.clone(),
+            predicted: sim,
+            local_inputs: TickInputs { first_tick: 0, inputs: vec![PaddleInput::default(); input_delay as usize].into() },
+            remote_inputs: TickInputs::default(),
+            used_remote_inputs: TickInputs::default(),
+            remote_ack: 0,
+            local_checksums: VecDeque::new(),
+            remote_checksums: VecDeque::new(),
+            desync_tick: None,
+            rollback_ticks: 0,
+        }
+    }
+
+    pub fn sim(&self) -> &PongSim {
+        &self.predicted
+    }
+
+    /// Ticks the prediction runs ahead of the confirmed state.
+    pub fn prediction_ticks(&self) -> u64 {
+        self.predicted.tick - self.confirmed.tick
+    }
+
+    /// Steps the predicted match with a new local input, `None` while waiting for the peer.
+    pub fn advance(&mut self, local_input: PaddleInput) -> Option<Vec<SimEvent>> {
+        if self.prediction_ticks() >= MAX_PREDICTION {
+            return None;
+        }
+
+        self.local_inputs.push(local_input);
+
+        let tick = self.predicted.tick;
+        let remote_input = self.remote_input_guess(tick);
+        self.used_remote_inputs.push(remote_input);
+
+        let events = self.predicted.step(&self.inputs(tick, remote_input), self.dt);
+        self.confirm();
+
+        Some(events)
+    }
+
+    /// Takes inputs from the peer in, rolling back if they contradict the prediction.
+    /// Returns the score changes the correction caused.
+    pub fn receive(&mut self, start_tick: u64, inputs: &[PaddleInput], ack: u64, checksums: &[(u64, u64)]) -> Vec<SimEvent> {
+        self.remote_ack = self.remote_ack.max(ack);
+
+        for checksum in checksums {
+            if !self.remote_checksums.contains(checksum) {
+                push_bounded(&mut self.remote_checksums, *checksum);
+            }
+        }
+
+        let mut mispredicted = false;
+        for (offset, input) in inputs.iter().enumerate() {
+            let tick = start_tick + offset as u64;
+            if tick != self.remote_inputs.end() {
+                continue;
+            }
+
+            self.remote_inputs.push(*input);
+            if let Some(used) = self.used_remote_inputs.get(tick) {
+                mispredicted |= used != *input;
+            }
+        }
+
+        let events = if mispredicted { self.rollback() } else { Vec::new() };
+
+        self.confirm();
+        events
+    }
+
+    /// Message carrying the local inputs the peer has not acknowledged yet.
+    pub fn inputs_message(&self) -> NetMessage {
+        let start = self.remote_ack.min(self.local_inputs.end());
+
+        NetMessage::Inputs {
+            start_tick: start,
+            inputs: self.local_inputs.from(start).take(MAX_INPUTS_PER_MESSAGE).collect(),
+            ack: self.remote_inputs.end(),
+            checksums: self.local_checksums
+                .iter()
+                .skip(self.local_checksums.len().saturating_sub(CHECKSUMS_PER_MESSAGE))
+                .copied()
+                .collect(),
+        }
+    }
+
+    fn inputs(&self, tick: u64, remote_input: PaddleInput) -> SimInputs {
+        let mut inputs = SimInputs::default();
+        *inputs.get_mut(self.local_side) = self.local_inputs.get(tick).expect("local inputs are kept until confirmed");
+        *inputs.get_mut(self.local_side.opponent()) = remote_input;
+        inputs
+    }
+
+    /// Known remote input of `tick`, or the last one received: players mostly hold their keys.
+    fn remote_input_guess(&self, tick: u64) -> PaddleInput {
+        match self.remote_inputs.get(tick) {
+            Some(input) => input,
+            None => self.remote_inputs.last().map(|input| PaddleInput { serve: false, ..input }).unwrap_or_default(),
+        }
+    }
+
+    /// Simulates again from the confirmed state up to the current tick.
+    fn rollback(&mut self) -> Vec<SimEvent> {
+        let previous = self.predicted.clone();
+        let target_tick = previous.tick;
+
+        self.predicted = self.confirmed.clone();
+        self.used_remote_inputs.truncate(self.predicted.tick);
+
+        while self.predicted.tick < target_tick {
+            let tick = self.predicted.tick;
+            let remote_input = self.remote_input_guess(tick);
+            self.used_remote_inputs.push(remote_input);
+            self.predicted.step(&self.inputs(tick, remote_input), self.dt);
+            self.rollback_ticks += 1;
+        }
+
+        // Hits and serves already played out, only the results are worth correcting on screen
+        let mut events = Vec::new();
+        for side in [Side::Left, Side::Right] {
+            if self.predicted.score(side) != previous.score(side) {
+                events.push(SimEvent::Scored(side));
+            }
+            if self.predicted.sets(side) != previous.sets(side) {
+                events.push(SimEvent::SetWon(side));
+            }
+        }
+        if let (Some(winner), None) = (self.predicted.game_over, previous.game_over) {
+            events.push(SimEvent::GameOver(winner));
+        }
+
+        events
+    }
+
+    /// Moves the confirmed state forward over the ticks both inputs are known for.
+    fn confirm(&mut self) {
+        while self.confirmed.tick < self.predicted.tick
+            && self.confirmed.tick < self.remote_inputs.end()
+        {
+            let tick = self.confirmed.tick;
+            let inputs = self.inputs(tick, self.remote_inputs.get(tick).unwrap_or_default());
+            self.confirmed.step(&inputs, self.dt);
+
+            push_bounded(&mut self.local_checksums, (self.confirmed.tick, self.confirmed.checksum()));
+        }
+
+        // Rollbacks start from the confirmed tick: older inputs are only kept for the peer until it acknowledges them,
+        // and the last remote one for the guesses
+        let confirmed_tick = self.confirmed.tick;
+        self.local_inputs.forget_before(confirmed_tick.min(self.remote_ack));
+        self.remote_inputs.forget_before(confirmed_tick.saturating_sub(1));
+        self.used_remote_inputs.forget_before(confirmed_tick);
+
+        self.check_desync();
+    }
+
+    fn check_desync(&mut self) {
+        if self.desync_tick.is_some() {
+            return;
+        }
+
+        for (tick, checksum) in self.remote_checksums.iter() {
+            let local = self.local_checksums.iter().find(|(local_tick, _)| local_tick == tick);
+            if let Some((_, local_checksum)) = local {
+                if local_checksum != checksum {
+                    self.desync_tick = Some(*tick);
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Inputs of consecutive ticks from `first_tick` on, the older ones being forgotten once no longer needed.
+#[derive(Default)]
+struct TickInputs {
+    first_tick: u64,
+    inputs: VecDeque<PaddleInput>,
+}
+
+impl TickInputs {
+    /// Tick following the last input.
+    fn end(&self) -> u64 {
+        self.first_tick + self.inputs.len() as u64
+    }
+
+    fn get(&self, tick: u64) -> Option<PaddleInput> {
+        let index = tick.checked_sub(self.first_tick)?;
+        self.inputs.get(index as usize).copied()
+    }
+
+    fn last(&self) -> Option<PaddleInput> {
+        self.inputs.back().copied()
+    }
+
+    /// Inputs of `tick` and the following ones.
+    fn from(&self, tick: u64) -> impl Iterator<Item = PaddleInput> + '_ {
+        self.inputs.iter().skip(tick.saturating_sub(self.first_tick) as usize).copied()
+    }
+
+    fn push(&mut self, input: PaddleInput) {
+        self.inputs.push_back(input);
+    }
+
+    /// Drops the inputs of `tick` and the following ones.
+    fn truncate(&mut self, tick: u64) {
+        self.inputs.truncate(tick.saturating_sub(self.first_tick) as usize);
+    }
+
+    /// Drops the inputs before `tick`.
+    fn forget_before(&mut self, tick: u64) {
+        while self.first_tick < tick && self.inputs.pop_front().is_some() {
+            self.first_tick += 1;
+        }
+    }
+}
+
+fn push_bounded(history: &mut VecDeque<(u64, u64)>, entry: (u64, u64)) {
+    history.push_back(entry);
+    if history.len() > CHECKSUM_HISTORY {
+        history.pop_front();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec2;
+
+    use crate::arena::Arena;
+    use super::*;
+
+    const DT: f32 = 1. / 120.;
+    /// Iterations a message spends on the way, enough for the remote inputs to come in late.
+    const LATENCY: u64 = 5;
+
+    fn settings() -> SimSettings {
+        SimSettings {
+            court_size: Vec2::new(768., 576.),
+            unit_size: 16.,
+            paddle_speed: 400.,
+            ball_speed_min: 500.,
+            ball_speed_max: 750.,
+            ball_speed_incr: 5.,
+            ball_oob_x: 200.,
+            score_to_win: 9,
+            rules: MatchRules::default(),
+            first_service: None,
+            forward_offset: 192.,
+            arena: Arena::default(),
+        }
+    }
+
+    fn sessions() -> (RollbackSession, RollbackSession) {
+        let sim = PongSim::new(settings(), SimMode::Versus, 5);
+        (
+            RollbackSession::new(sim.clone(), Side::Left, DEFAULT_INPUT_DELAY, DT),
+            RollbackSession::new(sim, Side::Right, DEFAULT_INPUT_DELAY, DT),
+        )
+    }
+
+    /// Keys changing every few ticks, on a different beat for each side.
+    fn scripted_input(side: Side, tick: u64) -> PaddleInput {
+        let period = if side == Side::Left { 30 } else { 45 };
+        PaddleInput {
+            axis: [1., 0., -1.][(tick / period % 3) as usize],
+            serve: tick % 100 == 1,
+        }
+    }
+
+    /// Sends the inputs message of `from` to `to`, through bytes like the socket does.
+    fn message(from: &RollbackSession) -> NetMessage {
+        NetMessage::from_bytes(&from.inputs_message().to_bytes()).unwrap()
+    }
+
+    fn deliver(to: &mut RollbackSession, message: NetMessage) {
+        let NetMessage::Inputs { start_tick, inputs, ack, checksums } = message else {
+            panic!("not an inputs message: {:?}", message);
+        };
+        to.receive(start_tick, &inputs, ack, &checksums);
+    }
+
+    /// Plays `ticks` ticks on both peers with messages arriving `LATENCY` iterations late, then lets them catch up.
+    fn play(host: &mut RollbackSession, join: &mut RollbackSession, ticks: u64) {
+        let mut to_host = VecDeque::new();
+        let mut to_join = VecDeque::new();
+
+        for tick in 0..ticks {
+            assert!(host.advance(scripted_input(Side::Left, tick)).is_some(), "host stalled at {}", tick);
+            assert!(join.advance(scripted_input(Side::Right, tick)).is_some(), "join stalled at {}", tick);
+
+            to_join.push_back((tick + LATENCY, message(host)));
+            to_host.push_back((tick + LATENCY, message(join)));
+
+            while to_host.front().is_some_and(|(arrival, _)| *arrival <= tick) {
+                deliver(host, to_host.pop_front().unwrap().1);
+            }
+            while to_join.front().is_some_and(|(arrival, _)| *arrival <= tick) {
+                deliver(join, to_join.pop_front().unwrap().1);
+            }
+        }
+
+        for _ in 0..2 {
+            deliver(host, message(join));
+            deliver(join, message(host));
+        }
+    }
+
+    #[test]
+    fn misprediction_rolls_back() {
+        let (mut host, _) = sessions();
+        for _ in 0..10 {
+            host.advance(PaddleInput::default());
+        }
+
+        // The peer held up all along, not what was predicted
+        let up = PaddleInput { axis: 1., serve: false };
+        host.receive(0, &[up; 10], 0, &[]);
+
+        let mut reference = PongSim::new(settings(), SimMode::Versus, 5);
+        for _ in 0..10 {
+            reference.step(&SimInputs { right: up, ..Default::default() }, DT);
+        }
+        assert_eq!(host.rollback_ticks, 10);
+        assert_eq!(host.prediction_ticks(), 0);
+        assert_eq!(*host.sim(), reference);
+    }
+
+    #[test]
+    fn peers_end_on_the_same_match() {
+        let (mut host, mut join) = sessions();
+
+        play(&mut host, &mut join, 600);
+
+        assert!(host.rollback_ticks > 0 && join.rollback_ticks > 0, "the script should mispredict");
+        assert_eq!(host.prediction_ticks(), 0);
+        assert_eq!(join.prediction_ticks(), 0);
+        assert_eq!(host.sim().checksum(), join.sim().checksum());
+        assert_eq!(host.sim(), join.sim());
+        assert_eq!((host.desync_tick, join.desync_tick), (None, None));
+    }
+
+    #[test]
+    fn inputs_are_forgotten_once_confirmed_and_acknowledged() {
+        let (mut host, mut join) = sessions();
+
+        play(&mut host, &mut join, 600);
+
+        for session in [&host, &join] {
+            let confirmed_tick = session.confirmed.tick;
+            assert_eq!(session.local_inputs.first_tick, confirmed_tick.min(session.remote_ack));
+            assert!(session.local_inputs.inputs.len() < MAX_INPUTS_PER_MESSAGE);
+            assert_eq!(session.remote_inputs.first_tick, confirmed_tick - 1);
+            assert_eq!(session.used_remote_inputs.first_tick, confirmed_tick);
+        }
+    }
+
+    #[test]
+    fn prediction_stops_at_max_prediction() {
+        let (mut host, _) = sessions();
+        for _ in 0..MAX_PREDICTION {
+            assert!(host.advance(PaddleInput::default()).is_some());
+        }
+
+        assert_eq!(host.prediction_ticks(), MAX_PREDICTION);
+        assert_eq!(host.advance(PaddleInput::default()), None);
+        assert_eq!(host.sim().tick, MAX_PREDICTION);
+
+        // Going again once the peer catches up
+        host.receive(0, &[PaddleInput::default(); MAX_PREDICTION as usize], 0, &[]);
+        assert!(host.advance(PaddleInput::default()).is_some());
+    }
+
+    #[test]
+    fn diverged_match_is_a_desync() {
+        let (mut host, mut join) = sessions();
+        // As if the joining peer ran different rules
+        join.confirmed.balls[0].speed += 1.;
+        join.predicted.balls[0].speed += 1.;
+
+        play(&mut host, &mut join, 200);
+
+        // Every confirmed tick is compared, the first one already differs
+        assert_eq!(host.desync_tick, Some(1));
+        assert_eq!(join.desync_tick, Some(1));
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        let messages = [
+            NetMessage::Hello,
+            NetMessage::Welcome { seed: 77, fixed_hz: 120., settings: settings() },
+            NetMessage::Inputs {
+                start_tick: 12,
+                inputs: vec![PaddleInput { axis: -1., serve: true }, PaddleInput::default()],
+                ack: 9,
+                checksums: vec![(59, 1), (60, u64::MAX)],
+            },
+        ];
+
+        for message in messages {
+            assert_eq!(NetMessage::from_bytes(&message.to_bytes()), Some(message));
+        }
+        assert_eq!(NetMessage::from_bytes(b"not a message"), None);
+    }
+    #[test]
+    fn broken_welcome_is_refused() {
+        let welcome = |fixed_hz: f64, settings: SimSettings| NetMessage::Welcome { seed: 77, fixed_hz, settings };
+        assert_eq!(welcome(120., settings()).validate(), Vec::<String>::new());
+        assert_eq!(NetMessage::Hello.validate(), Vec::<String>::new());
+
+        for fixed_hz in [0., -120., 1e9, f64::NAN, f64::INFINITY] {
+            let problems = welcome(fixed_hz, settings()).validate();
+            assert_eq!(problems.len(), 1, "{:?}", problems);
+            assert!(problems[0].starts_with("fixed_hz: "), "{:?}", problems);
+        }
+
+        let broken = [
+            SimSettings { unit_size: 0., ..settings() },
+            SimSettings { unit_size: f32::NAN, ..settings() },
+            SimSettings { unit_size: 100., ..settings() },
+            SimSettings { court_size: Vec2::new(768., -576.), ..settings() },
+            SimSettings { court_size: Vec2::new(f32::INFINITY, 576.), ..settings() },
+            SimSettings { ball_speed_max: f32::NAN, ..settings() },
+            SimSettings { score_to_win: 0, ..settings() },
+            SimSettings { arena: Arena { name: "Empty path".to_string(), obstacles: vec![Default::default()], ..Default::default() }, ..settings() },
+        ];
+        for settings in broken {
+            assert!(!welcome(120., settings.clone()).validate().is_empty(), "{:?}", settings);
+        }
+    }
+}
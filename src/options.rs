@@ -0,0 +1,324 @@
+use bevy::prelude::*;
+use bevy::ui::UiSystem;
+use bevy::window::PrimaryWindow;
+
+use crate::components::*;
+use crate::config::*;
+use crate::input_map::*;
+use crate::navigation::*;
+use crate::pause::*;
+use crate::settings::*;
+use crate::systems_generic::*;
+use crate::state::*;
+
+#[derive(Component)]
+struct OptionsEntity {}
+
+#[derive(Component)]
+struct OptionsButton {}
+
+/// Value of a setting, pressing it moves to the next one.
+#[derive(Component, Copy, Clone)]
+enum OptionButton {
+    Volume,
+    AiDifficulty,
+    WindowScale,
+    Vsync,
+}
+
+impl OptionButton {
+    const ALL: [OptionButton; 4] = [
+        OptionButton::Volume,
+        OptionButton::AiDifficulty,
+        OptionButton::WindowScale,
+        OptionButton::Vsync,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            OptionButton::Volume => "Volume",
+            OptionButton::AiDifficulty => "AI level",
+            OptionButton::WindowScale => "Window size",
+            OptionButton::Vsync => "VSync",
+        }
+    }
+
+    fn value(self, settings: &Settings, ai_difficulty: &AiDifficulty) -> String {
+        match self {
+            OptionButton::Volume => format!("{}%", (settings.volume * 100.).round()),
+            OptionButton::AiDifficulty => ai_difficulty.0.label().to_string(),
+            OptionButton::WindowScale => format!("{}x", settings.display.scale),
+            OptionButton::Vsync => if settings.display.vsync { "on" } else { "off" }.to_string(),
+        }
+    }
+
+    fn next(self, settings: &mut Settings, ai_difficulty: &mut AiDifficulty) {
+        match self {
+            // Tenths, back to mute after full volume
+            OptionButton::Volume => {
+                let tenths = (settings.volume * 10.).round() as u32;
+                settings.volume = ((tenths + 1) % 11) as f32 / 10.;
+            }
+            OptionButton::AiDifficulty => ai_difficulty.0 = ai_difficulty.0.next(),
+            OptionButton::WindowScale => {
+                let index = WINDOW_SCALES.iter().position(|&scale| scale == settings.display.scale).unwrap_or(0);
+                settings.display.scale = WINDOW_SCALES[(index + 1) % WINDOW_SCALES.len()];
+            }
+            OptionButton::Vsync => settings.display.vsync = !settings.display.vsync,
+        }
+    }
+}
+
+#[derive(Component)]
+struct OptionsBackButton {}
+
+pub struct OptionsPlugin;
+
+impl Plugin for OptionsPlugin {
+    fn build(&self, app: &mut App) {
+        const GAME_STATE: GameState = GameState::Options;
+
+        app
+            .add_systems(OnEnter(GAME_STATE), setup_options_system)
+            .add_systems(PreUpdate, button_navigation_system::<OptionsButton>.after(UiSystem::Focus).run_if(in_state(GAME_STATE)))
+            .add_systems(Update, (
+                hover_options_buttons_system,
+                click_option_button_system,
+                click_back_button_system,
+                back_to_menu_system,
+            ).run_if(in_state(GAME_STATE)))
+            .add_systems(OnExit(GAME_STATE), (
+                cleanup_entities::<OptionsEntity>,
+                reset_cursor_system,
+            ));
+    }
+}
+
+fn setup_options_system(
+    mut commands: Commands,
+    window: Query<&Window, With<PrimaryWindow>>,
+    settings: Res<Settings>,
+    ai_difficulty: Res<AiDifficulty>,
+    bindings: Res<KeyBindings>,
+    config: Res<Config>,
+) {
+    let window = window.get_single().unwrap();
+
+    // Title
+    commands
+        .spawn(ButtonBundle {
+            style: Style {
+                width: Val::Px(416.),
+                height: Val::Px(96.),
+                right: Val::Px((window.width() - 416.) / 2.),
+                top: Val::Px(48.),
+                position_type: PositionType::Absolute,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            background_color: config.color_transparent.into(),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent.spawn(TextBundle {
+                text: Text::from_section(
+                    "OPTIONS",
+                    TextStyle {
+                        font: config.font.clone(),
+                        font_size: 66.,
+                        color: config.color_white,
+                    },
+                ),
+                ..Default::default()
+            });
+        })
+        .insert(OptionsEntity {});
+
+    for (index, option) in OptionButton::ALL.into_iter().enumerate() {
+        let top = 160. + index as f32 * 48.;
+
+        // Label
+        commands
+            .spawn(ButtonBundle {
+                style: Style {
+                    width: Val::Px(window.width() / 2. - 16.),
+                    height: Val::Px(44.),
+                    left: Val::Px(0.),
+                    top: Val::Px(top),
+                    position_type: PositionType::Absolute,
+                    justify_content: JustifyContent::FlexEnd,
+                    align_items: AlignItems::Center,
+                    ..Default::default()
+                },
+                background_color: config.color_transparent.into(),
+                ..Default::default()
+            })
+            .with_children(|parent| {
+                parent.spawn(TextBundle {
+                    text: Text::from_section(
+                        option.label(),
+                        TextStyle {
+                            font: config.font.clone(),
+                            font_size: 30.,
+                            color: config.color_yellow,
+                        },
+                    ),
+                    ..Default::default()
+                });
+            })
+            .insert(OptionsEntity {});
+
+        // Value
+        commands
+            .spawn(ButtonBundle {
+                style: Style {
+                    width: Val::Px(window.width() / 2. - 16.),
+                    height: Val::Px(44.),
+                    left: Val::Px(window.width() / 2. + 16.),
+                    top: Val::Px(top),
+                    position_type: PositionType::Absolute,
+                    justify_content: JustifyContent::FlexStart,
+                    align_items: AlignItems::Center,
+                    ..Default::default()
+                },
+                background_color: config.color_transparent.into(),
+                ..Default::default()
+            })
+            .with_children(|parent| {
+                parent.spawn(TextBundle {
+                    text: Text::from_section(
+                        option.value(&settings, &ai_difficulty),
+                        TextStyle {
+                            font: config.font.clone(),
+                            font_size: 30.,
+                            color: config.color_white,
+                        },
+                    ),
+                    ..Default::default()
+                });
+            })
+            .insert(OptionsEntity {})
+            .insert(OptionsButton {})
+            .insert(option);
+    }
+
+    // Back button
+    commands
+        .spawn(ButtonBundle {
+            style: Style {
+                width: Val::Px(256.),
+                height: Val::Px(44.),
+                right: Val::Px((window.width() - 256.) / 2.),
+                top: Val::Px(420.),
+                position_type: PositionType::Absolute,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            background_color: config.color_transparent.into(),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent.spawn(TextBundle {
+                text: Text::from_section(
+                    "Back",
+                    TextStyle {
+                        font: config.font.clone(),
+                        font_size: 36.,
+                        color: config.color_white,
+                    },
+                ),
+                ..Default::default()
+            });
+        })
+        .insert(OptionsEntity {})
+        .insert(OptionsButton {})
+        .insert(OptionsBackButton {});
+
+    // Keys
+    commands
+        .spawn(ButtonBundle {
+            style: Style {
+                width: Val::Px(window.width()),
+                height: Val::Px(32.),
+                left: Val::Px(0.),
+                top: Val::Px(484.),
+                position_type: PositionType::Absolute,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            background_color: config.color_transparent.into(),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent.spawn(TextBundle {
+                text: Text::from_section(
+                    format!(
+                        "UP/DOWN and {} to change, {} to go back",
+                        bindings.shared_label(Action::Serve),
+                        bindings.shared_label(Action::Back),
+                    ),
+                    TextStyle {
+                        font: config.font.clone(),
+                        font_size: 18.,
+                        color: config.color_grey,
+                    },
+                ),
+                ..Default::default()
+            });
+        })
+        .insert(OptionsEntity {});
+}
+
+fn hover_options_buttons_system(
+    mut window: Query<&mut Window, With<PrimaryWindow>>,
+    interaction_query: Query<(&Interaction, &Children), With<OptionsButton>>,
+    mut text_query: Query<&mut Text>,
+    config: Res<Config>,
+) {
+    let mut window = window.get_single_mut().unwrap();
+    let mut hovered: bool = false;
+
+    for (interaction, children) in interaction_query.iter() {
+        let mut text = text_query.get_mut(children[0]).unwrap();
+        match *interaction {
+            Interaction::Hovered => {
+                text.sections[0].style.color = config.color_yellow;
+                hovered = true;
+            }
+            Interaction::None => {
+                text.sections[0].style.color = config.color_white;
+            }
+            _ => {}
+        }
+    }
+
+    window.cursor.icon = if hovered { CursorIcon::Hand } else { CursorIcon::Default };
+}
+
+fn click_option_button_system(
+    mut settings: ResMut<Settings>,
+    mut ai_difficulty: ResMut<AiDifficulty>,
+    interaction_query: Query<(&Interaction, &OptionButton, &Children), Changed<Interaction>>,
+    mut text_query: Query<&mut Text>,
+) {
+    for (interaction, option, children) in interaction_query.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+
+        option.next(&mut settings, &mut ai_difficulty);
+        text_query.get_mut(children[0]).unwrap().sections[0].value = option.value(&settings, &ai_difficulty);
+    }
+}
+
+fn click_back_button_system(
+    mut state: ResMut<NextState<GameState>>,
+    interaction_query: Query<&Interaction, (Changed<Interaction>, With<OptionsBackButton>)>,
+) {
+    if interaction_query.iter().any(|interaction| *interaction == Interaction::Pressed) {
+        state.set(GameState::Menu);
+    }
+}
@@ -0,0 +1,342 @@
+use bevy::prelude::*;
+use bevy::ui::UiSystem;
+use bevy::window::{PrimaryWindow, WindowFocused};
+
+use crate::components::*;
+use crate::config::*;
+use crate::controller::*;
+use crate::game_over::*;
+use crate::gamepad::*;
+use crate::input_map::*;
+use crate::navigation::*;
+use crate::systems_generic::*;
+use crate::state::*;
+
+/// Behind the pause menu and the controls opened from it, the match stays visible.
+pub const BACKDROP_COLOR: Color = Color::rgba(0., 0., 0., 0.85);
+
+/// Local matches, the only ones that can be paused.
+pub const LOCAL_MATCH_STATES: [GameState; 5] = [
+    GameState::Mode1P,
+    GameState::Mode2P,
+    GameState::ModeWall,
+    GameState::Mode4P,
+    GameState::Mode2v2,
+];
+
+#[derive(Component)]
+struct PauseEntity {}
+
+#[derive(Component)]
+struct PauseButton {}
+
+#[derive(Component, Copy, Clone)]
+enum PauseAction {
+    Resume,
+    Restart,
+    Settings,
+    Quit,
+}
+
+impl PauseAction {
+    const ALL: [PauseAction; 4] = [PauseAction::Resume, PauseAction::Restart, PauseAction::Settings, PauseAction::Quit];
+
+    fn label(self) -> &'static str {
+        match self {
+            PauseAction::Resume => "Resume",
+            PauseAction::Restart => "Restart",
+            PauseAction::Settings => "Settings",
+            PauseAction::Quit => "Quit to menu",
+        }
+    }
+}
+
+pub struct PausePlugin;
+
+impl Plugin for PausePlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_state::<PauseState>()
+            .add_systems(Update, (
+                pause_system,
+                pause_on_focus_lost_system,
+            ).run_if(in_local_match).run_if(not(match_over)).run_if(in_state(PauseState::Running)))
+            .add_systems(OnEnter(PauseState::Running), resume_time_system)
+            .add_systems(OnEnter(PauseState::Paused), (
+                pause_time_system,
+                setup_pause_menu_system,
+            ))
+            .add_systems(PreUpdate, button_navigation_system::<PauseButton>
+                .after(UiSystem::Focus)
+                .run_if(in_state(PauseState::Paused)))
+            .add_systems(Update, (
+                hover_pause_buttons_system,
+                click_pause_buttons_system,
+                resume_system,
+            ).run_if(in_state(PauseState::Paused)))
+            .add_systems(OnExit(PauseState::Paused), (
+                cleanup_entities::<PauseEntity>,
+                reset_cursor_system,
+            ))
+            .add_systems(OnExit(PauseState::Settings), rebind_controllers_system)
+            .add_systems(OnEnter(GameState::Restarting), restart_system);
+
+        // Whatever way the match is left, the next one starts running
+        for state in LOCAL_MATCH_STATES {
+            app.add_systems(OnExit(state), reset_pause_system);
+        }
+    }
+}
+
+/// Outside replay playback, which has its own pause.
+pub fn in_local_match(
+    state: Res<State<GameState>>,
+    playback: Option<Res<ReplayPlayback>>,
+) -> bool {
+    LOCAL_MATCH_STATES.contains(state.get()) && playback.is_none()
+}
+
+/// The pause key, the back key or Start open the pause menu.
+fn pause_system(
+    mut pause_state: ResMut<NextState<PauseState>>,
+    keyboard: Res<Input<KeyCode>>,
+    gamepads: GamepadInputs,
+    bindings: Res<KeyBindings>,
+) {
+    let key_pressed = bindings.keys(Action::Pause)
+        .chain(bindings.keys(Action::Back))
+        .any(|key| keyboard.just_pressed(key));
+
+    if key_pressed || gamepads.any_just_pressed(GamepadButtonType::Start) {
+        pause_state.set(PauseState::Paused);
+    }
+}
+
+fn pause_on_focus_lost_system(
+    mut pause_state: ResMut<NextState<PauseState>>,
+    mut focus_events: EventReader<WindowFocused>,
+) {
+    if focus_events.read().any(|event| !event.focused) {
+        pause_state.set(PauseState::Paused);
+    }
+}
+
+/// The same keys close the pause menu.
+fn resume_system(
+    mut pause_state: ResMut<NextState<PauseState>>,
+    keyboard: Res<Input<KeyCode>>,
+    gamepads: GamepadInputs,
+    bindings: Res<KeyBindings>,
+) {
+    let key_pressed = bindings.keys(Action::Pause)
+        .chain(bindings.keys(Action::Back))
+        .any(|key| keyboard.just_pressed(key));
+
+    if key_pressed || gamepads.any_just_pressed(GamepadButtonType::Start) {
+        pause_state.set(PauseState::Running);
+    }
+}
+
+fn pause_time_system(
+    mut virtual_time: ResMut<Time<Virtual>>,
+) {
+    virtual_time.pause();
+}
+
+fn resume_time_system(
+    mut virtual_time: ResMut<Time<Virtual>>,
+) {
+    virtual_time.unpause();
+}
+
+fn reset_pause_system(
+    mut pause_state: ResMut<NextState<PauseState>>,
+) {
+    pause_state.set(PauseState::Running);
+}
+
+fn restart_system(
+    mut state: ResMut<NextState<GameState>>,
+    restart_state: Res<RestartState>,
+) {
+    state.set(restart_state.0.clone());
+}
+
+fn setup_pause_menu_system(
+    mut commands: Commands,
+    window: Query<&Window, With<PrimaryWindow>>,
+    bindings: Res<KeyBindings>,
+    config: Res<Config>,
+) {
+    let window = window.get_single().unwrap();
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(100.),
+                height: Val::Percent(100.),
+                position_type: PositionType::Absolute,
+                ..Default::default()
+            },
+            background_color: BACKDROP_COLOR.into(),
+            z_index: ZIndex::Global(1),
+            ..Default::default()
+        })
+        .insert(PauseEntity {})
+        .with_children(|parent| {
+            // Title
+            parent
+                .spawn(ButtonBundle {
+                    style: Style {
+                        width: Val::Px(416.),
+                        height: Val::Px(96.),
+                        right: Val::Px((window.width() - 416.) / 2.),
+                        top: Val::Px(96.),
+                        position_type: PositionType::Absolute,
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    background_color: config.color_transparent.into(),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    parent.spawn(TextBundle {
+                        text: Text::from_section(
+                            "PAUSED",
+                            TextStyle {
+                                font: config.font.clone(),
+                                font_size: 66.,
+                                color: config.color_white,
+                            },
+                        ),
+                        ..Default::default()
+                    });
+                });
+
+            for (index, action) in PauseAction::ALL.into_iter().enumerate() {
+                parent
+                    .spawn(ButtonBundle {
+                        style: Style {
+                            width: Val::Px(320.),
+                            height: Val::Px(44.),
+                            right: Val::Px((window.width() - 320.) / 2.),
+                            top: Val::Px(224. + index as f32 * 52.),
+                            position_type: PositionType::Absolute,
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..Default::default()
+                        },
+                        background_color: config.color_transparent.into(),
+                        ..Default::default()
+                    })
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle {
+                            text: Text::from_section(
+                                action.label(),
+                                TextStyle {
+                                    font: config.font.clone(),
+                                    font_size: 36.,
+                                    color: config.color_white,
+                                },
+                            ),
+                            ..Default::default()
+                        });
+                    })
+                    .insert(PauseButton {})
+                    .insert(action);
+            }
+
+            // Keys
+            parent
+                .spawn(ButtonBundle {
+                    style: Style {
+                        width: Val::Px(window.width()),
+                        height: Val::Px(32.),
+                        left: Val::Px(0.),
+                        top: Val::Px(460.),
+                        position_type: PositionType::Absolute,
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    background_color: config.color_transparent.into(),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    parent.spawn(TextBundle {
+                        text: Text::from_section(
+                            format!(
+                                "UP/DOWN and {} to choose, {} to resume",
+                                bindings.shared_label(Action::Serve),
+                                bindings.shared_label(Action::Pause),
+                            ),
+                            TextStyle {
+                                font: config.font.clone(),
+                                font_size: 18.,
+                                color: config.color_grey,
+                            },
+                        ),
+                        ..Default::default()
+                    });
+                });
+        });
+}
+
+fn hover_pause_buttons_system(
+    mut window: Query<&mut Window, With<PrimaryWindow>>,
+    interaction_query: Query<(&Interaction, &Children), With<PauseButton>>,
+    mut text_query: Query<&mut Text>,
+    config: Res<Config>,
+) {
+    let mut window = window.get_single_mut().unwrap();
+    let mut hovered: bool = false;
+
+    for (interaction, children) in interaction_query.iter() {
+        let mut text = text_query.get_mut(children[0]).unwrap();
+        match *interaction {
+            Interaction::Hovered => {
+                text.sections[0].style.color = config.color_yellow;
+                hovered = true;
+            }
+            Interaction::None => {
+                text.sections[0].style.color = config.color_white;
+            }
+            _ => {}
+        }
+    }
+
+    window.cursor.icon = if hovered { CursorIcon::Hand } else { CursorIcon::Default };
+}
+
+fn click_pause_buttons_system(
+    mut commands: Commands,
+    mut game_state: ResMut<NextState<GameState>>,
+    mut pause_state: ResMut<NextState<PauseState>>,
+    interaction_query: Query<(&Interaction, &PauseAction), Changed<Interaction>>,
+    state: Res<State<GameState>>,
+) {
+    for (interaction, action) in interaction_query.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+
+        match action {
+            PauseAction::Resume => pause_state.set(PauseState::Running),
+            PauseAction::Restart => {
+                commands.insert_resource(RestartState(state.get().clone()));
+                game_state.set(GameState::Restarting);
+            }
+            PauseAction::Settings => pause_state.set(PauseState::Settings),
+            PauseAction::Quit => game_state.set(GameState::Menu),
+        }
+        return;
+    }
+}
+
+/// A menu may close with a key while a button is hovered.
+pub fn reset_cursor_system(
+    mut window: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    window.get_single_mut().unwrap().cursor.icon = CursorIcon::Default;
+}
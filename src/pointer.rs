@@ -0,0 +1,106 @@
+use bevy::ecs::system::SystemParam;
+use bevy::utils::HashSet;
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+
+use crate::input_map::*;
+
+/// Pixels a touch may move and still count as a tap.
+const TAP_MAX_DISTANCE: f32 = 24.;
+
+/// Part of the window where the mouse or a finger drives a paddle.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PointerZone {
+    Whole,
+    /// Split screen, when both paddles are played on the same device.
+    LeftHalf,
+    RightHalf,
+}
+
+impl PointerZone {
+    fn contains(self, window: &Window, position: Vec2) -> bool {
+        match self {
+            PointerZone::Whole => true,
+            PointerZone::LeftHalf => position.x < window.width() / 2.,
+            PointerZone::RightHalf => position.x >= window.width() / 2.,
+        }
+    }
+}
+
+/// Presses seen by a system, so the release of a press that started elsewhere (e.g. on the pause menu) is no tap.
+#[derive(Default)]
+pub struct PointerTaps {
+    touches: HashSet<u64>,
+    mouse: bool,
+    /// Start positions of the taps of this frame.
+    released_touches: Vec<Vec2>,
+    clicked: bool,
+}
+
+/// Touches, and the mouse when the option is on, in a system.
+#[derive(SystemParam)]
+pub struct PointerInputs<'w, 's> {
+    window: Query<'w, 's, &'static Window, With<PrimaryWindow>>,
+    touches: Res<'w, Touches>,
+    mouse_buttons: Res<'w, Input<MouseButton>>,
+    bindings: Res<'w, KeyBindings>,
+    taps: Local<'s, PointerTaps>,
+}
+
+impl<'w, 's> PointerInputs<'w, 's> {
+    /// World y of the first finger in the zone, or of the mouse cursor.
+    pub fn target_y(&self, zone: PointerZone) -> Option<f32> {
+        let window = self.window.get_single().ok()?;
+
+        let touch = self.touches
+            .iter()
+            .map(|touch| touch.position())
+            .find(|&position| zone.contains(window, position));
+
+        let cursor = window
+            .cursor_position()
+            .filter(|&position| self.bindings.follow_mouse && zone.contains(window, position));
+
+        // Window positions go down from the top, the world up from the center
+        touch.or(cursor).map(|position| window.height() / 2. - position.y)
+    }
+
+    /// Finds the taps of this frame, once per run of the system before `tapped`.
+    pub fn update_taps(&mut self) {
+        let taps = &mut *self.taps;
+
+        taps.touches.extend(self.touches.iter_just_pressed().map(|touch| touch.id()));
+        taps.released_touches = self.touches
+            .iter_just_released()
+            .filter(|touch| taps.touches.contains(&touch.id()) && touch.distance().length() <= TAP_MAX_DISTANCE)
+            .map(|touch| touch.start_position())
+            .collect();
+
+        for touch in self.touches.iter_just_released().chain(self.touches.iter_just_canceled()) {
+            taps.touches.remove(&touch.id());
+        }
+
+        taps.mouse |= self.mouse_buttons.just_pressed(MouseButton::Left);
+        taps.clicked = taps.mouse && self.mouse_buttons.just_released(MouseButton::Left);
+        if self.mouse_buttons.just_released(MouseButton::Left) {
+            taps.mouse = false;
+        }
+    }
+
+    /// A finger lifted without sliding, or a click, in the zone.
+    pub fn tapped(&self, zone: PointerZone) -> bool {
+        let Ok(window) = self.window.get_single() else {
+            return false;
+        };
+
+        let tap = self.taps.released_touches
+            .iter()
+            .any(|&position| zone.contains(window, position));
+
+        let click = self.bindings.follow_mouse
+            && self.taps.clicked
+            && window.cursor_position().is_some_and(|position| zone.contains(window, position));
+
+        tap || click
+    }
+}
@@ -0,0 +1,304 @@
+use std::fmt;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+
+use crate::sim::*;
+
+/// Bumped whenever the file layout or the simulation rules change.
+pub const REPLAY_VERSION: u32 = 8;
+
+/// Game mode a match was played in, to play it back with the same presentation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MatchKind {
+    OnePlayer,
+    TwoPlayers,
+    Wall,
+    FourPlayers,
+    Doubles,
+}
+
+impl MatchKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            MatchKind::OnePlayer => "1p",
+            MatchKind::TwoPlayers => "2p",
+            MatchKind::Wall => "wall",
+            MatchKind::FourPlayers => "4p",
+            MatchKind::Doubles => "2v2",
+        }
+    }
+}
+
+/// Consecutive ticks sharing the same inputs.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct InputRun {
+    pub ticks: u32,
+    pub inputs: SimInputs,
+}
+
+/// Everything needed to replay a match: the simulation setup and its inputs, tick by tick.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Replay {
+    pub version: u32,
+    pub kind: MatchKind,
+    pub seed: u64,
+    pub fixed_hz: f64,
+    pub mode: SimMode,
+    pub settings: SimSettings,
+    /// Run-length encoded, held keys and idle AI make long runs.
+    pub runs: Vec<InputRun>,
+}
+
+/// Read before the rest, so a replay of another version reports it rather than a parse error.
+#[derive(Deserialize)]
+struct ReplayHeader {
+    version: u32,
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(std::io::Error),
+    Parse(ron::error::SpannedError),
+    Serialize(ron::Error),
+    Version(u32),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Io(error) => write!(f, "{}", error),
+            ReplayError::Parse(error) => write!(f, "{}", error),
+            ReplayError::Serialize(error) => write!(f, "{}", error),
+            ReplayError::Version(version) => write!(f, "unsupported replay version {}", version),
+        }
+    }
+}
+
+impl Replay {
+    pub fn new(kind: MatchKind, sim: &PongSim, fixed_hz: f64) -> Self {
+        Replay {
+            version: REPLAY_VERSION,
+            kind,
+            seed: sim.seed,
+            fixed_hz,
+            mode: sim.mode,
+            settings: sim.settings.clone(),
+            runs: Vec::new(),
+        }
+    }
+
+    /// Records the inputs of the next tick.
+    pub fn push(&mut self, inputs: &SimInputs) {
+        if let Some(run) = self.runs.last_mut() {
+            if run.inputs == *inputs {
+                run.ticks += 1;
+                return;
+            }
+        }
+
+        self.runs.push(InputRun { ticks: 1, inputs: *inputs });
+    }
+
+    pub fn ticks(&self) -> u64 {
+        self.runs.iter().map(|run| run.ticks as u64).sum()
+    }
+
+    /// Inputs recorded for the given tick, `None` once the recording is over.
+    pub fn inputs_at(&self, tick: u64) -> Option<SimInputs> {
+        let mut start = 0;
+        for run in self.runs.iter() {
+            if tick < start + run.ticks as u64 {
+                return Some(run.inputs);
+            }
+            start += run.ticks as u64;
+        }
+
+        None
+    }
+
+    /// Duration of a tick, computed like `Time<Fixed>` does so playback steps bit for bit the same.
+    pub fn tick_seconds(&self) -> f32 {
+        Duration::from_secs_f64(1. / self.fixed_hz).as_secs_f32()
+    }
+
+    /// Simulation in the state the recorded match started from.
+    pub fn new_sim(&self) -> PongSim {
+        PongSim::new(self.settings.clone(), self.mode, self.seed)
+    }
+
+    /// Steps `sim` with the recorded inputs up to `tick`, or the end of the recording.
+    pub fn fast_forward(&self, sim: &mut PongSim, tick: u64) -> Vec<SimEvent> {
+        let dt = self.tick_seconds();
+        let mut events = Vec::new();
+
+        while sim.tick < tick {
+            let Some(inputs) = self.inputs_at(sim.tick) else {
+                break;
+            };
+            events.append(&mut sim.step(&inputs, dt));
+        }
+
+        events
+    }
+
+    pub fn to_ron(&self) -> Result<String, ReplayError> {
+        ron::to_string(self).map_err(ReplayError::Serialize)
+    }
+
+    pub fn from_ron(text: &str) -> Result<Self, ReplayError> {
+        let header: ReplayHeader = ron::from_str(text).map_err(ReplayError::Parse)?;
+        if header.version != REPLAY_VERSION {
+            return Err(ReplayError::Version(header.version));
+        }
+
+        ron::from_str(text).map_err(ReplayError::Parse)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod files {
+    use std::fs;
+    use std::path::PathBuf;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use super::*;
+
+    /// Replays folder of the data directory.
+    pub fn replay_dir() -> PathBuf {
+        crate::storage::data_dir().join("replays")
+    }
+
+    pub fn save(replay: &Replay) -> Result<PathBuf, ReplayError> {
+        let dir = replay_dir();
+        fs::create_dir_all(&dir).map_err(ReplayError::Io)?;
+
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let path = dir.join(format!("{}-{}-{}.ron", timestamp, replay.kind.label(), replay.seed));
+
+        fs::write(&path, replay.to_ron()?).map_err(ReplayError::Io)?;
+        Ok(path)
+    }
+
+    pub fn load(path: &PathBuf) -> Result<Replay, ReplayError> {
+        let text = fs::read_to_string(path).map_err(ReplayError::Io)?;
+        Replay::from_ron(&text)
+    }
+
+    /// Saved replays, most recent first.
+    pub fn list() -> Vec<PathBuf> {
+        let Ok(entries) = fs::read_dir(replay_dir()) else {
+            return Vec::new();
+        };
+
+        let mut paths: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().map_or(false, |extension| extension == "ron"))
+            .collect();
+
+        // File names start with the timestamp
+        paths.sort();
+        paths.reverse();
+        paths
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec2;
+
+    use crate::arena::Arena;
+    use super::*;
+
+    fn settings() -> SimSettings {
+        SimSettings {
+            court_size: Vec2::new(768., 576.),
+            unit_size: 16.,
+            paddle_speed: 400.,
+            ball_speed_min: 500.,
+            ball_speed_max: 750.,
+            ball_speed_incr: 5.,
+            ball_oob_x: 200.,
+            score_to_win: 9,
+            rules: MatchRules::default(),
+            first_service: None,
+            forward_offset: 192.,
+            arena: Arena::default(),
+        }
+    }
+
+    fn inputs(axis: f32, serve: bool) -> SimInputs {
+        SimInputs {
+            left: PaddleInput { axis, serve },
+            right: PaddleInput { axis: -axis, serve },
+            ..Default::default()
+        }
+    }
+
+    /// Plays `ticks` ticks with inputs changing every 40 ticks, recording them.
+    fn record(ticks: u64) -> (Replay, PongSim) {
+        let mut sim = PongSim::new(settings(), SimMode::Versus, 9);
+        let mut replay = Replay::new(MatchKind::TwoPlayers, &sim, 120.);
+
+        for tick in 0..ticks {
+            let axis = [0., 1., -1., 0.5][(tick / 40 % 4) as usize];
+            let tick_inputs = inputs(axis, tick % 200 == 1);
+            replay.push(&tick_inputs);
+            sim.step(&tick_inputs, replay.tick_seconds());
+        }
+
+        (replay, sim)
+    }
+
+    #[test]
+    fn push_merges_repeated_inputs() {
+        let mut replay = Replay::new(MatchKind::TwoPlayers, &PongSim::new(settings(), SimMode::Versus, 0), 120.);
+        for _ in 0..3 {
+            replay.push(&inputs(1., false));
+        }
+        replay.push(&inputs(0., true));
+        replay.push(&inputs(1., false));
+
+        let runs: Vec<u32> = replay.runs.iter().map(|run| run.ticks).collect();
+        assert_eq!(runs, vec![3, 1, 1]);
+        assert_eq!(replay.ticks(), 5);
+        assert_eq!(replay.inputs_at(0), Some(inputs(1., false)));
+        assert_eq!(replay.inputs_at(2), Some(inputs(1., false)));
+        assert_eq!(replay.inputs_at(3), Some(inputs(0., true)));
+        assert_eq!(replay.inputs_at(4), Some(inputs(1., false)));
+        assert_eq!(replay.inputs_at(5), None);
+    }
+
+    #[test]
+    fn playback_ends_like_the_recorded_match() {
+        let (replay, recorded) = record(1200);
+        assert!(recorded.left_score + recorded.right_score > 0, "the recording should score points");
+
+        let mut sim = replay.new_sim();
+        replay.fast_forward(&mut sim, u64::MAX);
+
+        assert_eq!(sim, recorded);
+    }
+
+    #[test]
+    fn ron_round_trip() {
+        let (replay, _) = record(300);
+
+        let text = replay.to_ron().unwrap();
+        assert_eq!(Replay::from_ron(&text).unwrap(), replay);
+
+        let path = std::env::temp_dir().join(format!("pong_bevy-replay-test-{}.ron", std::process::id()));
+        std::fs::write(&path, text).unwrap();
+        let loaded = files::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), replay);
+    }
+
+    #[test]
+    fn other_versions_are_refused() {
+        let (mut replay, _) = record(10);
+        replay.version = REPLAY_VERSION + 1;
+
+        let text = replay.to_ron().unwrap();
+        assert!(matches!(Replay::from_ron(&text), Err(ReplayError::Version(version)) if version == REPLAY_VERSION + 1));
+    }
+}
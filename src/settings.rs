@@ -0,0 +1,399 @@
+use bevy::prelude::*;
+use bevy::window::{PresentMode, PrimaryWindow};
+use serde::{Deserialize, Serialize};
+
+use crate::ai::Difficulty;
+use crate::components::AiDifficulty;
+use crate::input_map::KeyBindings;
+use crate::sim::{MatchRules, PaddleLine, ServeRule, Side};
+use crate::storage::{self, Folder};
+
+/// Bumped whenever the layout of `Settings` changes, `Settings::load` migrates older files.
+pub const SETTINGS_VERSION: u32 = 2;
+/// Name of the settings file in the config directory.
+const SETTINGS_FILE: &str = "settings";
+/// Version 1 only saved the key bindings, in this file of the data directory.
+const BINDINGS_FILE_V1: &str = "controls";
+
+/// Match lengths offered, in sets.
+pub const BEST_OF_CHOICES: [u32; 3] = [1, 3, 5];
+/// Serve rules offered.
+pub const SERVE_RULE_CHOICES: [ServeRule; 4] = [
+    ServeRule::Winner,
+    ServeRule::Loser,
+    ServeRule::Alternate { points: 2 },
+    ServeRule::Alternate { points: 5 },
+];
+/// Seconds to serve offered, `None` waiting for the server.
+pub const SERVE_TIME_LIMIT_CHOICES: [Option<u32>; 4] = [None, Some(3), Some(5), Some(10)];
+/// Paddle hits between two extra balls offered, `None` playing a single ball.
+pub const MULTI_BALL_CHOICES: [Option<u32>; 4] = [None, Some(4), Some(8), Some(16)];
+/// Highest score to win the match rules screen goes up to.
+pub const MAX_SCORE_TO_WIN: u32 = 99;
+/// Most lives a side may start a four players match with.
+pub const MAX_LIVES: u32 = 9;
+
+/// Sizes the window may be scaled to, its content keeps the same layout.
+pub const WINDOW_SCALES: [f64; 3] = [1., 1.5, 2.];
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DisplaySettings {
+    /// One of `WINDOW_SCALES`.
+    pub scale: f64,
+    pub vsync: bool,
+}
+
+impl Default for DisplaySettings {
+    fn default() -> Self {
+        DisplaySettings {
+            scale: 1.,
+            vsync: true,
+        }
+    }
+}
+
+/// Who drives a paddle in four players.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SeatControl {
+    /// Keys and gamepad of player 1.
+    PlayerOne,
+    PlayerTwo,
+    Ai,
+}
+
+impl SeatControl {
+    pub const ALL: [SeatControl; 3] = [SeatControl::PlayerOne, SeatControl::PlayerTwo, SeatControl::Ai];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SeatControl::PlayerOne => "player 1",
+            SeatControl::PlayerTwo => "player 2",
+            SeatControl::Ai => "AI",
+        }
+    }
+}
+
+/// Moves `seats[index]` to the next control, a player driving a single paddle at a time.
+fn next_seat(seats: &mut [SeatControl], index: usize) {
+    let mut seat = seats[index];
+    loop {
+        let position = SeatControl::ALL.iter().position(|&control| control == seat).unwrap_or(0);
+        seat = SeatControl::ALL[(position + 1) % SeatControl::ALL.len()];
+
+        let taken = seats.iter().enumerate().any(|(other, &control)| other != index && control == seat);
+        if seat == SeatControl::Ai || !taken {
+            break;
+        }
+    }
+    seats[index] = seat;
+}
+
+/// Seats driven by the same player after the first one go to the AI.
+fn dedup_seats(seats: &mut [SeatControl]) {
+    for index in 1..seats.len() {
+        let seat = seats[index];
+        if seat != SeatControl::Ai && seats[..index].contains(&seat) {
+            seats[index] = SeatControl::Ai;
+        }
+    }
+}
+
+/// Paddles of a four players match, in the order of `Side::ALL`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FourPlayersSettings {
+    pub seats: [SeatControl; 4],
+}
+
+impl Default for FourPlayersSettings {
+    fn default() -> Self {
+        FourPlayersSettings {
+            seats: [SeatControl::PlayerOne, SeatControl::PlayerTwo, SeatControl::Ai, SeatControl::Ai],
+        }
+    }
+}
+
+impl FourPlayersSettings {
+    pub fn seat(&self, side: Side) -> SeatControl {
+        self.seats[side as usize]
+    }
+
+    /// Next control of the paddle of `side`, a player driving a single paddle at a time.
+    pub fn next_seat(&mut self, side: Side) {
+        next_seat(&mut self.seats, side as usize);
+    }
+}
+
+/// Paddles of a doubles match: back then forward of the left team, then of the right one.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DoublesSettings {
+    pub seats: [SeatControl; 4],
+}
+
+impl Default for DoublesSettings {
+    fn default() -> Self {
+        DoublesSettings {
+            seats: [SeatControl::PlayerOne, SeatControl::Ai, SeatControl::PlayerTwo, SeatControl::Ai],
+        }
+    }
+}
+
+impl DoublesSettings {
+    fn index(side: Side, line: PaddleLine) -> usize {
+        let team = if side == Side::Right { 2 } else { 0 };
+        team + line as usize
+    }
+
+    pub fn seat(&self, side: Side, line: PaddleLine) -> SeatControl {
+        self.seats[DoublesSettings::index(side, line)]
+    }
+
+    pub fn next_seat(&mut self, side: Side, line: PaddleLine) {
+        next_seat(&mut self.seats, DoublesSettings::index(side, line));
+    }
+}
+
+/// User preferences, saved whenever one of them changes.
+/// Bindings and AI level are edited through their own resources, `save_settings_system` copies them here.
+#[derive(Clone, Debug, PartialEq, Resource, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub version: u32,
+    /// Sound effects, from 0 to 1.
+    pub volume: f32,
+    pub bindings: KeyBindings,
+    pub ai_difficulty: Difficulty,
+    /// Points to win a set of a 1 vs 1 match, the config file decides when `None`.
+    pub score_to_win: Option<u32>,
+    /// Picked on the match rules screen before a local match.
+    pub match_rules: MatchRules,
+    pub four_players: FourPlayersSettings,
+    pub doubles: DoublesSettings,
+    /// Name of the arena of 1 and 2 players matches, the classic court when `None`.
+    pub arena: Option<String>,
+    pub display: DisplaySettings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            version: SETTINGS_VERSION,
+            volume: 1.,
+            bindings: KeyBindings::default(),
+            ai_difficulty: Difficulty::default(),
+            score_to_win: None,
+            match_rules: MatchRules::default(),
+            four_players: FourPlayersSettings::default(),
+            doubles: DoublesSettings::default(),
+            arena: None,
+            display: DisplaySettings::default(),
+        }
+    }
+}
+
+/// Read before the rest of the file, to know its layout.
+#[derive(Deserialize)]
+struct SettingsHeader {
+    version: u32,
+}
+
+impl Settings {
+    /// Saved settings of any version, the defaults when there are none or they cannot be read.
+    pub fn load() -> Self {
+        let saved = match storage::read(Folder::Config, SETTINGS_FILE) {
+            Some(text) => ron::from_str::<SettingsHeader>(&text)
+                .map(|header| (header.version, text))
+                .map_err(|error| error.to_string()),
+            None => match storage::read(Folder::Data, BINDINGS_FILE_V1) {
+                Some(text) => Ok((1, text)),
+                None => return Settings::default(),
+            },
+        };
+
+        let settings = saved.and_then(|(version, text)| {
+            let settings = Settings::migrate(version, &text)?;
+
+            // Written back in the current layout, so it is migrated once
+            if version < SETTINGS_VERSION {
+                info!("Settings migrated from version {} to {}", version, SETTINGS_VERSION);
+                settings.save();
+            }
+
+            Ok(settings)
+        });
+
+        match settings {
+            Ok(settings) => settings.validated(),
+            Err(error) => {
+                warn!("Using the default settings: {}", error);
+                Settings::default()
+            }
+        }
+    }
+
+    /// Reads a file in the layout of `version`.
+    fn migrate(version: u32, text: &str) -> Result<Settings, String> {
+        match version {
+            1 => {
+                let bindings = ron::from_str::<KeyBindings>(text).map_err(|error| error.to_string())?;
+                Ok(Settings { bindings, ..Default::default() })
+            }
+            SETTINGS_VERSION => ron::from_str::<Settings>(text).map_err(|error| error.to_string()),
+            // Left untouched, until a setting changes
+            _ => Err(format!("unsupported settings version {}", version)),
+        }
+    }
+
+    /// Values hand edited out of range are brought back to the defaults.
+    fn validated(mut self) -> Self {
+        let conflict = self.bindings.conflicts().next();
+        if let Some(conflict) = conflict {
+            warn!("Using the default controls, saved ones conflict: {}", conflict);
+            self.bindings = KeyBindings::default();
+        }
+
+        self.volume = self.volume.clamp(0., 1.);
+
+        if self.score_to_win == Some(0) {
+            self.score_to_win = None;
+        }
+        self.score_to_win = self.score_to_win.map(|score_to_win| score_to_win.min(MAX_SCORE_TO_WIN));
+
+        if !BEST_OF_CHOICES.contains(&self.match_rules.best_of) {
+            self.match_rules.best_of = 1;
+        }
+
+        if self.match_rules.serve == (ServeRule::Alternate { points: 0 }) {
+            self.match_rules.serve = ServeRule::Alternate { points: 1 };
+        }
+
+        self.match_rules.lives = self.match_rules.lives.clamp(1, MAX_LIVES);
+
+        dedup_seats(&mut self.four_players.seats);
+        dedup_seats(&mut self.doubles.seats);
+
+        if self.match_rules.serve_time_limit == Some(0) {
+            self.match_rules.serve_time_limit = None;
+        }
+
+        if self.match_rules.multi_ball == Some(0) {
+            self.match_rules.multi_ball = None;
+        }
+
+        if !WINDOW_SCALES.contains(&self.display.scale) {
+            self.display.scale = 1.;
+        }
+
+        self
+    }
+
+    pub fn save(&self) {
+        if let Err(error) = storage::save(Folder::Config, SETTINGS_FILE, self) {
+            warn!("Could not save the settings: {}", error);
+        }
+    }
+}
+
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_systems(Update, apply_display_system.run_if(resource_changed::<Settings>()))
+            .add_systems(Last, save_settings_system);
+    }
+}
+
+fn apply_display_system(
+    mut window: Query<&mut Window, With<PrimaryWindow>>,
+    settings: Res<Settings>,
+) {
+    let mut window = window.get_single_mut().unwrap();
+
+    // On top of the scale of the screen, so 1x stays the size the system picks
+    let scale_factor = window.resolution.base_scale_factor() * settings.display.scale;
+    if window.resolution.scale_factor_override() != Some(scale_factor) {
+        window.resolution.set_scale_factor_override(Some(scale_factor));
+    }
+
+    let present_mode = if settings.display.vsync { PresentMode::AutoVsync } else { PresentMode::AutoNoVsync };
+    if window.present_mode != present_mode {
+        window.present_mode = present_mode;
+    }
+}
+
+fn save_settings_system(
+    mut settings: ResMut<Settings>,
+    bindings: Res<KeyBindings>,
+    ai_difficulty: Res<AiDifficulty>,
+) {
+    if bindings.is_changed() && settings.bindings != *bindings {
+        settings.bindings = bindings.clone();
+    }
+
+    if ai_difficulty.is_changed() && settings.ai_difficulty != ai_difficulty.0 {
+        settings.ai_difficulty = ai_difficulty.0;
+    }
+
+    // Nothing new when loaded
+    if settings.is_changed() && !settings.is_added() {
+        settings.save();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `controls.ron` as version 1 saved it.
+    const CONTROLS_V1: &str = "(one:(move_up:W,move_down:S,serve:Space,pause:Return,back:Escape),\
+        two:(move_up:Up,move_down:Down,serve:Space,pause:Return,back:Escape))";
+
+    #[test]
+    fn version_1_bindings_are_migrated() {
+        let settings = Settings::migrate(1, CONTROLS_V1).unwrap().validated();
+
+        assert_eq!(settings.version, SETTINGS_VERSION);
+        assert_eq!(settings.bindings.one.move_up, KeyCode::W);
+        assert_eq!(settings.bindings.one.move_down, KeyCode::S);
+        assert_eq!(settings.bindings.two.move_up, KeyCode::Up);
+        assert_eq!(settings.bindings.two.move_down, KeyCode::Down);
+        assert_eq!(settings.bindings.two.serve, KeyCode::Space);
+        assert_eq!(Settings { bindings: KeyBindings::default(), ..settings }, Settings::default());
+    }
+
+    #[test]
+    fn current_version_round_trips() {
+        let settings = Settings { volume: 0.25, score_to_win: Some(5), ..Default::default() };
+        let text = ron::to_string(&settings).unwrap();
+
+        let header: SettingsHeader = ron::from_str(&text).unwrap();
+        assert_eq!(header.version, SETTINGS_VERSION);
+        assert_eq!(Settings::migrate(header.version, &text), Ok(settings));
+        assert!(Settings::migrate(SETTINGS_VERSION + 1, &text).is_err());
+    }
+
+    #[test]
+    fn out_of_range_values_are_reset() {
+        let mut conflicting = KeyBindings::default();
+        conflicting.two.move_up = conflicting.one.move_down;
+
+        let settings = Settings {
+            volume: 3.5,
+            bindings: conflicting,
+            score_to_win: Some(0),
+            display: DisplaySettings { scale: 1.7, vsync: false },
+            ..Default::default()
+        }.validated();
+
+        assert_eq!(settings.volume, 1.);
+        assert_eq!(settings.bindings, KeyBindings::default());
+        assert_eq!(settings.score_to_win, None);
+        assert_eq!(settings.display, DisplaySettings { scale: 1., vsync: false });
+
+        let quiet = Settings { volume: -1., ..Default::default() }.validated();
+        assert_eq!(quiet.volume, 0.);
+    }
+}
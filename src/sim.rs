@@ -0,0 +1,2048 @@
+use glam::Vec2;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::arena::*;
+use crate::collision::*;
+
+/// Upper bound of bounces resolved for the ball during a single tick (corners, wall then paddle...).
+const MAX_BALL_BOUNCES: usize = 4;
+/// Most balls in play at once with multi-ball.
+pub const MAX_BALLS: usize = 4;
+/// Seconds of rally before a power-up appears on the court, counted again once it is collected.
+const POWER_UP_INTERVAL: f32 = 6.;
+/// Longest a sticky paddle holds a ball before it leaves on its own.
+const STICKY_HOLD_TIME: f32 = 1.5;
+/// Paddle length factor of enlarge, and of shrink for the opponent.
+const ENLARGE_FACTOR: f32 = 1.5;
+const SHRINK_FACTOR: f32 = 0.5;
+/// Paddle speed factor of speed boost.
+const SPEED_BOOST_FACTOR: f32 = 1.5;
+/// Speed factor of the balls heading for the side that collected slow ball.
+const SLOW_BALL_FACTOR: f32 = 0.6;
+/// Distance, in pixels, a body may overshoot a wall by before `check_invariants` reports it.
+const COLLISION_TOLERANCE: f32 = 0.5;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Side {
+    #[default]
+    Left,
+    Right,
+    /// Only played in four players, like `Bottom`.
+    Top,
+    Bottom,
+}
+
+impl Side {
+    pub const ALL: [Side; 4] = [Side::Left, Side::Right, Side::Top, Side::Bottom];
+
+    pub fn opponent(self) -> Side {
+        match self {
+            Side::Left => Side::Right,
+            Side::Right => Side::Left,
+            Side::Top => Side::Bottom,
+            Side::Bottom => Side::Top,
+        }
+    }
+
+    /// Next side clockwise around the court.
+    pub fn clockwise(self) -> Side {
+        match self {
+            Side::Left => Side::Top,
+            Side::Top => Side::Right,
+            Side::Right => Side::Bottom,
+            Side::Bottom => Side::Left,
+        }
+    }
+
+    /// Horizontal direction this side plays towards, 0 for the top and bottom.
+    pub fn direction(self) -> f32 {
+        match self {
+            Side::Left => 1.,
+            Side::Right => -1.,
+            Side::Top | Side::Bottom => 0.,
+        }
+    }
+
+    /// Vertical direction this side plays towards, 0 for the left and right.
+    pub fn vertical_direction(self) -> f32 {
+        match self {
+            Side::Top => -1.,
+            Side::Bottom => 1.,
+            Side::Left | Side::Right => 0.,
+        }
+    }
+
+    /// Direction this side plays towards.
+    pub fn normal(self) -> Vec2 {
+        Vec2::new(self.direction(), self.vertical_direction())
+    }
+
+    /// Whether the paddle of this side moves up and down, rather than left and right.
+    pub fn is_vertical(self) -> bool {
+        matches!(self, Side::Left | Side::Right)
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Side::Left => "left",
+            Side::Right => "right",
+            Side::Top => "top",
+            Side::Bottom => "bottom",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SimMode {
+    /// Left paddle against right paddle, the match is decided by its `MatchRules`.
+    Versus,
+    /// A single left paddle against a right wall, every paddle hit scores.
+    Wall,
+    /// A paddle on every side of the court, each losing a life when the ball gets past it.
+    /// A side out of lives is walled up, the last one left wins.
+    FourPlayers,
+    /// Versus with a back and a forward paddle per side.
+    Doubles,
+}
+
+/// Paddle of a side: its only one, or one of the two of a doubles team.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum PaddleLine {
+    /// In front of the goal, where every mode has its paddles.
+    #[default]
+    Back,
+    /// Towards the net by `SimSettings::forward_offset`, in doubles.
+    Forward,
+}
+
+impl PaddleLine {
+    pub const ALL: [PaddleLine; 2] = [PaddleLine::Back, PaddleLine::Forward];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            PaddleLine::Back => "back",
+            PaddleLine::Forward => "forward",
+        }
+    }
+}
+
+/// Side serving after a point of a versus match.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ServeRule {
+    /// The side that scored the point.
+    #[default]
+    Winner,
+    /// The side that conceded the point.
+    Loser,
+    /// Like table tennis: each side serves `points` points in turn, then a single one each at deuce.
+    /// Sets start with the side that received first in the previous one.
+    Alternate { points: u32 },
+}
+
+impl ServeRule {
+    pub fn label(self) -> String {
+        match self {
+            ServeRule::Winner => "winner serves".to_string(),
+            ServeRule::Loser => "loser serves".to_string(),
+            ServeRule::Alternate { points: 1 } => "serve alternates every point".to_string(),
+            ServeRule::Alternate { points } => format!("serve alternates every {} points", points),
+        }
+    }
+}
+
+/// Item collected by a ball on the court, for the side whose paddle hit the ball last.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum PowerUpKind {
+    /// Longer paddles.
+    Enlarge,
+    /// Shorter paddles for the opponent.
+    Shrink,
+    /// Faster paddles.
+    SpeedBoost,
+    /// Balls heading for the side are slowed down.
+    SlowBall,
+    /// Sends another ball towards the opponent at once.
+    MultiBall,
+    /// Paddles catch the balls they hit, and serve them again.
+    Sticky,
+    /// Balls heading for the opponent only show in flashes.
+    Invisibility,
+    /// A wall closes the goal behind the paddles.
+    Shield,
+}
+
+impl PowerUpKind {
+    pub const ALL: [PowerUpKind; 8] = [
+        PowerUpKind::Enlarge,
+        PowerUpKind::Shrink,
+        PowerUpKind::SpeedBoost,
+        PowerUpKind::SlowBall,
+        PowerUpKind::MultiBall,
+        PowerUpKind::Sticky,
+        PowerUpKind::Invisibility,
+        PowerUpKind::Shield,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            PowerUpKind::Enlarge => "enlarge",
+            PowerUpKind::Shrink => "shrink",
+            PowerUpKind::SpeedBoost => "speed boost",
+            PowerUpKind::SlowBall => "slow ball",
+            PowerUpKind::MultiBall => "multi-ball",
+            PowerUpKind::Sticky => "sticky",
+            PowerUpKind::Invisibility => "invisibility",
+            PowerUpKind::Shield => "shield",
+        }
+    }
+
+    /// Seconds of rally the effect lasts, multi-ball having none.
+    pub fn duration(self) -> f32 {
+        match self {
+            PowerUpKind::Enlarge | PowerUpKind::Shrink => 10.,
+            PowerUpKind::SpeedBoost | PowerUpKind::Sticky => 8.,
+            PowerUpKind::SlowBall | PowerUpKind::Shield => 5.,
+            PowerUpKind::Invisibility => 3.,
+            PowerUpKind::MultiBall => 0.,
+        }
+    }
+}
+
+/// Power-ups enabled in a match, a bit each in the order of `PowerUpKind::ALL`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct PowerUps(pub u8);
+
+impl PowerUps {
+    fn bit(kind: PowerUpKind) -> u8 {
+        1 << kind as u8
+    }
+
+    pub fn contains(self, kind: PowerUpKind) -> bool {
+        self.0 & PowerUps::bit(kind) != 0
+    }
+
+    pub fn toggle(&mut self, kind: PowerUpKind) {
+        self.0 ^= PowerUps::bit(kind);
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn kinds(self) -> impl Iterator<Item = PowerUpKind> {
+        PowerUpKind::ALL.into_iter().filter(move |&kind| self.contains(kind))
+    }
+}
+
+/// How a versus match is won, on top of `SimSettings::score_to_win`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MatchRules {
+    /// Deuce: past the score to win, a set also needs a lead of two points.
+    pub win_by_two: bool,
+    /// Sets played at most, the first side to win more than half of them wins the match.
+    pub best_of: u32,
+    pub serve: ServeRule,
+    /// Balls a side may let through in four players before it is eliminated.
+    pub lives: u32,
+    /// Seconds a side has to serve before the ball leaves on its own, from the second point on.
+    pub serve_time_limit: Option<u32>,
+    /// Paddle hits of a rally between two extra balls, in versus. A single ball when `None`.
+    pub multi_ball: Option<u32>,
+    /// Power-ups appearing on the court in versus, none by default.
+    pub power_ups: PowerUps,
+}
+
+impl Default for MatchRules {
+    fn default() -> Self {
+        MatchRules {
+            win_by_two: false,
+            best_of: 1,
+            serve: ServeRule::Winner,
+            lives: 3,
+            serve_time_limit: None,
+            multi_ball: None,
+            power_ups: PowerUps::default(),
+        }
+    }
+}
+
+impl MatchRules {
+    pub fn sets_to_win(&self) -> u32 {
+        self.best_of.max(1) / 2 + 1
+    }
+
+    /// Short summary, such as "first to 11, win by two, best of 3 sets, loser serves".
+    pub fn describe(&self, score_to_win: u32) -> String {
+        let mut description = format!("first to {}", score_to_win);
+        if self.win_by_two {
+            description.push_str(", win by two");
+        }
+        if self.best_of > 1 {
+            description.push_str(&format!(", best of {} sets", self.best_of));
+        }
+        description.push_str(&format!(", {}", self.serve.label()));
+        if let Some(serve_time_limit) = self.serve_time_limit {
+            description.push_str(&format!(" within {}s", serve_time_limit));
+        }
+        if let Some(multi_ball) = self.multi_ball {
+            description.push_str(&format!(", a ball more every {} hits", multi_ball));
+        }
+        match self.power_ups.kinds().count() {
+            0 => {}
+            1 => description.push_str(", 1 power-up"),
+            count => description.push_str(&format!(", {} power-ups", count)),
+        }
+        description
+    }
+}
+
+/// Tuning of a simulation, usually built from `Config`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SimSettings {
+    pub court_size: Vec2,
+    pub unit_size: f32,
+    pub paddle_speed: f32,
+    pub ball_speed_min: f32,
+    pub ball_speed_max: f32,
+    pub ball_speed_incr: f32,
+    pub ball_oob_x: f32,
+    /// Points to win a set.
+    pub score_to_win: u32,
+    /// Replays recorded before sets existed were single set matches.
+    #[serde(default)]
+    pub rules: MatchRules,
+    /// Side serving first in versus, drawn from the seed when `None`.
+    #[serde(default)]
+    pub first_service: Option<Side>,
+    /// Distance from the back to the forward paddle of a doubles team.
+    #[serde(default)]
+    pub forward_offset: f32,
+    /// Layout of the court in versus, the classic one by default.
+    #[serde(default)]
+    pub arena: Arena,
+}
+
+impl SimSettings {
+    /// Values that would break the match, each naming the offending field. The ones of the config file are
+    /// checked when it loads, this is for settings coming from elsewhere.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        let size = self.court_size;
+        if !size.is_finite() || size.x <= 0. || size.y <= 0. {
+            problems.push(format!("court_size: must be positive, got {}", size));
+        }
+
+        // Power-ups spawn off the top and bottom walls, 4 units away
+        if !(self.unit_size > 0. && self.unit_size < size.y / 8.) {
+            problems.push(format!("unit_size: must be positive and below {}, got {}", size.y / 8., self.unit_size));
+        }
+
+        let positive = [
+            ("paddle_speed", self.paddle_speed),
+            ("ball_speed_min", self.ball_speed_min),
+            ("ball_speed_max", self.ball_speed_max),
+        ];
+        for (field, value) in positive {
+            if !(value.is_finite() && value > 0.) {
+                problems.push(format!("{}: must be positive, got {}", field, value));
+            }
+        }
+
+        let not_negative = [
+            ("ball_speed_incr", self.ball_speed_incr),
+            ("ball_oob_x", self.ball_oob_x),
+            ("forward_offset", self.forward_offset),
+        ];
+        for (field, value) in not_negative {
+            if !(value.is_finite() && value >= 0.) {
+                problems.push(format!("{}: must not be negative, got {}", field, value));
+            }
+        }
+
+        if self.ball_speed_min > self.ball_speed_max {
+            problems.push(format!("ball_speed_min: {} is above ball_speed_max {}", self.ball_speed_min, self.ball_speed_max));
+        }
+
+        if self.score_to_win == 0 {
+            problems.push("score_to_win: must be at least 1".to_string());
+        }
+
+        if self.arena != Arena::default() {
+            problems.extend(self.arena.validate().into_iter().chain(self.arena.validate_court(size)).map(|problem| format!("arena.{}", problem)));
+        }
+
+        problems
+    }
+}
+
+/// Moving box, the previous position is kept for render interpolation.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Body {
+    pub position: Vec2,
+    pub previous_position: Vec2,
+    pub size: Vec2,
+}
+
+impl Body {
+    pub fn new(position: Vec2, size: Vec2) -> Self {
+        Body { position, previous_position: position, size }
+    }
+
+    pub fn aabb(&self) -> Aabb {
+        Aabb::new(self.position, self.size)
+    }
+
+    /// Position between the previous and current tick, `alpha` going from 0 to 1.
+    pub fn interpolate(&self, alpha: f32) -> Vec2 {
+        self.previous_position.lerp(self.position, alpha)
+    }
+
+    /// Moves without interpolating from the previous position (e.g. resets).
+    pub fn snap(&mut self, position: Vec2) {
+        self.position = position;
+        self.previous_position = position;
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SimPaddle {
+    pub side: Side,
+    pub line: PaddleLine,
+    pub body: Body,
+    pub speed: f32,
+}
+
+impl SimPaddle {
+    /// Center along the side of the paddle: y on the left and right, x on the top and bottom.
+    pub fn position_along(&self) -> f32 {
+        if self.side.is_vertical() { self.body.position.y } else { self.body.position.x }
+    }
+
+    /// Size along the side of the paddle.
+    pub fn length(&self) -> f32 {
+        if self.side.is_vertical() { self.body.size.y } else { self.body.size.x }
+    }
+
+    /// Position of a ball waiting in front of the paddle, from its center: to be served, or caught by sticky.
+    pub fn front_offset(&self) -> Vec2 {
+        if self.side.is_vertical() {
+            Vec2::new(self.side.direction() * (self.body.size.x + 2.), 0.)
+        } else {
+            Vec2::new(0., self.side.vertical_direction() * (self.body.size.y + 2.))
+        }
+    }
+
+    /// Axis bringing the paddle center to `target` along its side at full speed,
+    /// proportional once within a tick of it so the paddle does not oscillate.
+    pub fn axis_towards(&self, target: f32, dt: f32) -> f32 {
+        let max_step = self.speed * dt;
+        if max_step <= 0. {
+            return 0.;
+        }
+
+        // Top and bottom paddles go left for up
+        let step = if self.side.is_vertical() {
+            target - self.body.position.y
+        } else {
+            self.body.position.x - target
+        };
+        (step / max_step).clamp(-1., 1.)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SimBall {
+    pub body: Body,
+    pub speed: f32,
+    pub velocity: Vec2,
+    /// Side of the paddle that served or hit the ball last, credited with the power-ups it collects.
+    pub last_hit: Option<Side>,
+    pub hold: Option<BallHold>,
+}
+
+/// Sticky: ball caught in front of a paddle, following it until served again. Its velocity is the one of the catch.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BallHold {
+    /// Index of the paddle in `PongSim::paddles`.
+    pub paddle: usize,
+    pub time: f32,
+}
+
+/// Moving obstacle of the arena, going to the point `next` of its path.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SimObstacle {
+    pub body: Body,
+    pub next: usize,
+}
+
+/// What a ball bounces off.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Collider {
+    /// Side of the paddle, and its index in `PongSim::paddles`.
+    Paddle(Side, usize),
+    Wall,
+    /// Arena bumper, multiplying the ball speed by its boost.
+    Bumper(f32),
+}
+
+/// Power-up waiting on the court for a ball.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SimPowerUp {
+    pub kind: PowerUpKind,
+    pub body: Body,
+}
+
+/// Power-up running for the side that collected it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SimEffect {
+    pub kind: PowerUpKind,
+    pub side: Side,
+    /// Seconds of rally left.
+    pub time_left: f32,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PaddleInput {
+    /// Movement along the paddle, from -1 (down) to 1 (up).
+    /// Top and bottom paddles go left for up and right for down.
+    pub axis: f32,
+    /// Launches the ball if this side is serving.
+    pub serve: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SimInputs {
+    pub left: PaddleInput,
+    pub right: PaddleInput,
+    /// Only recorded in four players, replays of the other modes stay as small.
+    #[serde(default, skip_serializing_if = "PaddleInput::is_idle")]
+    pub top: PaddleInput,
+    #[serde(default, skip_serializing_if = "PaddleInput::is_idle")]
+    pub bottom: PaddleInput,
+    /// Forward paddles of doubles.
+    #[serde(default, skip_serializing_if = "PaddleInput::is_idle")]
+    pub left_forward: PaddleInput,
+    #[serde(default, skip_serializing_if = "PaddleInput::is_idle")]
+    pub right_forward: PaddleInput,
+}
+
+impl PaddleInput {
+    pub fn is_idle(&self) -> bool {
+        *self == PaddleInput::default()
+    }
+}
+
+impl SimInputs {
+    pub fn get(&self, side: Side) -> &PaddleInput {
+        match side {
+            Side::Left => &self.left,
+            Side::Right => &self.right,
+            Side::Top => &self.top,
+            Side::Bottom => &self.bottom,
+        }
+    }
+
+    pub fn get_mut(&mut self, side: Side) -> &mut PaddleInput {
+        match side {
+            Side::Left => &mut self.left,
+            Side::Right => &mut self.right,
+            Side::Top => &mut self.top,
+            Side::Bottom => &mut self.bottom,
+        }
+    }
+
+    /// Inputs of a paddle of `side`, the top and bottom only having a back one.
+    pub fn get_at(&self, side: Side, line: PaddleLine) -> &PaddleInput {
+        match (side, line) {
+            (Side::Left, PaddleLine::Forward) => &self.left_forward,
+            (Side::Right, PaddleLine::Forward) => &self.right_forward,
+            _ => self.get(side),
+        }
+    }
+
+    pub fn get_at_mut(&mut self, side: Side, line: PaddleLine) -> &mut PaddleInput {
+        match (side, line) {
+            (Side::Left, PaddleLine::Forward) => &mut self.left_forward,
+            (Side::Right, PaddleLine::Forward) => &mut self.right_forward,
+            _ => self.get_mut(side),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SimEvent {
+    PaddleHit(Side),
+    WallHit,
+    /// The ball left the court on the given side.
+    BallOut(Side),
+    Scored(Side),
+    /// Scores are back to 0 for the next set, unless the match is over.
+    SetWon(Side),
+    /// The ball got past the paddle of a side, in four players.
+    LifeLost(Side),
+    /// Out of lives, the side is walled up.
+    Eliminated(Side),
+    Served(Side),
+    /// Multi-ball sent another ball from the middle of the court.
+    BallAdded,
+    PowerUpSpawned(PowerUpKind),
+    /// A ball last hit by the side collected the power-up.
+    PowerUpCollected(Side, PowerUpKind),
+    PowerUpEnded(Side, PowerUpKind),
+    GameOver(Side),
+}
+
+/// Headless Pong rules: paddles, ball, walls, serving and scoring.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PongSim {
+    pub settings: SimSettings,
+    pub mode: SimMode,
+    pub paddles: Vec<SimPaddle>,
+    pub walls: Vec<Aabb>,
+    pub obstacles: Vec<SimObstacle>,
+    /// Balls in play, never empty: the first one is the one served, multi-ball adds the others.
+    pub balls: Vec<SimBall>,
+    /// Paddle hits since the last serve.
+    pub rally_hits: u32,
+    pub power_up: Option<SimPowerUp>,
+    /// Seconds of rally since the last power-up was collected.
+    pub power_up_wait: f32,
+    pub effects: Vec<SimEffect>,
+    pub left_score: u32,
+    pub right_score: u32,
+    /// Sets won, the scores only count the points of the current set.
+    pub left_sets: u32,
+    pub right_sets: u32,
+    pub service: Option<Side>,
+    /// Seconds the serving side has been holding the ball.
+    pub service_wait: f32,
+    /// Side serving first in the current set, alternating serves count from it.
+    pub set_first_service: Side,
+    /// Lives left of each side in four players, in the order of `Side::ALL`.
+    pub lives: [u32; 4],
+    pub game_over: Option<Side>,
+    pub tick: u64,
+    /// Seed of `rng`, replaying the same inputs from the same seed gives the same match.
+    pub seed: u64,
+    rng: ChaCha8Rng,
+}
+
+impl PongSim {
+    pub fn new(settings: SimSettings, mode: SimMode, seed: u64) -> Self {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+
+        let width = settings.court_size.x;
+        let height = settings.court_size.y;
+        let unit_size = settings.unit_size;
+
+        let mut walls = vec![
+            // Top
+            Aabb::new(Vec2::new(0., height / 2. - unit_size / 2.), Vec2::new(width, unit_size)),
+            // Bottom
+            Aabb::new(Vec2::new(0., -height / 2. + unit_size / 2.), Vec2::new(width, unit_size)),
+        ];
+
+        let paddle_size = Vec2::new(unit_size, unit_size * 4.);
+        let paddle_x = width / 2. - unit_size / 2. - unit_size;
+        let mut paddles = vec![
+            SimPaddle {
+                side: Side::Left,
+                line: PaddleLine::Back,
+                body: Body::new(Vec2::new(-paddle_x, 0.), paddle_size),
+                speed: settings.paddle_speed,
+            },
+        ];
+
+        let ball_position: Vec2;
+        let starting_side: Side;
+        match mode {
+            SimMode::Versus | SimMode::Doubles => {
+                paddles.push(SimPaddle {
+                    side: Side::Right,
+                    line: PaddleLine::Back,
+                    body: Body::new(Vec2::new(paddle_x, 0.), paddle_size),
+                    speed: settings.paddle_speed,
+                });
+
+                if mode == SimMode::Doubles {
+                    for side in [Side::Left, Side::Right] {
+                        paddles.push(SimPaddle {
+                            side,
+                            line: PaddleLine::Forward,
+                            body: Body::new(Vec2::new(-side.direction() * (paddle_x - settings.forward_offset), 0.), paddle_size),
+                            speed: settings.paddle_speed,
+                        });
+                    }
+                }
+
+                ball_position = Vec2::ZERO;
+                starting_side = match settings.first_service {
+                    Some(side) => side,
+                    None => if rng.gen::<bool>() { Side::Left } else { Side::Right },
+                };
+            }
+            SimMode::Wall => {
+                walls.push(Aabb::new(Vec2::new(width / 2. - unit_size / 2., 0.), Vec2::new(unit_size, height)));
+
+                ball_position = Vec2::new(-128., -80.);
+                starting_side = Side::Left;
+            }
+            SimMode::FourPlayers => {
+                // The top and bottom are played, only the corners are walls
+                let corner_size = Vec2::splat(unit_size * 2.);
+                let corner = Vec2::new(width, height) / 2. - corner_size / 2.;
+                walls = [(-1., -1.), (-1., 1.), (1., -1.), (1., 1.)]
+                    .into_iter()
+                    .map(|(x, y)| Aabb::new(corner * Vec2::new(x, y), corner_size))
+                    .collect();
+
+                let paddle_y = height / 2. - unit_size / 2. - unit_size;
+                paddles.push(SimPaddle {
+                    side: Side::Right,
+                    line: PaddleLine::Back,
+                    body: Body::new(Vec2::new(paddle_x, 0.), paddle_size),
+                    speed: settings.paddle_speed,
+                });
+                for side in [Side::Top, Side::Bottom] {
+                    paddles.push(SimPaddle {
+                        side,
+                        line: PaddleLine::Back,
+                        body: Body::new(Vec2::new(0., -side.vertical_direction() * paddle_y), Vec2::new(paddle_size.y, paddle_size.x)),
+                        speed: settings.paddle_speed,
+                    });
+                }
+
+                ball_position = Vec2::ZERO;
+                starting_side = Side::ALL[rng.gen_range(0..Side::ALL.len())];
+            }
+        }
+
+        // Arenas add to the court of versus
+        let mut obstacles = Vec::new();
+        if matches!(mode, SimMode::Versus | SimMode::Doubles) {
+            walls.extend(settings.arena.walls.iter().map(|wall| Aabb::new(wall.center, wall.size)));
+
+            if let Some(goal_height) = settings.arena.goal_height.filter(|&goal_height| goal_height < height) {
+                let edge_height = (height - goal_height) / 2.;
+                for side in [Side::Left, Side::Right] {
+                    for sign in [-1., 1.] {
+                        let center = Vec2::new(-side.direction() * (width / 2. - unit_size / 2.), sign * (height - edge_height) / 2.);
+                        walls.push(Aabb::new(center, Vec2::new(unit_size, edge_height)));
+                    }
+                }
+            }
+
+            obstacles = settings.arena.obstacles
+                .iter()
+                .map(|obstacle| SimObstacle {
+                    body: Body::new(obstacle.path[0], obstacle.size),
+                    next: 1 % obstacle.path.len(),
+                })
+                .collect();
+        }
+
+        let lives = [settings.rules.lives; 4];
+
+        let ball = SimBall {
+            body: Body::new(ball_position, Vec2::splat(unit_size)),
+            speed: settings.ball_speed_min,
+            velocity: Vec2::ZERO,
+            last_hit: None,
+            hold: None,
+        };
+
+        PongSim {
+            settings,
+            mode,
+            paddles,
+            walls,
+            obstacles,
+            balls: vec![ball],
+            rally_hits: 0,
+            power_up: None,
+            power_up_wait: 0.,
+            effects: Vec::new(),
+            left_score: 0,
+            right_score: 0,
+            left_sets: 0,
+            right_sets: 0,
+            service: Some(starting_side),
+            service_wait: 0.,
+            set_first_service: starting_side,
+            lives,
+            game_over: None,
+            tick: 0,
+            seed,
+            rng,
+        }
+    }
+
+    /// Ball served, the only one outside multi-ball.
+    pub fn ball(&self) -> &SimBall {
+        &self.balls[0]
+    }
+
+    /// Back paddle of `side`, the only one outside doubles.
+    pub fn paddle(&self, side: Side) -> Option<&SimPaddle> {
+        self.paddle_at(side, PaddleLine::Back)
+    }
+
+    pub fn paddle_at(&self, side: Side, line: PaddleLine) -> Option<&SimPaddle> {
+        self.paddles.iter().find(|paddle| paddle.side == side && paddle.line == line)
+    }
+
+    /// Whether power-ups appear in this match, only in versus.
+    fn power_ups_enabled(&self) -> bool {
+        matches!(self.mode, SimMode::Versus | SimMode::Doubles) && !self.settings.rules.power_ups.is_empty()
+    }
+
+    /// Whether `side` collected a power-up of `kind` still running.
+    pub fn has_effect(&self, side: Side, kind: PowerUpKind) -> bool {
+        self.effects.iter().any(|effect| effect.side == side && effect.kind == kind)
+    }
+
+    /// Sides whose goal a shield closes, see `side_wall`.
+    pub fn shields(&self) -> impl Iterator<Item = Side> + '_ {
+        self.effects.iter().filter(|effect| effect.kind == PowerUpKind::Shield).map(|effect| effect.side)
+    }
+
+    pub fn score(&self, side: Side) -> u32 {
+        match side {
+            Side::Left => self.left_score,
+            Side::Right => self.right_score,
+            Side::Top | Side::Bottom => 0,
+        }
+    }
+
+    pub fn sets(&self, side: Side) -> u32 {
+        match side {
+            Side::Left => self.left_sets,
+            Side::Right => self.right_sets,
+            Side::Top | Side::Bottom => 0,
+        }
+    }
+
+    pub fn lives(&self, side: Side) -> u32 {
+        self.lives[side as usize]
+    }
+
+    /// Sides still playing in four players.
+    pub fn alive_sides(&self) -> impl Iterator<Item = Side> + '_ {
+        Side::ALL.into_iter().filter(|&side| self.lives(side) > 0)
+    }
+
+    /// Whether `side` takes the current set with its score.
+    fn set_won(&self, side: Side) -> bool {
+        let score = self.score(side);
+        let opponent_score = self.score(side.opponent());
+
+        score >= self.settings.score_to_win && (!self.settings.rules.win_by_two || score >= opponent_score + 2)
+    }
+
+    /// Side serving the next point, once `scorer` got the last one.
+    fn next_service(&self, scorer: Side) -> Side {
+        match self.settings.rules.serve {
+            ServeRule::Winner => scorer,
+            ServeRule::Loser => scorer.opponent(),
+            ServeRule::Alternate { points } => {
+                let points = points.max(1);
+                let played = self.left_score + self.right_score;
+                let deuce = self.settings.score_to_win.saturating_sub(1) * 2;
+
+                let turns = if self.settings.rules.win_by_two && played >= deuce {
+                    deuce / points + (played - deuce)
+                } else {
+                    played / points
+                };
+
+                if turns % 2 == 0 { self.set_first_service } else { self.set_first_service.opponent() }
+            }
+        }
+    }
+
+    /// Highest y reachable by the center of a paddle of the given height.
+    pub fn paddle_bound_y(&self, paddle_height: f32) -> f32 {
+        self.settings.court_size.y / 2. - self.settings.unit_size - paddle_height / 2.
+    }
+
+    /// Furthest a paddle center goes from the middle of its side, along it.
+    /// In four players, paddles stop at the corner walls.
+    pub fn paddle_bound(&self, paddle: &SimPaddle) -> f32 {
+        if self.mode != SimMode::FourPlayers {
+            return self.paddle_bound_y(paddle.body.size.y);
+        }
+
+        let unit_size = self.settings.unit_size;
+        if paddle.side.is_vertical() {
+            self.settings.court_size.y / 2. - unit_size * 2. - paddle.body.size.y / 2.
+        } else {
+            self.settings.court_size.x / 2. - unit_size * 2. - paddle.body.size.x / 2.
+        }
+    }
+
+    /// Wall replacing the paddle of an eliminated side, on the edge of the court.
+    pub fn side_wall(&self, side: Side) -> Aabb {
+        let size = self.settings.court_size;
+        let unit_size = self.settings.unit_size;
+
+        if side.is_vertical() {
+            Aabb::new(Vec2::new(-side.direction() * (size.x / 2. - unit_size / 2.), 0.), Vec2::new(unit_size, size.y))
+        } else {
+            Aabb::new(Vec2::new(0., -side.vertical_direction() * (size.y / 2. - unit_size / 2.)), Vec2::new(size.x, unit_size))
+        }
+    }
+
+    /// Applies new speeds to a match in progress, the court and the rules are kept.
+    pub fn retune(&mut self, settings: &SimSettings) {
+        self.settings.paddle_speed = settings.paddle_speed;
+        self.settings.ball_speed_min = settings.ball_speed_min;
+        self.settings.ball_speed_max = settings.ball_speed_max;
+        self.settings.ball_speed_incr = settings.ball_speed_incr;
+        self.settings.ball_oob_x = settings.ball_oob_x;
+
+        for paddle in self.paddles.iter_mut() {
+            paddle.speed = settings.paddle_speed;
+        }
+
+        for ball in self.balls.iter_mut() {
+            ball.speed = ball.speed.clamp(settings.ball_speed_min, settings.ball_speed_max);
+            if ball.velocity != Vec2::ZERO {
+                ball.velocity = ball.velocity.normalize() * ball.speed;
+            }
+        }
+    }
+
+    /// Describes the first broken rule of the court (ball through a wall, paddle off the court...), if any.
+    pub fn check_invariants(&self) -> Option<String> {
+        let bodies = self.paddles.iter().map(|paddle| &paddle.body).chain(self.balls.iter().map(|ball| &ball.body));
+        for body in bodies {
+            if !body.position.is_finite() {
+                return Some(format!("body at non finite position {}", body.position));
+            }
+        }
+
+        for paddle in self.paddles.iter() {
+            let bound = self.paddle_bound(paddle);
+            let position = if paddle.side.is_vertical() { paddle.body.position.y } else { paddle.body.position.x };
+            if position.abs() > bound + COLLISION_TOLERANCE {
+                return Some(format!("{:?} paddle off the court at {}", paddle.side, paddle.body.position));
+            }
+        }
+
+        // The top and bottom of four players are open
+        if self.mode == SimMode::FourPlayers {
+            return None;
+        }
+
+        for ball in self.balls.iter() {
+            // Past the left or right edge, a ball is on its way out above or below the walls
+            let in_court = ball.body.position.x.abs() <= self.settings.court_size.x / 2.;
+            let ball_bound_y = self.paddle_bound_y(ball.body.size.y);
+            if in_court && ball.body.position.y.abs() > ball_bound_y + COLLISION_TOLERANCE {
+                return Some(format!("ball through a wall at y {}", ball.body.position.y));
+            }
+
+            if self.mode == SimMode::Wall {
+                let wall_x = self.settings.court_size.x / 2. - self.settings.unit_size - ball.body.size.x / 2.;
+                if ball.body.position.x > wall_x + COLLISION_TOLERANCE {
+                    return Some(format!("ball through the right wall at x {}", ball.body.position.x));
+                }
+            }
+        }
+
+        None
+    }
+
+    /// FNV-1a hash of the match state, compared between online peers to detect desyncs.
+    pub fn checksum(&self) -> u64 {
+        let mut hash: u64 = 0xcbf29ce484222325;
+        let mut write = |value: u64| {
+            for byte in value.to_le_bytes() {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(0x100000001b3);
+            }
+        };
+
+        write(self.tick);
+        for paddle in self.paddles.iter() {
+            write(paddle.body.position.x.to_bits() as u64);
+            write(paddle.body.position.y.to_bits() as u64);
+        }
+        for obstacle in self.obstacles.iter() {
+            write(obstacle.body.position.x.to_bits() as u64);
+            write(obstacle.body.position.y.to_bits() as u64);
+            write(obstacle.next as u64);
+        }
+        for ball in self.balls.iter() {
+            write(ball.body.position.x.to_bits() as u64);
+            write(ball.body.position.y.to_bits() as u64);
+            write(ball.velocity.x.to_bits() as u64);
+            write(ball.velocity.y.to_bits() as u64);
+            write(ball.speed.to_bits() as u64);
+            write(ball.last_hit.map_or(0, |side| side as u64 + 1));
+            if let Some(hold) = ball.hold {
+                write(hold.paddle as u64);
+                write(hold.time.to_bits() as u64);
+            }
+        }
+        write(self.rally_hits as u64);
+        if let Some(power_up) = &self.power_up {
+            write(power_up.kind as u64);
+            write(power_up.body.position.x.to_bits() as u64);
+            write(power_up.body.position.y.to_bits() as u64);
+        }
+        write(self.power_up_wait.to_bits() as u64);
+        for effect in self.effects.iter() {
+            write(effect.kind as u64);
+            write(effect.side as u64);
+            write(effect.time_left.to_bits() as u64);
+        }
+        write(self.left_score as u64);
+        write(self.right_score as u64);
+        write(self.left_sets as u64);
+        write(self.right_sets as u64);
+        write(self.service.map_or(0, |side| side as u64 + 1));
+        write(self.service_wait.to_bits() as u64);
+        write(self.set_first_service as u64);
+        for lives in self.lives {
+            write(lives as u64);
+        }
+        write(self.game_over.map_or(0, |side| side as u64 + 1));
+        write(self.rng.get_word_pos() as u64);
+
+        hash
+    }
+
+    /// Advances the simulation by `dt` seconds and returns what happened during the tick.
+    pub fn step(&mut self, inputs: &SimInputs, dt: f32) -> Vec<SimEvent> {
+        let mut events = Vec::new();
+
+        self.tick += 1;
+
+        for paddle in self.paddles.iter_mut() {
+            paddle.body.previous_position = paddle.body.position;
+        }
+        for ball in self.balls.iter_mut() {
+            ball.body.previous_position = ball.body.position;
+        }
+        for obstacle in self.obstacles.iter_mut() {
+            obstacle.body.previous_position = obstacle.body.position;
+        }
+
+        self.update_power_ups(dt, &mut events);
+        self.move_paddles(inputs, dt);
+        self.move_obstacles(dt);
+        self.hold_balls(inputs, dt);
+        self.serve(inputs, dt, &mut events);
+        // Balls added on the way start moving on the next tick
+        for index in 0..self.balls.len() {
+            self.move_ball(index, dt, &mut events);
+        }
+        self.collect_power_up(&mut events);
+        self.check_balls_out(&mut events);
+
+        events
+    }
+
+    fn move_paddles(&mut self, inputs: &SimInputs, dt: f32) {
+        for index in 0..self.paddles.len() {
+            let paddle = &self.paddles[index];
+            let axis = inputs.get_at(paddle.side, paddle.line).axis.clamp(-1., 1.);
+            let bound = self.paddle_bound(paddle);
+            let speed = if self.has_effect(paddle.side, PowerUpKind::SpeedBoost) {
+                paddle.speed * SPEED_BOOST_FACTOR
+            } else {
+                paddle.speed
+            };
+
+            let paddle = &mut self.paddles[index];
+            if paddle.side.is_vertical() {
+                let y = paddle.body.position.y + axis * speed * dt;
+                paddle.body.position.y = y.min(bound).max(-bound);
+            } else {
+                let x = paddle.body.position.x - axis * speed * dt;
+                paddle.body.position.x = x.min(bound).max(-bound);
+            }
+        }
+    }
+
+    /// Arena obstacles go along their path, passing several points in a tick if they have to.
+    fn move_obstacles(&mut self, dt: f32) {
+        for (obstacle, arena_obstacle) in self.obstacles.iter_mut().zip(self.settings.arena.obstacles.iter()) {
+            let path = &arena_obstacle.path;
+            let mut step = arena_obstacle.speed * dt;
+
+            for _ in 0..path.len() {
+                let to_next = path[obstacle.next] - obstacle.body.position;
+                let distance = to_next.length();
+                if distance > step {
+                    obstacle.body.position += to_next / distance * step;
+                    break;
+                }
+
+                obstacle.body.position = path[obstacle.next];
+                obstacle.next = (obstacle.next + 1) % path.len();
+                step -= distance;
+            }
+        }
+    }
+
+    /// Sticky: caught balls follow their paddle until it serves them, or holds them too long.
+    fn hold_balls(&mut self, inputs: &SimInputs, dt: f32) {
+        for ball in self.balls.iter_mut() {
+            let Some(hold) = ball.hold.as_mut() else {
+                continue;
+            };
+
+            let paddle = &self.paddles[hold.paddle];
+            hold.time += dt;
+            if inputs.get_at(paddle.side, paddle.line).serve || hold.time >= STICKY_HOLD_TIME {
+                ball.hold = None;
+                continue;
+            }
+
+            let offset = paddle.front_offset();
+            ball.body.position = paddle.body.position + offset;
+            ball.body.previous_position = paddle.body.previous_position + offset;
+        }
+    }
+
+    fn serve(&mut self, inputs: &SimInputs, dt: f32, events: &mut Vec<SimEvent>) {
+        let Some(side) = self.service else {
+            return;
+        };
+
+        // The first serve waits for the players to read the instructions
+        self.service_wait += dt;
+        let first_point = self.left_score + self.right_score + self.left_sets + self.right_sets == 0;
+        let timed_out = match self.settings.rules.serve_time_limit {
+            Some(limit) => !first_point && self.service_wait >= limit as f32,
+            None => false,
+        };
+
+        if self.game_over.is_none() && (inputs.get(side).serve || timed_out) {
+            let spin = if self.rng.gen::<bool>() { -0.25 } else { 0.25 };
+            let mut direction = if side.is_vertical() {
+                Vec2::new(side.direction(), spin)
+            } else {
+                Vec2::new(spin, side.vertical_direction())
+            };
+            direction = direction.normalize();
+
+            let ball = &mut self.balls[0];
+            ball.velocity = direction * ball.speed;
+            ball.last_hit = Some(side);
+            self.service = None;
+            self.service_wait = 0.;
+            self.rally_hits = 0;
+
+            events.push(SimEvent::Served(side));
+            return;
+        }
+
+        // Outside wall mode, the ball sticks to the serving paddle, following its interpolation too
+        if self.mode != SimMode::Wall {
+            if let Some(paddle) = self.paddle(side) {
+                let offset = paddle.front_offset();
+                let position = paddle.body.position + offset;
+                let previous_position = paddle.body.previous_position + offset;
+
+                let ball = &mut self.balls[0];
+                ball.body.position = position;
+                ball.body.previous_position = previous_position;
+            }
+        }
+    }
+
+    fn move_ball(&mut self, index: usize, dt: f32, events: &mut Vec<SimEvent>) {
+        if self.balls[index].velocity == Vec2::ZERO || self.balls[index].hold.is_some() {
+            return;
+        }
+
+        let velocity = self.balls[index].velocity;
+        let slowed = self.effects
+            .iter()
+            .any(|effect| effect.kind == PowerUpKind::SlowBall && velocity.dot(effect.side.normal()) < 0.);
+        let dt = if slowed { dt * SLOW_BALL_FACTOR } else { dt };
+
+        let colliders: Vec<(Collider, Aabb)> = self.paddles
+            .iter()
+            .enumerate()
+            .map(|(paddle_index, paddle)| (Collider::Paddle(paddle.side, paddle_index), paddle.body.aabb()))
+            .chain(self.walls.iter().map(|wall| (Collider::Wall, *wall)))
+            .chain(self.shields().map(|side| (Collider::Wall, self.side_wall(side))))
+            .chain(self.obstacles.iter().map(|obstacle| (Collider::Wall, obstacle.body.aabb())))
+            .chain(self.settings.arena.bumpers.iter().map(|bumper| (Collider::Bumper(bumper.boost), Aabb::new(bumper.center, bumper.size))))
+            .collect();
+
+        // A paddle or an obstacle may have moved into the ball since the last tick: push the ball out first
+        for (collider, collider_aabb) in colliders.iter() {
+            if self.passes_through(index, *collider) {
+                continue;
+            }
+
+            if let Some(push) = overlap_aabb(&self.balls[index].body.aabb(), collider_aabb) {
+                self.balls[index].body.position += push;
+
+                let normal = push.normalize();
+                if self.balls[index].velocity.dot(normal) < 0. {
+                    self.bounce_ball(index, *collider, collider_aabb, normal, events);
+                }
+            }
+        }
+
+        // Sweep the whole tick displacement, bouncing on the earliest contact each time
+        let mut remaining = dt;
+        for _ in 0..MAX_BALL_BOUNCES {
+            // Caught by a sticky paddle
+            if self.balls[index].hold.is_some() {
+                return;
+            }
+
+            let ball_aabb = self.balls[index].body.aabb();
+            let displacement = self.balls[index].velocity * remaining;
+
+            let earliest_hit = colliders
+                .iter()
+                .filter(|(collider, _)| !self.passes_through(index, *collider))
+                .filter_map(|(collider, collider_aabb)| {
+                    sweep_aabb(&ball_aabb, displacement, collider_aabb).map(|hit| (hit, *collider, collider_aabb))
+                })
+                .min_by(|a, b| a.0.time.total_cmp(&b.0.time));
+
+            let Some((hit, collider, collider_aabb)) = earliest_hit else {
+                self.balls[index].body.position += displacement;
+                return;
+            };
+
+            self.balls[index].body.position += displacement * hit.time;
+            remaining *= 1. - hit.time;
+
+            self.bounce_ball(index, collider, collider_aabb, hit.normal, events);
+        }
+    }
+
+    /// In doubles, a ball going away from a side goes through its paddles, so a team never blocks its own shots.
+    fn passes_through(&self, index: usize, collider: Collider) -> bool {
+        match collider {
+            Collider::Paddle(side, _) => self.mode == SimMode::Doubles && self.balls[index].velocity.dot(side.normal()) > 0.,
+            Collider::Wall | Collider::Bumper(_) => false,
+        }
+    }
+
+    /// Applies the collision response to the ball velocity.
+    fn bounce_ball(&mut self, index: usize, collider: Collider, collider_aabb: &Aabb, normal: Vec2, events: &mut Vec<SimEvent>) {
+        let ball = &mut self.balls[index];
+        let mut front_hit = false;
+
+        match collider {
+            // Hitting the front of a paddle: angle depends on where the ball touched it
+            Collider::Paddle(side, _) if side.is_vertical() && normal.x != 0. => {
+                let hit_factor = (ball.body.position.y - collider_aabb.center.y) / (collider_aabb.half_size.y * 2.);
+
+                let mut new_ball_vel = Vec2::default();
+                new_ball_vel.x = if ball.velocity.x > 0. { -1. } else { 1. };
+                new_ball_vel.y = hit_factor * 2.;
+                new_ball_vel = new_ball_vel.normalize();
+
+                ball.velocity = new_ball_vel * ball.speed;
+
+                if self.settings.ball_speed_max > ball.speed {
+                    ball.speed += self.settings.ball_speed_incr;
+                }
+                front_hit = true;
+            }
+            // Same for the top and bottom paddles, turned a quarter
+            Collider::Paddle(side, _) if !side.is_vertical() && normal.y != 0. => {
+                let hit_factor = (ball.body.position.x - collider_aabb.center.x) / (collider_aabb.half_size.x * 2.);
+
+                let new_ball_vel = Vec2::new(hit_factor * 2., if ball.velocity.y > 0. { -1. } else { 1. }).normalize();
+
+                ball.velocity = new_ball_vel * ball.speed;
+
+                if self.settings.ball_speed_max > ball.speed {
+                    ball.speed += self.settings.ball_speed_incr;
+                }
+                front_hit = true;
+            }
+            _ => {
+                // Setting new velocity for "bounce" effect
+                if 0. != normal.x {
+                    ball.velocity.x = -ball.velocity.x;
+                }
+
+                if 0. != normal.y {
+                    ball.velocity.y = -ball.velocity.y;
+                }
+            }
+        }
+
+        match collider {
+            Collider::Paddle(side, paddle) => {
+                events.push(SimEvent::PaddleHit(side));
+                self.rally_hits += 1;
+                self.balls[index].last_hit = Some(side);
+
+                // Sticky paddles catch the balls hitting their front, one at a time: the others bounce as usual
+                if front_hit && self.has_effect(side, PowerUpKind::Sticky) {
+                    let holding = self.balls.iter().any(|ball| ball.hold.is_some_and(|hold| hold.paddle == paddle));
+                    if !holding {
+                        self.balls[index].hold = Some(BallHold { paddle, time: 0. });
+                    }
+                }
+
+                // In wall mode, every paddle hit scores
+                if self.mode == SimMode::Wall && self.game_over.is_none() {
+                    self.left_score += 1;
+                    events.push(SimEvent::Scored(Side::Left));
+                }
+
+                self.add_ball_if_due(events);
+            }
+            Collider::Wall => events.push(SimEvent::WallHit),
+            Collider::Bumper(boost) => {
+                // Up to the maximum speed, a ball already past it keeps its speed
+                let ball = &mut self.balls[index];
+                ball.speed = (ball.speed * boost).min(self.settings.ball_speed_max.max(ball.speed));
+                ball.velocity = ball.velocity.normalize() * ball.speed;
+                events.push(SimEvent::WallHit);
+            }
+        }
+    }
+
+    /// Multi-ball: every `MatchRules::multi_ball` paddle hits of a rally, a ball leaves the middle of the court.
+    fn add_ball_if_due(&mut self, events: &mut Vec<SimEvent>) {
+        let Some(hits) = self.settings.rules.multi_ball else {
+            return;
+        };
+
+        let versus = matches!(self.mode, SimMode::Versus | SimMode::Doubles);
+        if !versus || self.rally_hits.checked_rem(hits) != Some(0) || self.balls.len() >= MAX_BALLS {
+            return;
+        }
+
+        let side = if self.rng.gen::<bool>() { Side::Left } else { Side::Right };
+        self.launch_extra_ball(side, events);
+    }
+
+    /// Sends a ball from the middle of the court, as if `side` served it.
+    fn launch_extra_ball(&mut self, side: Side, events: &mut Vec<SimEvent>) {
+        if self.balls.len() >= MAX_BALLS {
+            return;
+        }
+
+        let spin = if self.rng.gen::<bool>() { -0.25 } else { 0.25 };
+        let speed = self.settings.ball_speed_min;
+
+        self.balls.push(SimBall {
+            body: Body::new(Vec2::ZERO, Vec2::splat(self.settings.unit_size)),
+            speed,
+            velocity: Vec2::new(side.direction(), spin).normalize() * speed,
+            last_hit: None,
+            hold: None,
+        });
+        events.push(SimEvent::BallAdded);
+    }
+
+    /// Power-ups: during rallies, effects run out and a new item appears once in a while.
+    /// Paddles take the length their effects give them.
+    fn update_power_ups(&mut self, dt: f32, events: &mut Vec<SimEvent>) {
+        if !self.power_ups_enabled() || self.service.is_some() {
+            return;
+        }
+
+        for effect in self.effects.iter_mut() {
+            effect.time_left -= dt;
+        }
+        for effect in self.effects.iter().filter(|effect| effect.time_left <= 0.) {
+            events.push(SimEvent::PowerUpEnded(effect.side, effect.kind));
+        }
+        self.effects.retain(|effect| effect.time_left > 0.);
+
+        let length = self.settings.unit_size * 4.;
+        for index in 0..self.paddles.len() {
+            let side = self.paddles[index].side;
+            let mut factor = 1.;
+            if self.has_effect(side, PowerUpKind::Enlarge) {
+                factor *= ENLARGE_FACTOR;
+            }
+            if self.has_effect(side.opponent(), PowerUpKind::Shrink) {
+                factor *= SHRINK_FACTOR;
+            }
+            self.paddles[index].body.size.y = length * factor;
+        }
+
+        if self.power_up.is_some() {
+            return;
+        }
+
+        self.power_up_wait += dt;
+        if self.power_up_wait < POWER_UP_INTERVAL {
+            return;
+        }
+
+        // Anywhere around the net, off the walls, if the court leaves room for it
+        let unit_size = self.settings.unit_size;
+        let bound_y = self.settings.court_size.y / 2. - unit_size * 4.;
+        if bound_y <= 0. {
+            return;
+        }
+
+        let kinds: Vec<PowerUpKind> = self.settings.rules.power_ups.kinds().collect();
+        let kind = kinds[self.rng.gen_range(0..kinds.len())];
+        let x = self.rng.gen_range(-0.25..0.25) * self.settings.court_size.x;
+        let y = self.rng.gen_range(-bound_y..bound_y);
+
+        self.power_up = Some(SimPowerUp {
+            kind,
+            body: Body::new(Vec2::new(x, y), Vec2::new(unit_size * 6., unit_size * 2.)),
+        });
+        self.power_up_wait = 0.;
+        events.push(SimEvent::PowerUpSpawned(kind));
+    }
+
+    /// The first ball touching the power-up collects it for the side that hit it last.
+    /// Collecting a running effect again starts it over.
+    fn collect_power_up(&mut self, events: &mut Vec<SimEvent>) {
+        let Some(power_up) = &self.power_up else {
+            return;
+        };
+
+        let power_up_aabb = power_up.body.aabb();
+        let collector = self.balls
+            .iter()
+            .filter(|ball| overlap_aabb(&ball.body.aabb(), &power_up_aabb).is_some())
+            .find_map(|ball| ball.last_hit);
+        let Some(side) = collector else {
+            return;
+        };
+
+        let kind = power_up.kind;
+        self.power_up = None;
+        events.push(SimEvent::PowerUpCollected(side, kind));
+
+        if kind == PowerUpKind::MultiBall {
+            self.launch_extra_ball(side, events);
+            return;
+        }
+
+        self.effects.retain(|effect| effect.side != side || effect.kind != kind);
+        self.effects.push(SimEffect { kind, side, time_left: kind.duration() });
+    }
+
+    /// Side whose edge of the court `ball` is past, if any.
+    fn ball_out_side(&self, ball: &SimBall) -> Option<Side> {
+        let limit = self.settings.court_size / 2. + self.settings.ball_oob_x;
+        let ball_position = ball.body.position;
+
+        if ball_position.x < -limit.x {
+            Some(Side::Left)
+        } else if ball_position.x > limit.x {
+            Some(Side::Right)
+        } else if self.mode == SimMode::FourPlayers && ball_position.y > limit.y {
+            Some(Side::Top)
+        } else if self.mode == SimMode::FourPlayers && ball_position.y < -limit.y {
+            Some(Side::Bottom)
+        } else {
+            None
+        }
+    }
+
+    /// Every ball leaving the court scores, the point is over once the last one is out.
+    fn check_balls_out(&mut self, events: &mut Vec<SimEvent>) {
+        let mut index = 0;
+        while index < self.balls.len() {
+            if self.game_over.is_some() {
+                return;
+            }
+
+            let Some(out_side) = self.ball_out_side(&self.balls[index]) else {
+                index += 1;
+                continue;
+            };
+
+            events.push(SimEvent::BallOut(out_side));
+
+            // The other balls keep the rally going
+            let last_ball = self.balls.len() == 1;
+            if !last_ball {
+                self.balls.remove(index);
+            }
+
+            self.ball_out(out_side, last_ball, events);
+
+            if last_ball || self.service.is_some() {
+                return;
+            }
+        }
+    }
+
+    fn ball_out(&mut self, out_side: Side, last_ball: bool, events: &mut Vec<SimEvent>) {
+        if self.mode == SimMode::FourPlayers {
+            self.lose_life(out_side, events);
+            return;
+        }
+
+        if self.mode == SimMode::Wall {
+            self.game_over = Some(Side::Right);
+            events.push(SimEvent::GameOver(Side::Right));
+            return;
+        }
+
+        let scorer = out_side.opponent();
+        let score = match scorer {
+            Side::Left => &mut self.left_score,
+            Side::Right => &mut self.right_score,
+            // Only four players lets the ball out at the top or bottom
+            Side::Top | Side::Bottom => return,
+        };
+        *score += 1;
+        events.push(SimEvent::Scored(scorer));
+
+        if self.set_won(scorer) {
+            let sets = match scorer {
+                Side::Left => &mut self.left_sets,
+                Side::Right => &mut self.right_sets,
+                Side::Top | Side::Bottom => return,
+            };
+            *sets += 1;
+            events.push(SimEvent::SetWon(scorer));
+
+            if *sets >= self.settings.rules.sets_to_win() {
+                self.game_over = Some(scorer);
+                events.push(SimEvent::GameOver(scorer));
+                return;
+            }
+
+            self.left_score = 0;
+            self.right_score = 0;
+
+            if let ServeRule::Alternate { .. } = self.settings.rules.serve {
+                self.set_first_service = self.set_first_service.opponent();
+            }
+        } else if !last_ball {
+            return;
+        }
+
+        self.service = Some(self.next_service(scorer));
+        self.reset_point();
+    }
+
+    /// Stops the ball until the next serve, with every paddle back in the middle of its side.
+    /// Extra balls of multi-ball are dropped.
+    fn reset_point(&mut self) {
+        self.balls.truncate(1);
+        self.balls[0].velocity = Vec2::ZERO;
+        self.balls[0].hold = None;
+
+        for paddle in self.paddles.iter_mut() {
+            let position = if paddle.side.is_vertical() {
+                Vec2::new(paddle.body.position.x, 0.)
+            } else {
+                Vec2::new(0., paddle.body.position.y)
+            };
+            paddle.body.snap(position);
+        }
+    }
+
+    /// Four players: `side` let the ball through, it serves next unless that was its last life.
+    fn lose_life(&mut self, side: Side, events: &mut Vec<SimEvent>) {
+        let lives = &mut self.lives[side as usize];
+        *lives = lives.saturating_sub(1);
+        events.push(SimEvent::LifeLost(side));
+
+        if *lives == 0 {
+            self.paddles.retain(|paddle| paddle.side != side);
+            self.walls.push(self.side_wall(side));
+            events.push(SimEvent::Eliminated(side));
+        }
+
+        let alive: Vec<Side> = self.alive_sides().collect();
+        if let [winner] = alive[..] {
+            self.game_over = Some(winner);
+            events.push(SimEvent::GameOver(winner));
+            return;
+        }
+
+        let mut server = side;
+        while self.lives(server) == 0 {
+            server = server.clockwise();
+        }
+
+        self.service = Some(server);
+        self.reset_point();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f32 = 1. / 120.;
+
+    fn settings() -> SimSettings {
+        SimSettings {
+            court_size: Vec2::new(768., 576.),
+            unit_size: 16.,
+            paddle_speed: 400.,
+            ball_speed_min: 500.,
+            ball_speed_max: 750.,
+            ball_speed_incr: 5.,
+            ball_oob_x: 200.,
+            score_to_win: 9,
+            rules: MatchRules::default(),
+            first_service: None,
+            forward_offset: 192.,
+            arena: Arena::default(),
+        }
+    }
+
+    /// Versus rally with the ball at `position` going along `direction` at the maximum speed.
+    fn fast_rally(position: Vec2, direction: Vec2) -> PongSim {
+        rally(SimMode::Versus, position, direction)
+    }
+
+    fn rally(mode: SimMode, position: Vec2, direction: Vec2) -> PongSim {
+        let mut sim = PongSim::new(settings(), mode, 3);
+        sim.service = None;
+
+        let ball = &mut sim.balls[0];
+        ball.body.snap(position);
+        ball.speed = sim.settings.ball_speed_max;
+        ball.velocity = direction.normalize() * ball.speed;
+        sim
+    }
+
+    #[test]
+    fn fast_ball_never_goes_through_a_paddle() {
+        for dt in [1. / 30., 0.1, 0.25, 0.5] {
+            let mut sim = fast_rally(Vec2::new(330., 0.), Vec2::X);
+            let paddle_x = sim.paddle(Side::Right).unwrap().body.position.x;
+
+            let events = sim.step(&SimInputs::default(), dt);
+
+            assert!(events.contains(&SimEvent::PaddleHit(Side::Right)), "no hit with dt {}", dt);
+            assert!(sim.ball().velocity.x < 0.);
+            assert!(sim.ball().body.position.x < paddle_x);
+        }
+    }
+
+    #[test]
+    fn wall_then_paddle_in_one_step() {
+        let mut sim = fast_rally(Vec2::new(280., 230.), Vec2::ONE);
+        let bound_y = sim.paddle_bound_y(sim.paddles[1].body.size.y);
+        let paddle_x = sim.paddles[1].body.position.x;
+        sim.paddles[1].body.snap(Vec2::new(paddle_x, bound_y));
+
+        let events = sim.step(&SimInputs::default(), 0.2);
+
+        assert_eq!(events, vec![SimEvent::WallHit, SimEvent::PaddleHit(Side::Right)]);
+        assert!(sim.ball().velocity.x < 0.);
+        assert!(sim.ball().velocity.y < 0.);
+        // Still under the top wall
+        assert!(sim.ball().body.position.y <= 576. / 2. - 16. - 8.);
+    }
+
+    /// Sends the ball out on the given side within the next step.
+    fn miss(sim: &mut PongSim, side: Side) -> Vec<SimEvent> {
+        let limit = sim.settings.court_size / 2. + sim.settings.ball_oob_x - 1.;
+        sim.service = None;
+        sim.balls[0].body.snap(-side.normal() * limit);
+        sim.balls[0].velocity = -side.normal() * sim.balls[0].speed;
+
+        sim.step(&SimInputs::default(), DT)
+    }
+
+    #[test]
+    fn ball_out_scores_for_the_opponent() {
+        let mut sim = PongSim::new(settings(), SimMode::Versus, 3);
+
+        let events = miss(&mut sim, Side::Left);
+
+        assert_eq!(events, vec![SimEvent::BallOut(Side::Left), SimEvent::Scored(Side::Right)]);
+        assert_eq!((sim.score(Side::Left), sim.score(Side::Right)), (0, 1));
+        assert_eq!(sim.ball().velocity, Vec2::ZERO);
+        assert_eq!(sim.game_over, None);
+    }
+
+    #[test]
+    fn game_over_at_score_to_win() {
+        let mut sim = PongSim::new(settings(), SimMode::Versus, 3);
+        sim.right_score = sim.settings.score_to_win - 1;
+
+        let events = miss(&mut sim, Side::Left);
+
+        assert!(events.contains(&SimEvent::GameOver(Side::Right)));
+        assert_eq!(sim.game_over, Some(Side::Right));
+        assert_eq!(sim.service, None);
+    }
+
+    /// Side and velocity of the first serve of a versus match.
+    fn first_serve(seed: u64) -> (Side, Vec2) {
+        let mut sim = PongSim::new(settings(), SimMode::Versus, seed);
+        let inputs = SimInputs {
+            left: PaddleInput { axis: 0., serve: true },
+            right: PaddleInput { axis: 0., serve: true },
+            ..Default::default()
+        };
+
+        let events = sim.step(&inputs, DT);
+        let Some(&SimEvent::Served(side)) = events.first() else {
+            panic!("no serve in {:?}", events);
+        };
+        (side, sim.ball().velocity)
+    }
+
+    #[test]
+    fn same_seed_same_serve() {
+        for seed in [0, 7, 1234, u64::MAX] {
+            assert_eq!(first_serve(seed), first_serve(seed));
+        }
+
+        let serves: Vec<(Side, Vec2)> = (0..16).map(first_serve).collect();
+        assert!(serves.iter().any(|serve| *serve != serves[0]), "every seed served the same");
+    }
+
+    #[test]
+    fn winner_of_the_point_serves_next() {
+        let mut sim = PongSim::new(settings(), SimMode::Versus, 3);
+        miss(&mut sim, Side::Left);
+        assert_eq!(sim.service, Some(Side::Right));
+
+        // The ball waits on the serving paddle
+        sim.step(&SimInputs::default(), DT);
+        let paddle = sim.paddle(Side::Right).unwrap();
+        assert_eq!(sim.ball().body.position.y, paddle.body.position.y);
+        assert!(sim.ball().body.position.x < paddle.body.position.x);
+    }
+
+    #[test]
+    fn loser_of_the_point_serves_next() {
+        let mut settings = settings();
+        settings.rules.serve = ServeRule::Loser;
+
+        let mut sim = PongSim::new(settings, SimMode::Versus, 3);
+        miss(&mut sim, Side::Left);
+        assert_eq!(sim.service, Some(Side::Left));
+    }
+
+    fn rules_settings(win_by_two: bool, best_of: u32) -> SimSettings {
+        SimSettings { score_to_win: 11, rules: MatchRules { win_by_two, best_of, ..Default::default() }, ..settings() }
+    }
+
+    #[test]
+    fn win_by_two_needs_a_two_point_lead() {
+        let mut sim = PongSim::new(rules_settings(true, 1), SimMode::Versus, 3);
+        sim.left_score = 10;
+        sim.right_score = 10;
+
+        for missed in [Side::Left, Side::Right, Side::Left] {
+            let events = miss(&mut sim, missed);
+            assert!(!events.contains(&SimEvent::SetWon(Side::Right)), "set won at {:?}", (sim.left_score, sim.right_score));
+        }
+        assert_eq!((sim.left_score, sim.right_score), (11, 12));
+
+        let events = miss(&mut sim, Side::Left);
+        assert!(events.contains(&SimEvent::SetWon(Side::Right)));
+        assert_eq!(sim.game_over, Some(Side::Right));
+
+        // A single point is enough without the rule
+        let mut sim = PongSim::new(rules_settings(false, 1), SimMode::Versus, 3);
+        sim.left_score = 10;
+        sim.right_score = 10;
+        miss(&mut sim, Side::Left);
+        assert_eq!(sim.game_over, Some(Side::Right));
+    }
+
+    #[test]
+    fn next_set_starts_from_zero() {
+        let mut sim = PongSim::new(rules_settings(false, 3), SimMode::Versus, 3);
+        sim.left_score = 4;
+        sim.right_score = 10;
+
+        let events = miss(&mut sim, Side::Left);
+
+        assert_eq!(events, vec![SimEvent::BallOut(Side::Left), SimEvent::Scored(Side::Right), SimEvent::SetWon(Side::Right)]);
+        assert_eq!((sim.left_score, sim.right_score), (0, 0));
+        assert_eq!((sim.left_sets, sim.right_sets), (0, 1));
+        assert_eq!(sim.game_over, None);
+        assert_eq!(sim.service, Some(Side::Right));
+    }
+
+    #[test]
+    fn match_ends_with_most_of_the_sets() {
+        assert_eq!(MatchRules { best_of: 1, ..Default::default() }.sets_to_win(), 1);
+        assert_eq!(MatchRules { best_of: 3, ..Default::default() }.sets_to_win(), 2);
+        assert_eq!(MatchRules { best_of: 5, ..Default::default() }.sets_to_win(), 3);
+
+        let mut sim = PongSim::new(rules_settings(false, 5), SimMode::Versus, 3);
+        sim.left_sets = 2;
+        sim.right_sets = 1;
+        sim.right_score = 10;
+        miss(&mut sim, Side::Left);
+        assert_eq!(sim.game_over, None);
+        assert_eq!((sim.left_sets, sim.right_sets), (2, 2));
+
+        sim.right_score = 10;
+        let events = miss(&mut sim, Side::Left);
+        assert!(events.contains(&SimEvent::GameOver(Side::Right)));
+        assert_eq!((sim.left_sets, sim.right_sets), (2, 3));
+        assert_eq!(sim.right_score, 11);
+    }
+
+    /// Service after each missed ball, in order.
+    fn services(sim: &mut PongSim, missed: &[Side]) -> Vec<Side> {
+        missed.iter().map(|&side| {
+            miss(sim, side);
+            sim.service.unwrap()
+        }).collect()
+    }
+
+    #[test]
+    fn alternate_serve_every_two_points_then_every_point_at_deuce() {
+        let mut settings = rules_settings(true, 1);
+        settings.rules.serve = ServeRule::Alternate { points: 2 };
+        settings.first_service = Some(Side::Left);
+
+        let mut sim = PongSim::new(settings.clone(), SimMode::Versus, 3);
+        let served = services(&mut sim, &[Side::Left, Side::Right, Side::Left, Side::Left]);
+        assert_eq!(served, vec![Side::Left, Side::Right, Side::Right, Side::Left]);
+
+        let mut sim = PongSim::new(settings, SimMode::Versus, 3);
+        sim.left_score = 10;
+        sim.right_score = 9;
+        let served = services(&mut sim, &[Side::Left, Side::Left, Side::Right, Side::Right]);
+        assert_eq!((sim.left_score, sim.right_score), (12, 11));
+        assert_eq!(served, vec![Side::Left, Side::Right, Side::Left, Side::Right]);
+    }
+
+    /// Ticks until the serving side serves without any input, if within `max_ticks`.
+    fn ticks_to_auto_serve(sim: &mut PongSim, max_ticks: u32) -> Option<u32> {
+        (1..=max_ticks).find(|_| sim.step(&SimInputs::default(), DT).contains(&SimEvent::Served(Side::Left)))
+    }
+
+    #[test]
+    fn serve_timer_serves_when_it_expires() {
+        let mut settings = settings();
+        settings.rules.serve_time_limit = Some(3);
+        settings.first_service = Some(Side::Left);
+
+        // The first point waits for the players
+        let mut sim = PongSim::new(settings.clone(), SimMode::Versus, 3);
+        assert_eq!(ticks_to_auto_serve(&mut sim, 1200), None);
+
+        let mut sim = PongSim::new(settings, SimMode::Versus, 3);
+        sim.right_score = 1;
+        let ticks = ticks_to_auto_serve(&mut sim, 1200).unwrap();
+        assert!((359..=361).contains(&ticks), "served after {} ticks", ticks);
+        assert_eq!(sim.service, None);
+        assert_eq!(sim.service_wait, 0.);
+    }
+
+    fn four_players() -> PongSim {
+        PongSim::new(settings(), SimMode::FourPlayers, 3)
+    }
+
+    #[test]
+    fn ball_out_costs_a_life() {
+        let mut sim = four_players();
+
+        let events = miss(&mut sim, Side::Top);
+
+        assert_eq!(events, vec![SimEvent::BallOut(Side::Top), SimEvent::LifeLost(Side::Top)]);
+        assert_eq!(sim.lives, [3, 3, 2, 3]);
+        assert_eq!(sim.service, Some(Side::Top));
+        assert_eq!(sim.game_over, None);
+    }
+
+    #[test]
+    fn side_out_of_lives_is_walled_up() {
+        let mut sim = four_players();
+        sim.lives[Side::Top as usize] = 1;
+
+        let events = miss(&mut sim, Side::Top);
+
+        assert!(events.contains(&SimEvent::Eliminated(Side::Top)));
+        assert!(sim.paddle(Side::Top).is_none());
+        assert!(sim.walls.contains(&sim.side_wall(Side::Top)));
+        assert_eq!(sim.alive_sides().collect::<Vec<_>>(), vec![Side::Left, Side::Right, Side::Bottom]);
+        assert_eq!(sim.game_over, None);
+    }
+
+    #[test]
+    fn serve_skips_eliminated_sides() {
+        let mut sim = four_players();
+        sim.lives = [3, 1, 1, 3];
+        miss(&mut sim, Side::Right);
+
+        // Top is out too, Right after it clockwise is already out
+        let events = miss(&mut sim, Side::Top);
+
+        assert!(events.contains(&SimEvent::Eliminated(Side::Top)));
+        assert_eq!(sim.service, Some(Side::Bottom));
+    }
+
+    #[test]
+    fn last_side_standing_wins() {
+        let mut sim = four_players();
+        sim.lives = [1, 1, 1, 2];
+        miss(&mut sim, Side::Right);
+        miss(&mut sim, Side::Top);
+
+        let events = miss(&mut sim, Side::Left);
+
+        assert_eq!(events, vec![
+            SimEvent::BallOut(Side::Left),
+            SimEvent::LifeLost(Side::Left),
+            SimEvent::Eliminated(Side::Left),
+            SimEvent::GameOver(Side::Bottom),
+        ]);
+        assert_eq!(sim.game_over, Some(Side::Bottom));
+    }
+
+    fn paddle_index(sim: &PongSim, side: Side, line: PaddleLine) -> usize {
+        sim.paddles.iter().position(|paddle| paddle.side == side && paddle.line == line).unwrap()
+    }
+
+    #[test]
+    fn ball_goes_through_its_own_team_from_behind() {
+        let mut sim = rally(SimMode::Doubles, Vec2::new(-220., 0.), Vec2::X);
+        let forward_x = sim.paddles[paddle_index(&sim, Side::Left, PaddleLine::Forward)].body.position.x;
+        assert_eq!(forward_x, -168.);
+
+        let events = sim.step(&SimInputs::default(), 0.1);
+
+        assert_eq!(events, vec![]);
+        assert!(sim.ball().body.position.x > forward_x + 8.);
+        assert!(sim.ball().velocity.x > 0.);
+    }
+
+    #[test]
+    fn both_paddles_of_a_side_return_the_ball() {
+        let mut sim = rally(SimMode::Doubles, Vec2::new(-120., 0.), -Vec2::X);
+        let events = sim.step(&SimInputs::default(), 0.1);
+        assert_eq!(events, vec![SimEvent::PaddleHit(Side::Left)]);
+        assert!(sim.ball().velocity.x > 0.);
+        assert!(sim.ball().body.position.x > -160.);
+
+        // Forward paddle out of the way, the back one gets it
+        let mut sim = rally(SimMode::Doubles, Vec2::new(-120., 0.), -Vec2::X);
+        let forward = paddle_index(&sim, Side::Left, PaddleLine::Forward);
+        sim.paddles[forward].body.snap(Vec2::new(-168., 200.));
+        let events = sim.step(&SimInputs::default(), 0.4);
+        assert_eq!(events, vec![SimEvent::PaddleHit(Side::Left)]);
+        assert!(sim.ball().velocity.x > 0.);
+        assert!(sim.ball().body.position.x > -352.);
+    }
+
+    fn multi_ball(hits: u32, mode: SimMode) -> PongSim {
+        let mut settings = settings();
+        settings.rules.multi_ball = Some(hits);
+        PongSim::new(settings, mode, 3)
+    }
+
+    #[test]
+    fn extra_ball_every_few_hits_up_to_the_maximum() {
+        let mut sim = multi_ball(2, SimMode::Versus);
+        let mut events = Vec::new();
+
+        for rally_hits in 1..=10 {
+            sim.rally_hits = rally_hits;
+            sim.add_ball_if_due(&mut events);
+            let expected = (1 + rally_hits as usize / 2).min(MAX_BALLS);
+            assert_eq!(sim.balls.len(), expected, "after {} hits", rally_hits);
+        }
+        assert_eq!(events, vec![SimEvent::BallAdded; MAX_BALLS - 1]);
+
+        // Every ball starts from the middle, towards one side
+        for ball in sim.balls[1..].iter() {
+            assert_eq!(ball.body.position, Vec2::ZERO);
+            assert_ne!(ball.velocity.x, 0.);
+        }
+    }
+
+    #[test]
+    fn paddle_hit_adds_the_ball() {
+        let mut sim = multi_ball(1, SimMode::Versus);
+        sim.service = None;
+        sim.balls[0].body.snap(Vec2::new(330., 0.));
+        sim.balls[0].velocity = Vec2::X * sim.balls[0].speed;
+
+        let events = sim.step(&SimInputs::default(), 0.1);
+
+        assert_eq!(events, vec![SimEvent::PaddleHit(Side::Right), SimEvent::BallAdded]);
+        assert_eq!(sim.balls.len(), 2);
+
+        // Only versus and doubles
+        let mut sim = multi_ball(1, SimMode::FourPlayers);
+        sim.rally_hits = 1;
+        let mut events = Vec::new();
+        sim.add_ball_if_due(&mut events);
+        assert_eq!(sim.balls.len(), 1);
+    }
+
+    #[test]
+    fn point_ends_with_the_last_ball() {
+        let mut sim = multi_ball(2, SimMode::Versus);
+        sim.balls.push(SimBall { body: Body::new(Vec2::new(100., 0.), Vec2::splat(16.)), speed: 500., velocity: Vec2::new(500., 0.), hold: None, last_hit: None });
+
+        // A ball out scores, the rally goes on with the other one
+        let events = miss(&mut sim, Side::Left);
+        assert_eq!(events, vec![SimEvent::BallOut(Side::Left), SimEvent::Scored(Side::Right)]);
+        assert_eq!(sim.balls.len(), 1);
+        assert_eq!(sim.service, None);
+        assert_ne!(sim.ball().velocity, Vec2::ZERO);
+
+        let events = miss(&mut sim, Side::Right);
+        assert_eq!(events, vec![SimEvent::BallOut(Side::Right), SimEvent::Scored(Side::Left)]);
+        assert_eq!((sim.left_score, sim.right_score), (1, 1));
+        assert_eq!(sim.service, Some(Side::Left));
+        assert_eq!(sim.ball().velocity, Vec2::ZERO);
+    }
+
+    #[test]
+    fn sticky_paddle_holds_one_ball_at_a_time() {
+        let mut sim = fast_rally(Vec2::new(330., 0.), Vec2::X);
+        sim.effects.push(SimEffect { kind: PowerUpKind::Sticky, side: Side::Right, time_left: 8. });
+        let second_ball = SimBall { body: Body::new(Vec2::new(300., 20.), Vec2::splat(16.)), ..sim.ball().clone() };
+        sim.balls.push(second_ball);
+
+        let events = sim.step(&SimInputs::default(), 0.1);
+
+        assert_eq!(events, vec![SimEvent::PaddleHit(Side::Right), SimEvent::PaddleHit(Side::Right)]);
+        assert!(sim.balls[0].hold.is_some());
+        assert_eq!(sim.balls[1].hold, None);
+        assert!(sim.balls[1].velocity.x < 0.);
+    }
+
+    #[test]
+    fn obstacles_go_back_and_forth_along_their_path() {
+        let mut settings = settings();
+        settings.arena.obstacles.push(ArenaObstacle { size: Vec2::new(16., 96.), path: vec![Vec2::new(0., 160.), Vec2::new(0., -160.)], speed: 120. });
+        let mut sim = PongSim::new(settings, SimMode::Versus, 3);
+
+        let mut lowest = f32::MAX;
+        let mut highest = f32::MIN;
+        for _ in 0..720 {
+            sim.step(&SimInputs::default(), DT);
+            let position = sim.obstacles[0].body.position;
+            assert_eq!(position.x, 0.);
+            assert!(position.y.abs() <= 160., "obstacle at {}", position);
+            lowest = lowest.min(position.y);
+            highest = highest.max(position.y);
+        }
+        assert_eq!((lowest, highest), (-160., 160.));
+
+        // A long tick goes past the end of the path and back
+        sim.obstacles[0].body.position = Vec2::new(0., 150.);
+        sim.obstacles[0].next = 0;
+        sim.step(&SimInputs::default(), 0.5);
+        assert_eq!(sim.obstacles[0].body.position, Vec2::new(0., 110.));
+        assert_eq!(sim.obstacles[0].next, 1);
+    }
+}
@@ -1,10 +1,99 @@
-use bevy::prelude::States;
+use bevy::prelude::{Resource, States};
+
+use crate::replay::MatchKind;
 
 #[derive(Debug, Default, Clone, Eq, PartialEq, Hash, States)]
 pub enum GameState {
+    /// Waiting for the config file.
     #[default]
+    Loading,
     Menu,
     Mode1P,
     Mode2P,
     ModeWall,
+    /// A paddle on every side of the court.
+    Mode4P,
+    /// Two paddles per side.
+    Mode2v2,
+    Watch,
+    Online,
+    Replays,
+    /// Key bindings screen.
+    Controls,
+    /// Wall mode high-score table.
+    HighScores,
+    /// Volume, AI level and display settings.
+    Options,
+    /// Match rules picked before a local match, the mode in `MatchSetupMode` follows.
+    MatchSetup,
+    /// Left for a single frame, so the mode in `RestartState` is set up again from scratch.
+    Restarting,
+}
+
+/// Pause of a local match, on top of its `GameState`.
+#[derive(Debug, Default, Clone, Eq, PartialEq, Hash, States)]
+pub enum PauseState {
+    #[default]
+    Running,
+    Paused,
+    /// Controls screen opened from the pause menu.
+    Settings,
 }
+
+impl GameState {
+    /// Keys of the modes the config file can override the game tuning of.
+    pub const CONFIG_KEYS: [&'static str; 8] = ["menu", "1p", "2p", "wall", "4p", "2v2", "watch", "online"];
+
+    pub fn config_key(&self) -> Option<&'static str> {
+        match self {
+            GameState::Menu => Some("menu"),
+            GameState::Mode1P => Some("1p"),
+            GameState::Mode2P => Some("2p"),
+            GameState::ModeWall => Some("wall"),
+            GameState::Mode4P => Some("4p"),
+            GameState::Mode2v2 => Some("2v2"),
+            GameState::Watch => Some("watch"),
+            GameState::Online => Some("online"),
+            _ => None,
+        }
+    }
+
+    pub fn match_kind(&self) -> Option<MatchKind> {
+        match self {
+            GameState::Mode1P => Some(MatchKind::OnePlayer),
+            GameState::Mode2P => Some(MatchKind::TwoPlayers),
+            GameState::ModeWall => Some(MatchKind::Wall),
+            GameState::Mode4P => Some(MatchKind::FourPlayers),
+            GameState::Mode2v2 => Some(MatchKind::Doubles),
+            _ => None,
+        }
+    }
+
+    pub fn from_match_kind(kind: MatchKind) -> Self {
+        match kind {
+            MatchKind::OnePlayer => GameState::Mode1P,
+            MatchKind::TwoPlayers => GameState::Mode2P,
+            MatchKind::Wall => GameState::ModeWall,
+            MatchKind::FourPlayers => GameState::Mode4P,
+            MatchKind::Doubles => GameState::Mode2v2,
+        }
+    }
+}
+
+/// Local mode the match rules screen leads to.
+#[derive(Resource)]
+pub struct MatchSetupMode(pub GameState);
+
+/// State entered once loading is over: the menu, unless the command line asks for another one.
+#[derive(Resource)]
+pub struct StartupState(pub GameState);
+
+impl Default for StartupState {
+    fn default() -> Self {
+        StartupState(GameState::Menu)
+    }
+}
+
+/// Mode entered again after `GameState::Restarting`.
+#[derive(Resource)]
+pub struct RestartState(pub GameState);
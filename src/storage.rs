@@ -0,0 +1,101 @@
+#[cfg(not(target_arch = "wasm32"))]
+use std::fs;
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::PathBuf;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Where a file goes: preferences in the config directory, what the game produces in the data one.
+/// The web has a single local storage for both.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Folder {
+    Config,
+    Data,
+}
+
+/// `$XDG_DATA_HOME/pong_bevy`, falling back to `~/.local/share`.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn data_dir() -> PathBuf {
+    let data_dir = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+        .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))
+        .unwrap_or_default();
+
+    data_dir.join("pong_bevy")
+}
+
+/// `$XDG_CONFIG_HOME/pong_bevy`, falling back to `~/.config`.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn config_dir() -> PathBuf {
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))
+        .unwrap_or_default();
+
+    config_dir.join("pong_bevy")
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Folder {
+    fn dir(self) -> PathBuf {
+        match self {
+            Folder::Config => config_dir(),
+            Folder::Data => data_dir(),
+        }
+    }
+}
+
+/// Reads `<name>.ron` from the folder, `None` if missing or unreadable.
+/// On the web, it is the `pong_bevy.<name>` key of the local storage.
+pub fn load<T: DeserializeOwned>(folder: Folder, name: &str) -> Option<T> {
+    let text = read(folder, name)?;
+
+    match ron::from_str(&text) {
+        Ok(value) => Some(value),
+        Err(error) => {
+            bevy::log::warn!("Ignoring saved {}: {}", name, error);
+            None
+        }
+    }
+}
+
+/// Writes `<name>.ron` to the folder, or the local storage on the web.
+pub fn save<T: Serialize>(folder: Folder, name: &str, value: &T) -> Result<(), String> {
+    let text = ron::ser::to_string_pretty(value, ron::ser::PrettyConfig::default()).map_err(|error| error.to_string())?;
+    write(folder, name, &text)
+}
+
+/// Text of `<name>.ron`, for files that need a look before deserializing (e.g. their version).
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read(folder: Folder, name: &str) -> Option<String> {
+    fs::read_to_string(folder.dir().join(format!("{}.ron", name))).ok()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn write(folder: Folder, name: &str, text: &str) -> Result<(), String> {
+    let dir = folder.dir();
+    fs::create_dir_all(&dir).map_err(|error| error.to_string())?;
+
+    fs::write(dir.join(format!("{}.ron", name)), text).map_err(|error| error.to_string())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn local_storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok()?
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn read(_folder: Folder, name: &str) -> Option<String> {
+    local_storage()?.get_item(&format!("pong_bevy.{}", name)).ok()?
+}
+
+#[cfg(target_arch = "wasm32")]
+fn write(_folder: Folder, name: &str, text: &str) -> Result<(), String> {
+    let storage = local_storage().ok_or("local storage unavailable")?;
+
+    storage
+        .set_item(&format!("pong_bevy.{}", name), text)
+        .map_err(|_| "local storage full or disabled".to_string())
+}
@@ -1,19 +1,63 @@
 use bevy::prelude::*;
 use bevy::window::PrimaryWindow;
-use rand::*;
+use rand::random;
 
 use crate::config::*;
 use crate::components::*;
 use crate::events::*;
 use crate::helpers_sprite::*;
+use crate::settings::*;
+use crate::sim::*;
+use crate::state::*;
 
-pub fn reset_game_data_system(
-    mut game_data: ResMut<GameData>,
+pub fn setup_sim_system(
+    mut commands: Commands,
+    window: Query<&Window, With<PrimaryWindow>>,
+    playback: Option<Res<ReplayPlayback>>,
+    rematch_service: Option<Res<RematchService>>,
+    state: Res<State<GameState>>,
+    user_settings: Res<Settings>,
+    config: Res<Config>,
+    arena_assets: Res<Assets<ArenaAsset>>,
 ) {
-    game_data.left_score = 0;
-    game_data.right_score = 0;
-    game_data.starting_side = if random::<u32>() % 2 == 0 { Side::Left } else { Side::Right };
-    game_data.game_over = None;
+    let sim = match playback {
+        Some(playback) => playback.replay.new_sim(),
+        None => {
+            let window = window.get_single().unwrap();
+            let mut settings = config.sim_settings(state.get(), Vec2::new(window.width(), window.height()));
+            if let Some(score_to_win) = user_settings.score_to_win {
+                settings.score_to_win = score_to_win;
+            }
+            settings.rules = user_settings.match_rules;
+            if let Some(rematch_service) = rematch_service {
+                settings.first_service = Some(rematch_service.0);
+                commands.remove_resource::<RematchService>();
+            }
+
+            // Arenas are picked for 1 and 2 players only
+            let arena_name = user_settings.arena.as_ref().filter(|_| matches!(state.get(), GameState::Mode1P | GameState::Mode2P));
+            if let Some(arena_name) = arena_name {
+                match config.loaded_arenas(&arena_assets).find(|arena| &arena.name == arena_name) {
+                    Some(arena) => {
+                        let problems = arena.validate_court(settings.court_size);
+                        if problems.is_empty() {
+                            settings.arena = arena.clone();
+                        } else {
+                            warn!("Arena {} does not fit the court, playing on the classic court: {}", arena_name, problems.join(", "));
+                        }
+                    }
+                    None => warn!("Arena {} is not loaded, playing on the classic court", arena_name),
+                }
+            }
+
+            let mode = if *state.get() == GameState::Mode2v2 { SimMode::Doubles } else { SimMode::Versus };
+            PongSim::new(settings, mode, config.game_seed.unwrap_or_else(random))
+        }
+    };
+
+    commands.insert_resource(GameRng::new(sim.seed));
+    commands.insert_resource(Sim(sim));
+    commands.insert_resource(PendingInputs::default());
 }
 
 pub fn setup_court_system(
@@ -25,18 +69,6 @@ pub fn setup_court_system(
     let color = config.color_white;
     let unit_size = config.sprite_unit_size;
 
-    commands
-        .spawn(create_top_wall_sprite(window.width(), window.height(), unit_size, color))
-        .insert(GameModeEntity {})
-        .insert(SoundEmitter { source: config.audio_wall.clone() })
-        .insert(Collider::Wall);
-
-    commands
-        .spawn(create_bottom_wall_sprite(window.width(), window.height(), unit_size, color))
-        .insert(GameModeEntity {})
-        .insert(SoundEmitter { source: config.audio_wall.clone() })
-        .insert(Collider::Wall);
-
     // Net
     {
         commands
@@ -60,6 +92,7 @@ pub fn setup_court_system(
 
 pub fn setup_scores_system(
     mut commands: Commands,
+    sim: Res<Sim>,
     config: Res<Config>,
 ) {
     // Left score
@@ -94,7 +127,7 @@ pub fn setup_scores_system(
                 ..Default::default()
             },
             text: Text::from_section(
-                format!("/{}", config.game_1v1_score_to_win),
+                format!("/{}", sim.0.settings.score_to_win),
                 TextStyle {
                     font: config.font.clone(),
                     font_size: 21.,
@@ -137,7 +170,7 @@ pub fn setup_scores_system(
                 ..Default::default()
             },
             text: Text::from_section(
-                format!("/{}", config.game_1v1_score_to_win),
+                format!("/{}", sim.0.settings.score_to_win),
                 TextStyle {
                     font: config.font.clone(),
                     font_size: 21.,
@@ -147,211 +180,183 @@ pub fn setup_scores_system(
             ..Default::default()
         })
         .insert(GameModeEntity {});
-}
-
-pub fn setup_left_paddle_system(
-    mut commands: Commands,
-    window: Query<&Window, With<PrimaryWindow>>,
-    config: Res<Config>,
-    game_data: Res<GameData>,
-) {
-    let window = window.get_single().unwrap();
 
-    let entity = commands
-        .spawn(create_left_paddle_sprite(window.width(), config.sprite_unit_size, config.color_white))
-        .insert(GameModeEntity {})
-        .insert(LeftPaddle { speed: config.game_paddle_speed })
-        .insert(SoundEmitter { source: config.audio_paddle_left.clone() })
-        .insert(Collider::Paddle)
-        .id();
-
-    if game_data.starting_side == Side::Left {
-        commands.entity(entity).insert(Service {});
+    if sim.0.settings.rules.best_of > 1 {
+        for (side, left) in [(Side::Left, 310.), (Side::Right, 426.)] {
+            commands
+                .spawn(TextBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        left: Val::Px(left),
+                        top: Val::Px(112.),
+                        ..Default::default()
+                    },
+                    text: Text::from_section(
+                        format!("sets {}", sim.0.sets(side)),
+                        TextStyle {
+                            font: config.font.clone(),
+                            font_size: 21.,
+                            color: config.color_grey,
+                        },
+                    ),
+                    ..Default::default()
+                })
+                .insert(GameModeEntity {})
+                .insert(SetCounter { side });
+        }
     }
 }
 
-pub fn setup_right_paddle_system(
+/// Power-ups running for each side, under its score, when the match has any.
+pub fn setup_power_ups_system(
     mut commands: Commands,
     window: Query<&Window, With<PrimaryWindow>>,
+    sim: Res<Sim>,
     config: Res<Config>,
-    game_data: Res<GameData>,
 ) {
-    let window = window.get_single().unwrap();
+    if sim.0.settings.rules.power_ups.is_empty() {
+        return;
+    }
 
-    let entity = commands
-        .spawn(create_right_paddle_sprite(window.width(), config.sprite_unit_size, config.color_white))
-        .insert(GameModeEntity {})
-        .insert(RightPaddle { velocity: Vec3::default(), speed: config.game_paddle_speed })
-        .insert(SoundEmitter { source: config.audio_paddle_right.clone() })
-        .insert(Collider::Paddle)
-        .id();
+    let window = window.get_single().unwrap();
+    for side in [Side::Left, Side::Right] {
+        let (style, alignment) = match side {
+            Side::Left => (Style { right: Val::Px(window.width() / 2. + 24.), ..Default::default() }, TextAlignment::Right),
+            _ => (Style { left: Val::Px(window.width() / 2. + 24.), ..Default::default() }, TextAlignment::Left),
+        };
 
-    if game_data.starting_side == Side::Right {
-        commands.entity(entity).insert(Service {});
+        commands
+            .spawn(TextBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(136.),
+                    ..style
+                },
+                text: Text::from_section(
+                    "",
+                    TextStyle {
+                        font: config.font.clone(),
+                        font_size: 18.,
+                        color: config.color_green,
+                    },
+                )
+                .with_alignment(alignment),
+                ..Default::default()
+            })
+            .insert(GameModeEntity {})
+            .insert(PowerUpText { side });
     }
 }
 
-pub fn setup_ball_system(
-    mut commands: Commands,
-    config: Res<Config>,
-) {
-    commands
-        .spawn(create_ball_sprite(config.sprite_unit_size, Vec3::default(), config.color_yellow))
-        .insert(GameModeEntity {})
-        .insert(Ball { speed: config.game_ball_speed_min, velocity: Vec3::default() });
-}
-
-pub fn service_system(
-    mut ball_query: Query<&mut Transform, With<Ball>>,
-    paddle_query: Query<(&Transform, Option<&LeftPaddle>, Option<&RightPaddle>), (With<Service>, Without<Ball>)>,
+/// Follows the sim, a line per power-up with its seconds left.
+pub fn update_power_ups_system(
+    mut power_up_text_query: Query<(&PowerUpText, &mut Text)>,
+    sim: Res<Sim>,
 ) {
-    let paddle = paddle_query.get_single();
-    if !paddle.is_ok() {
-        // No serving paddle
-        return;
-    }
-
-    let (paddle_transform, left_paddle, right_paddle) = paddle.unwrap();
-    let mut ball_transform = ball_query.single_mut();
-
-    ball_transform.translation.y = paddle_transform.translation.y;
-
-    if let Some(_) = left_paddle {
-        ball_transform.translation.x = paddle_transform.translation.x + paddle_transform.scale.x + 2.;
-    } else if let Some(_) = right_paddle {
-        ball_transform.translation.x = paddle_transform.translation.x - paddle_transform.scale.x - 2.;
+    for (power_up_text, mut text) in power_up_text_query.iter_mut() {
+        let value = sim.0.effects
+            .iter()
+            .filter(|effect| effect.side == power_up_text.side)
+            .map(|effect| format!("{} {}s", effect.kind.label(), effect.time_left.ceil()))
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        if text.sections[0].value != value {
+            text.sections[0].value = value;
+        }
     }
 }
 
-pub fn launch_ball_system(
+/// Rules of the match, above the instructions of the mode and hidden with them.
+pub fn setup_rules_instruction_system(
     mut commands: Commands,
-    mut ball_query: Query<&mut Ball, With<Ball>>,
-    paddle_query: Query<(Entity, Option<&LeftPaddle>, Option<&RightPaddle>), With<Service>>,
-    instructions_query: Query<Entity, With<Instruction>>,
-    keyboard: Res<Input<KeyCode>>,
-    game_data: Res<GameData>,
+    window: Query<&Window, With<PrimaryWindow>>,
+    sim: Res<Sim>,
+    config: Res<Config>,
 ) {
-    if game_data.game_over.is_some() {
-        return;
-    }
-
-    if !keyboard.just_released(KeyCode::Space) {
-        return;
-    }
-
-    let paddle = paddle_query.get_single();
-    if paddle.is_err() {
-        // Already launched?
-        return;
-    }
-
-    let (paddle_entity, left_paddle, right_paddle) = paddle.unwrap();
-    let mut ball = ball_query.single_mut();
-
-    if random::<i32>() % 2 == 0 {
-        ball.velocity.y = -0.25;
-    } else {
-        ball.velocity.y = 0.25;
-    }
-
-    if let Some(_) = left_paddle {
-        ball.velocity.x = 1.;
-    } else if let Some(_) = right_paddle {
-        ball.velocity.x = -1.;
-    }
-
-    ball.velocity = ball.velocity.normalize();
-    ball.velocity.x *= ball.speed;
-    ball.velocity.y *= ball.speed;
-
-    commands.entity(paddle_entity).remove::<Service>();
+    let window = window.get_single().unwrap();
+    let settings = &sim.0.settings;
 
-    // Also hide instructions
-    for instruction_entity in instructions_query.iter() {
-        commands.entity(instruction_entity).despawn_recursive();
-    }
+    commands
+        .spawn(ButtonBundle {
+            style: Style {
+                width: Val::Px(window.width()),
+                height: Val::Px(32.),
+                bottom: Val::Px(240.),
+                left: Val::Px(0.),
+                position_type: PositionType::Absolute,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            background_color: config.color_transparent.into(),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent.spawn(TextBundle {
+                text: Text::from_section(
+                    settings.rules.describe(settings.score_to_win),
+                    TextStyle {
+                        font: config.font.clone(),
+                        font_size: 18.,
+                        color: config.color_grey,
+                    },
+                ),
+                ..Default::default()
+            });
+        })
+        .insert(GameModeEntity {})
+        .insert(Instruction {});
 }
 
-pub fn increment_score_system(
-    mut ball_out_event: EventReader<BallOutEvent>,
+pub fn update_scores_system(
+    mut match_events: EventReader<MatchEvent>,
     mut left_score_query: Query<&mut Text, (With<LeftScore>, Without<RightScore>)>,
     mut right_score_query: Query<&mut Text, (With<RightScore>, Without<LeftScore>)>,
-    mut game_data: ResMut<GameData>,
+    sim: Res<Sim>,
 ) {
-    if game_data.game_over.is_some() {
-        return;
-    }
-
-    for event in ball_out_event.read() {
-        match event.0 {
-            Side::Left => {
-                game_data.right_score += 1;
-                right_score_query.single_mut().sections[0].value = format!("{}", game_data.right_score);
-            }
-            Side::Right => {
-                game_data.left_score += 1;
-                left_score_query.single_mut().sections[0].value = format!("{}", game_data.left_score);
-            }
+    for event in match_events.read() {
+        // A new set starts both scores over
+        let sides = match event.0 {
+            SimEvent::Scored(side) => vec![side],
+            SimEvent::SetWon(_) => vec![Side::Left, Side::Right],
+            _ => continue,
+        };
+
+        for side in sides {
+            let mut text = match side {
+                Side::Left => left_score_query.single_mut(),
+                Side::Right => right_score_query.single_mut(),
+                Side::Top | Side::Bottom => continue,
+            };
+            text.sections[0].value = format!("{}", sim.0.score(side));
         }
     }
 }
 
-pub fn check_game_over_system(
-    mut commands: Commands,
-    mut ball_out_event: EventReader<BallOutEvent>,
-    mut game_over_event: EventWriter<GameOverEvent>,
-    mut ball_query: Query<&mut Ball>,
-    mut left_paddle_query: Query<(Entity, &mut Transform), (With<LeftPaddle>, Without<RightPaddle>, Without<Ball>)>,
-    mut right_paddle_query: Query<(Entity, &mut Transform), (With<RightPaddle>, Without<LeftPaddle>, Without<Ball>)>,
-    mut game_data: ResMut<GameData>,
-    config: Res<Config>,
+/// Follows the sim rather than its events, so seeking a replay or starting the next watched match is covered too.
+pub fn update_set_counters_system(
+    mut set_counter_query: Query<(&SetCounter, &mut Text)>,
+    sim: Res<Sim>,
 ) {
-    if game_data.game_over.is_some() {
-        return;
-    }
-
-    for event in ball_out_event.read() {
-        let mut ball = ball_query.single_mut();
-        let (right_paddle_entity, mut right_paddle_transform) = right_paddle_query.single_mut();
-        let (left_paddle_entity, mut left_paddle_transform) = left_paddle_query.single_mut();
-
-        let mut game_over = false;
-
-        match event.0 {
-            Side::Left => {
-                if game_data.right_score == config.game_1v1_score_to_win {
-                    game_data.game_over = Some(Side::Right);
-                    game_over_event.send(GameOverEvent(Side::Right));
-                    game_over = true;
-                } else {
-                    commands.entity(right_paddle_entity).insert(Service {});
-                }
-            }
-            Side::Right => {
-                if game_data.left_score == config.game_1v1_score_to_win {
-                    game_data.game_over = Some(Side::Left);
-                    game_over_event.send(GameOverEvent(Side::Left));
-                    game_over = true;
-                } else {
-                    commands.entity(left_paddle_entity).insert(Service {});
-                }
-            }
-        }
-
-        if !game_over {
-            left_paddle_transform.translation.y = 0.;
-            right_paddle_transform.translation.y = 0.;
-            ball.velocity = Vec3::default();
+    for (set_counter, mut text) in set_counter_query.iter_mut() {
+        let value = format!("sets {}", sim.0.sets(set_counter.side));
+        if text.sections[0].value != value {
+            text.sections[0].value = value;
         }
     }
 }
 
 pub fn game_over_system(
     mut commands: Commands,
-    mut game_over_event: EventReader<GameOverEvent>,
+    mut match_events: EventReader<MatchEvent>,
     config: Res<Config>,
 ) {
-    for event in game_over_event.read() {
+    for event in match_events.read() {
+        let SimEvent::GameOver(winner) = event.0 else {
+            continue;
+        };
+
         const WIN_TEXT: &str = "WIN";
         const LOSE_TEXT: &str = "LOSE";
 
@@ -360,7 +365,7 @@ pub fn game_over_system(
         let left_color: Color;
         let right_color: Color;
 
-        match event.0 {
+        match winner {
             Side::Left => {
                 left_text = WIN_TEXT;
                 right_text = LOSE_TEXT;
@@ -373,6 +378,8 @@ pub fn game_over_system(
                 left_color = config.color_red;
                 right_color = config.color_green;
             }
+            // 1 vs 1 modes only
+            Side::Top | Side::Bottom => continue,
         }
 
         // Left
@@ -404,7 +411,8 @@ pub fn game_over_system(
                     ..Default::default()
                 });
             })
-            .insert(GameModeEntity {});
+            .insert(GameModeEntity {})
+            .insert(GameOverText {});
 
         // Right
         commands
@@ -435,6 +443,7 @@ pub fn game_over_system(
                     ..Default::default()
                 });
             })
-            .insert(GameModeEntity {});
+            .insert(GameModeEntity {})
+            .insert(GameOverText {});
     }
 }
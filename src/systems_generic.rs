@@ -2,189 +2,347 @@ use bevy::{
     prelude::*,
     input::keyboard::*,
     input::ButtonState,
-    sprite::collide_aabb::*,
 };
-use bevy::window::PrimaryWindow;
 use bevy_kira_audio::{Audio, AudioControl};
 
 use crate::config::*;
 use crate::components::*;
 use crate::events::*;
+use crate::gamepad::*;
+use crate::helpers_sprite::*;
+use crate::input_map::*;
+use crate::replay::*;
+use crate::settings::*;
+use crate::sim::*;
 use crate::state::*;
 
-pub fn move_left_paddle_with_keyboard_system(
-    mut paddle_query: Query<(&LeftPaddle, &mut Transform)>,
-    keyboard: Res<Input<KeyCode>>,
-    window: Query<&Window, With<PrimaryWindow>>,
+/// Ordering of the `FixedUpdate` tick shared by every mode.
+#[derive(Debug, Eq, PartialEq, Clone, Hash, SystemSet)]
+pub enum SimSet {
+    /// Fills `PendingInputs` from the paddle controllers.
+    Input,
+    Step,
+}
+
+pub fn step_sim_system(
+    mut sim: ResMut<Sim>,
+    mut pending_inputs: ResMut<PendingInputs>,
+    mut match_events: EventWriter<MatchEvent>,
+    recorder: Option<ResMut<MatchRecorder>>,
+    playback: Option<Res<ReplayPlayback>>,
     time: Res<Time>,
+) {
+    if let Some(playback) = playback {
+        if sim.0.tick >= playback.replay.ticks() {
+            return;
+        }
+    }
+
+    if let Some(mut recorder) = recorder {
+        recorder.0.push(&pending_inputs.0);
+    }
+
+    let events = sim.0.step(&pending_inputs.0, time.delta_seconds());
+
+    pending_inputs.0.left.serve = false;
+    pending_inputs.0.right.serve = false;
+    pending_inputs.0.top.serve = false;
+    pending_inputs.0.bottom.serve = false;
+    pending_inputs.0.left_forward.serve = false;
+    pending_inputs.0.right_forward.serve = false;
+
+    match_events.send_batch(events.into_iter().map(MatchEvent));
+}
+
+pub fn start_recording_system(
+    mut commands: Commands,
+    sim: Res<Sim>,
+    state: Res<State<GameState>>,
+    playback: Option<Res<ReplayPlayback>>,
     config: Res<Config>,
 ) {
-    let window = window.get_single().unwrap();
-    let mut direction = 0.;
+    if playback.is_some() {
+        return;
+    }
 
-    if keyboard.pressed(KeyCode::S) {
-        direction += 1.;
+    if let Some(kind) = state.get().match_kind() {
+        commands.insert_resource(MatchRecorder(Replay::new(kind, &sim.0, config.game_fixed_hz)));
     }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn save_replay_system(
+    mut commands: Commands,
+    recorder: Option<Res<MatchRecorder>>,
+    sim: Res<Sim>,
+) {
+    let Some(recorder) = recorder else {
+        return;
+    };
 
-    if keyboard.pressed(KeyCode::X) {
-        direction -= 1.;
+    // Finished matches are saved from the game-over panel, if asked to
+    if recorder.0.ticks() > 0 && sim.0.game_over.is_none() {
+        match files::save(&recorder.0) {
+            Ok(path) => info!("Replay saved to {}", path.display()),
+            Err(error) => warn!("Could not save replay: {}", error),
+        }
     }
 
-    let (paddle_entity, mut paddle_transform) = paddle_query.single_mut();
-    let bound_y = window.height() / 2. - config.sprite_unit_size - paddle_transform.scale.y / 2.;
+    commands.remove_resource::<MatchRecorder>();
+}
 
-    let translation = &mut paddle_transform.translation;
-    translation.y += direction * paddle_entity.speed * time.delta_seconds();
-    translation.y = translation.y.min(bound_y).max(-bound_y);
+pub fn setup_bodies_system(
+    mut commands: Commands,
+    sim: Res<Sim>,
+    config: Res<Config>,
+) {
+    spawn_body_sprites(&mut commands, &sim.0, config.color_white, config.color_yellow, GameModeEntity {});
 }
 
-pub fn move_ball_system(
-    mut ball_query: Query<(&Ball, &mut Transform)>,
-    time: Res<Time>,
+/// Spawns the sprites of the walls, paddles, ball and arena of `sim`, each tagged with `marker`.
+/// Bumpers are drawn in `ball_color`.
+pub fn spawn_body_sprites<T: Component + Clone>(
+    commands: &mut Commands,
+    sim: &PongSim,
+    color: Color,
+    ball_color: Color,
+    marker: T,
 ) {
-    let (ball, mut transform) = ball_query.single_mut();
-    let velocity = ball.velocity;
+    for wall in sim.walls.iter() {
+        commands
+            .spawn(create_rect_sprite(wall.center, wall.half_size * 2., color))
+            .insert(marker.clone());
+    }
+
+    for bumper in sim.settings.arena.bumpers.iter() {
+        commands
+            .spawn(create_rect_sprite(bumper.center, bumper.size, ball_color))
+            .insert(marker.clone());
+    }
+
+    for (index, obstacle) in sim.obstacles.iter().enumerate() {
+        commands
+            .spawn(create_rect_sprite(obstacle.body.position, obstacle.body.size, color))
+            .insert(marker.clone())
+            .insert(ObstacleView { index });
+    }
+
+    for paddle in sim.paddles.iter() {
+        commands
+            .spawn(create_rect_sprite(paddle.body.position, paddle.body.size, color))
+            .insert(marker.clone())
+            .insert(PaddleView { side: paddle.side, line: paddle.line });
+    }
 
-    if velocity.x != 0. || velocity.y != 0. {
-        transform.translation += velocity * time.delta_seconds();
+    for (index, ball) in sim.balls.iter().enumerate() {
+        commands
+            .spawn(create_rect_sprite(ball.body.position, ball.body.size, ball_color))
+            .insert(marker.clone())
+            .insert(BallView { index });
     }
 }
 
-pub fn check_ball_collision_system(
-    mut ball_query: Query<(&mut Ball, &mut Transform)>,
-    mut ball_hit_paddle_event: EventWriter<BallHitPaddleEvent>,
-    collider_query: Query<(&Collider, &Transform, &SoundEmitter), Without<Ball>>,
+/// Multi-ball: one sprite per ball in play, following the sim so seeking a replay is covered too.
+pub fn sync_ball_views_system(
+    mut commands: Commands,
+    ball_query: Query<(Entity, &BallView)>,
+    sim: Res<Sim>,
     config: Res<Config>,
-    audio: Res<Audio>,
 ) {
-    for (collider, collider_transform, collider_sound) in collider_query.iter() {
-        let (mut ball, mut ball_transform) = ball_query.single_mut();
-
-        let bx = ball_transform.translation.x;
-        let by = ball_transform.translation.y;
-        let bwh = ball_transform.scale.x / 2.;
-        let bhh = ball_transform.scale.y / 2.;
-
-        let px = collider_transform.translation.x;
-        let py = collider_transform.translation.y;
-        let pwh = collider_transform.scale.x / 2.;
-        let phh = collider_transform.scale.y / 2.;
-
-        if !(bx - bwh >= px + pwh || bx + bwh <= px - pwh || by - bhh >= py + phh || by + bhh <= py - phh) {
-            let velocity1 = ball.velocity;
-            let v_x1 = velocity1.x;
-            let v_y1 = velocity1.y;
-
-            // Required move to go back to the position just before the collision
-            let x_to_collision = if v_x1 > 0. { (px - pwh) - (bx + bwh) } else { (px + pwh) - (bx - bwh) };
-            let y_to_collision = if v_y1 > 0. { (py - phh) - (by + bhh) } else { (py + phh) - (by - bhh) };
-
-            // Same as above expressed in percentage (value from 0 to 1)
-            let x_offset_to_collision = if 0. == v_x1 { -f32::INFINITY } else { x_to_collision / v_x1 };
-            let y_offset_to_collision = if 0. == v_y1 { -f32::INFINITY } else { y_to_collision / v_y1 };
-
-            // Collision time is the latest among the two axes
-            let collision_time = x_offset_to_collision.max(y_offset_to_collision);
-
-            // Collision normals to find on which AABB side the collision occurred
-            let normal_x: f32;
-            let normal_y: f32;
-            let collision_side: Collision;
-            if x_offset_to_collision > y_offset_to_collision {
-                normal_x = if x_to_collision < 0. { -1. } else { 1. };
-                normal_y = 0.;
-
-                collision_side = if -1. == normal_x { Collision::Left } else { Collision::Right };
-            } else {
-                normal_y = if y_to_collision < 0. { -1. } else { 1. };
-                normal_x = 0.;
-
-                collision_side = if -1. == normal_y { Collision::Top } else { Collision::Bottom };
-            }
+    for (entity, ball_view) in ball_query.iter() {
+        if ball_view.index >= sim.0.balls.len() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
 
-            // Position where the collision occurred
-            let x_collision = bx + v_x1 * collision_time;
-            let y_collision = by + v_y1 * collision_time;
-
-            ball_transform.translation.x = x_collision;
-            ball_transform.translation.y = y_collision;
-
-            let collision_resolved: bool;
-            match *collider {
-                Collider::Paddle => {
-                    match collision_side {
-                        Collision::Top => {
-                            collision_resolved = false;
-                            ball_hit_paddle_event.send(BallHitPaddleEvent());
-                        }
-                        Collision::Bottom => {
-                            collision_resolved = false;
-                            ball_hit_paddle_event.send(BallHitPaddleEvent());
-                        }
-                        _ => {
-                            let hit_factor = (ball_transform.translation.y - collider_transform.translation.y) / collider_transform.scale.y;
-
-                            let mut new_ball_vel = Vec3::default();
-                            new_ball_vel.x = if ball.velocity.x > 0. { -1. } else { 1. };
-                            new_ball_vel.y = hit_factor * 2.;
-                            new_ball_vel = new_ball_vel.normalize();
-
-                            ball.velocity.x = new_ball_vel.x * ball.speed;
-                            ball.velocity.y = new_ball_vel.y * ball.speed;
-
-                            if config.game_ball_speed_max > ball.speed {
-                                ball.speed += config.game_ball_speed_incr;
-                            }
-
-                            collision_resolved = true;
-
-                            ball_hit_paddle_event.send(BallHitPaddleEvent());
-                        }
-                    }
-                }
-                _ => {
-                    collision_resolved = false
-                }
-            }
+    for (index, ball) in sim.0.balls.iter().enumerate() {
+        if !ball_query.iter().any(|(_, ball_view)| ball_view.index == index) {
+            commands
+                .spawn(create_rect_sprite(ball.body.position, ball.body.size, config.color_yellow))
+                .insert(GameModeEntity {})
+                .insert(BallView { index });
+        }
+    }
+}
 
-            // Default behavior if collision not resolved by user
-            if !collision_resolved
-            {
-                // Setting new velocity for "bounce" effect
-                if 0. != normal_x
-                {
-                    ball.velocity.x = -v_x1;
-                }
-
-                if 0. != normal_y
-                {
-                    ball.velocity.y = -v_y1;
-                }
-            }
+/// Power-ups: the item on the court, the shields and the invisible balls, following the sim like the balls.
+pub fn sync_power_up_views_system(
+    mut commands: Commands,
+    power_up_query: Query<(Entity, &PowerUpView)>,
+    shield_query: Query<(Entity, &ShieldView)>,
+    mut ball_query: Query<(&BallView, &mut Visibility)>,
+    sim: Res<Sim>,
+    config: Res<Config>,
+) {
+    let power_up = sim.0.power_up.as_ref();
+    for (entity, power_up_view) in power_up_query.iter() {
+        if power_up.map(|power_up| power_up.kind) != Some(power_up_view.kind) {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+
+    if let Some(power_up) = power_up {
+        if !power_up_query.iter().any(|(_, power_up_view)| power_up_view.kind == power_up.kind) {
+            commands
+                .spawn(create_rect_sprite(power_up.body.position, power_up.body.size, config.color_grey))
+                .insert(GameModeEntity {})
+                .insert(PowerUpView { kind: power_up.kind });
+
+            commands
+                .spawn(Text2dBundle {
+                    text: Text::from_section(
+                        power_up.kind.label().to_uppercase(),
+                        TextStyle {
+                            font: config.font.clone(),
+                            font_size: 15.,
+                            color: config.color_white,
+                        },
+                    ),
+                    transform: Transform::from_translation(power_up.body.position.extend(1.)),
+                    ..Default::default()
+                })
+                .insert(GameModeEntity {})
+                .insert(PowerUpView { kind: power_up.kind });
+        }
+    }
+
+    for (entity, shield_view) in shield_query.iter() {
+        if !sim.0.shields().any(|side| side == shield_view.side) {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+
+    for side in sim.0.shields() {
+        if !shield_query.iter().any(|(_, shield_view)| shield_view.side == side) {
+            let wall = sim.0.side_wall(side);
+            commands
+                .spawn(create_rect_sprite(wall.center, wall.half_size * 2., config.color_green))
+                .insert(GameModeEntity {})
+                .insert(ShieldView { side });
+        }
+    }
+
+    // Invisibility: balls heading for the opponent of the side that collected it only show 4 ticks out of 30
+    let flash = sim.0.tick % 30 < 4;
+    for (ball_view, mut visibility) in ball_query.iter_mut() {
+        let Some(ball) = sim.0.balls.get(ball_view.index) else {
+            continue;
+        };
+
+        let hidden = !flash && sim.0.effects.iter().any(|effect| {
+            effect.kind == PowerUpKind::Invisibility && ball.velocity.dot(effect.side.normal()) > 0.
+        });
+        let value = if hidden { Visibility::Hidden } else { Visibility::Inherited };
+        if *visibility != value {
+            *visibility = value;
+        }
+    }
+}
+
+pub fn sync_transforms_system(
+    mut paddle_query: Query<(&PaddleView, &mut Transform), Without<BallView>>,
+    mut ball_query: Query<(&BallView, &mut Transform)>,
+    sim: Res<Sim>,
+    fixed_time: Res<Time<Fixed>>,
+) {
+    let alpha = fixed_time.overstep_percentage();
+
+    for (paddle_view, mut transform) in paddle_query.iter_mut() {
+        if let Some(paddle) = sim.0.paddle_at(paddle_view.side, paddle_view.line) {
+            transform.translation = paddle.body.interpolate(alpha).extend(0.);
+            // Enlarge and shrink
+            transform.scale = paddle.body.size.extend(0.);
+        }
+    }
+
+    for (ball_view, mut transform) in ball_query.iter_mut() {
+        if let Some(ball) = sim.0.balls.get(ball_view.index) {
+            transform.translation = ball.body.interpolate(alpha).extend(0.);
+        }
+    }
+}
+
+/// Moving obstacles of the arena, like `sync_transforms_system`.
+pub fn sync_obstacles_system(
+    mut obstacle_query: Query<(&ObstacleView, &mut Transform)>,
+    sim: Res<Sim>,
+    fixed_time: Res<Time<Fixed>>,
+) {
+    let alpha = fixed_time.overstep_percentage();
 
-            audio.play(collider_sound.source.clone());
+    for (obstacle_view, mut transform) in obstacle_query.iter_mut() {
+        if let Some(obstacle) = sim.0.obstacles.get(obstacle_view.index) {
+            transform.translation = obstacle.body.interpolate(alpha).extend(0.);
         }
     }
 }
 
-pub fn check_ball_out_system(
-    mut ball_out_event: EventWriter<BallOutEvent>,
-    mut ball_query: Query<&Transform, With<Ball>>,
-    window: Query<&Window, With<PrimaryWindow>>,
+pub fn play_sounds_system(
+    mut match_events: EventReader<MatchEvent>,
+    settings: Res<Settings>,
     config: Res<Config>,
-    game_data: Res<GameData>,
+    audio: Res<Audio>,
 ) {
-    if game_data.game_over.is_some() {
-        return;
+    for event in match_events.read() {
+        let source = match event.0 {
+            // Per team: both paddles of a side share a sound,
+            // and in four players the top paddle sounds like the left one, the bottom one like the right one
+            SimEvent::PaddleHit(Side::Left | Side::Top) => &config.audio_paddle_left,
+            SimEvent::PaddleHit(Side::Right | Side::Bottom) => &config.audio_paddle_right,
+            SimEvent::WallHit => &config.audio_wall,
+            _ => continue,
+        };
+
+        audio.play(source.clone()).with_volume(settings.volume as f64);
     }
+}
 
-    let window = window.get_single().unwrap();
-    let ball_transform = ball_query.single_mut();
+pub fn hide_instructions_system(
+    mut commands: Commands,
+    mut match_events: EventReader<MatchEvent>,
+    instructions_query: Query<Entity, With<Instruction>>,
+) {
+    for event in match_events.read() {
+        if let SimEvent::Served(_) = event.0 {
+            for instruction_entity in instructions_query.iter() {
+                commands.entity(instruction_entity).despawn_recursive();
+            }
+        }
+    }
+}
 
-    if ball_transform.translation.x < -window.width() / 2. - config.game_ball_oob_x {
-        ball_out_event.send(BallOutEvent(Side::Left));
-    } else if ball_transform.translation.x > window.width() / 2. + config.game_ball_oob_x {
-        ball_out_event.send(BallOutEvent(Side::Right));
+pub fn show_seed_system(
+    mut commands: Commands,
+    mut match_events: EventReader<MatchEvent>,
+    sim: Res<Sim>,
+    config: Res<Config>,
+) {
+    for event in match_events.read() {
+        let SimEvent::GameOver(_) = event.0 else {
+            continue;
+        };
+
+        // Bug reports can quote it, to replay the match with `--seed`
+        commands
+            .spawn(TextBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    bottom: Val::Px(config.sprite_unit_size * 2.),
+                    left: Val::Px(config.sprite_unit_size),
+                    ..Default::default()
+                },
+                text: Text::from_section(
+                    format!("seed {}", sim.0.seed),
+                    TextStyle {
+                        font: config.font.clone(),
+                        font_size: 18.,
+                        color: config.color_grey,
+                    },
+                ),
+                ..Default::default()
+            })
+            .insert(GameModeEntity {})
+            .insert(GameOverText {});
     }
 }
 
@@ -200,12 +358,129 @@ pub fn cleanup_entities<T: Component>(
 pub fn back_to_menu_system(
     mut state: ResMut<NextState<GameState>>,
     mut keyboard_input_events: EventReader<KeyboardInput>,
+    gamepads: GamepadInputs,
+    bindings: Res<KeyBindings>,
 ) {
+    if gamepads.any_just_pressed(GamepadButtonType::Select) || gamepads.any_just_pressed(GamepadButtonType::Start) {
+        state.set(GameState::Menu);
+    }
+
     for event in keyboard_input_events.read() {
         if let Some(key_code) = event.key_code {
-            if event.state == ButtonState::Released && key_code == KeyCode::Escape {
+            if event.state == ButtonState::Released && bindings.keys(Action::Back).any(|key| key == key_code) {
                 state.set(GameState::Menu);
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::ecs::system::RunSystemOnce;
+    use bevy::time::TimeUpdateStrategy;
+
+    use super::*;
+    use crate::ai::*;
+    use crate::controller::*;
+
+    /// Fixed step of these tests, a whole number of milliseconds so frame times add up exactly.
+    const FIXED_STEP_MS: u64 = 8;
+    /// Keys and serves only change on multiples of this, which ends a frame at every frame rate tested.
+    const SCRIPT_STEP_MS: u64 = 96;
+    const SCRIPT_STEPS: u64 = 60;
+
+    /// Match against the AI with the simulation systems on `FixedUpdate`, in the order of `Mode1PPlugin`.
+    fn match_app() -> App {
+        let config = Config {
+            game_paddle_speed: 400.,
+            game_ball_speed_min: 500.,
+            game_ball_speed_max: 750.,
+            game_ball_speed_incr: 5.,
+            game_ball_oob_x: 200.,
+            game_1v1_score_to_win: 9,
+            sprite_unit_size: 16.,
+            ..Default::default()
+        };
+        let settings = config.sim_settings(&GameState::Mode1P, Vec2::new(768., 576.));
+
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(Time::<Fixed>::from_duration(Duration::from_millis(FIXED_STEP_MS)))
+            .insert_resource(Sim(PongSim::new(settings, SimMode::Versus, 42)))
+            .insert_resource(GameRng::new(42))
+            .init_resource::<PendingInputs>()
+            .init_resource::<Input<KeyCode>>()
+            .init_resource::<GamepadAssignments>()
+            .init_resource::<Gamepads>()
+            .init_resource::<Input<GamepadButton>>()
+            .init_resource::<Axis<GamepadAxis>>()
+            .init_resource::<Touches>()
+            .init_resource::<Input<MouseButton>>()
+            .init_resource::<KeyBindings>()
+            .add_event::<MatchEvent>()
+            .add_systems(FixedUpdate, (
+                drive_paddles_system.in_set(SimSet::Input),
+                step_sim_system.in_set(SimSet::Step).after(SimSet::Input),
+            ));
+
+        app.world.spawn(PaddleController { side: Side::Left, line: PaddleLine::Back, kind: ControllerKind::Human(HumanControls::for_player(&KeyBindings::default(), Player::One)) });
+        app.world.spawn(PaddleController { side: Side::Right, line: PaddleLine::Back, kind: ControllerKind::Ai(PaddleAi::new(Difficulty::Normal)) });
+
+        // The clock only starts on the next update
+        app.update();
+        app.world.resource_mut::<Time<Virtual>>().set_max_delta(Duration::from_secs(1));
+        app
+    }
+
+    /// Holds the keys of the script step and taps the serve key, the AI serves on its own.
+    fn apply_script(app: &mut App, step: u64) {
+        let mut keyboard = app.world.resource_mut::<Input<KeyCode>>();
+        keyboard.release_all();
+        match step % 5 {
+            0 | 4 => keyboard.press(KeyCode::S),
+            2 | 3 => keyboard.press(KeyCode::X),
+            _ => {}
+        }
+
+        // Latched right away, so the next tick sees it at any frame rate
+        keyboard.press(KeyCode::Space);
+        keyboard.release(KeyCode::Space);
+        app.world.run_system_once(latch_serve_system);
+        app.world.resource_mut::<Input<KeyCode>>().clear();
+    }
+
+    /// Plays the script with the given frame times (in milliseconds, cycled), returns the simulation at the end.
+    fn play(frame_times_ms: &[u64]) -> PongSim {
+        let mut app = match_app();
+        let mut frame_times = frame_times_ms.iter().cycle();
+        let mut elapsed_ms = 0;
+
+        for step in 0..SCRIPT_STEPS {
+            apply_script(&mut app, step);
+
+            while elapsed_ms < (step + 1) * SCRIPT_STEP_MS {
+                let frame_time = *frame_times.next().unwrap();
+                app.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(frame_time)));
+                app.update();
+                elapsed_ms += frame_time;
+            }
+            assert_eq!(elapsed_ms, (step + 1) * SCRIPT_STEP_MS, "frame times must end on script steps");
+        }
+
+        app.world.resource::<Sim>().0.clone()
+    }
+
+    #[test]
+    fn same_match_at_any_frame_rate() {
+        let reference = play(&[FIXED_STEP_MS]);
+        assert_eq!(reference.tick, SCRIPT_STEPS * SCRIPT_STEP_MS / FIXED_STEP_MS);
+        assert!(reference.left_score + reference.right_score > 0, "the script should score points");
+
+        // 250, 62.5, 31.25 and 10.4 frames per second, then frame times all over the place
+        for frame_times_ms in [&[4][..], &[16], &[32], &[96], &[4, 20, 12, 8, 28, 24]] {
+            assert_eq!(play(frame_times_ms), reference, "frame times {:?}", frame_times_ms);
+        }
+    }
+}